@@ -3,7 +3,11 @@ use wasm_bindgen::JsCast;
 use xterm_js_rs::addons::fit::FitAddon;
 use xterm_js_rs::{OnKeyEvent, Terminal, TerminalOptions, Theme};
 
-use rclisp::{generate_default_env, interpret};
+use rclisp::{generate_default_env, interpret, print_string, set_standard_output, set_error_output, set_standard_input, Sink};
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
@@ -22,6 +26,24 @@ fn prompt(term: &Terminal, cont: bool) {
     term.write(prompt);
 }
 
+// Output of the interpreter, collected while it runs and then written to the terminal
+#[derive(Clone, Default)]
+struct TerminalOutput(Rc<RefCell<String>>);
+
+impl Sink for TerminalOutput {
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.0.borrow_mut().push_str(text);
+        Ok(())
+    }
+}
+
+impl TerminalOutput {
+    fn flush_to(&self, term: &Terminal) {
+        let text = std::mem::take(&mut *self.0.borrow_mut());
+        term.write(&text.replace('\n', "\r\n"));
+    }
+}
+
 fn quotes_matched<S: AsRef<str>>(input: S) -> bool {
     let mut escape = false;
     let mut count = 0;
@@ -79,8 +101,6 @@ pub fn main() -> Result<(), JsValue> {
     terminal.writeln(&format!("An incomplete implementation of Common Lisp, this time in browsers."));
     terminal.writeln("");
     terminal.writeln(&format!("rcl-wasm is free software, provided as is, with absolutely no warranty."));
-    terminal.writeln("");
-    terminal.writeln("WARNING: print, princ, and terpri currently don't work");
     terminal.open(elem.dyn_into()?);
     prompt(&terminal, false);
 
@@ -88,6 +108,10 @@ pub fn main() -> Result<(), JsValue> {
     let mut cursor_col = 0;
 
     let env = generate_default_env();
+    let output = TerminalOutput::default();
+    set_standard_output(&env, Box::new(output.clone()));
+    set_error_output(&env, Box::new(output.clone()));
+    set_standard_input(&env, Box::new(io::empty()));
 
     let term: Terminal = terminal.clone().dyn_into()?;
 
@@ -99,8 +123,10 @@ pub fn main() -> Result<(), JsValue> {
                     prompt(&term, false);
                 } else if quotes_matched(&line) {
                     term.writeln("");
-                    match interpret(line.as_bytes(), &env) {
-                        Ok(res) => term.writeln(&format!("{}", res)),
+                    let ret = interpret(line.as_bytes(), &env).and_then(|res| print_string(&env, &res, true));
+                    output.flush_to(&term);
+                    match ret {
+                        Ok(printed) => term.writeln(&printed),
                         Err(e) => term.writeln(&format!("Error: {}", e)),
                     }
                     line.clear();
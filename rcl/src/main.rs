@@ -2,14 +2,13 @@ mod helper;
 
 use log::{debug, error};
 use clap::Parser;
-use anyhow::{Error, bail};
+use anyhow::{Error, anyhow, bail};
 use rustyline::Editor;
 
-use rclisp::{interpret, generate_default_env};
+use rclisp::{interpret, load, print_string, generate_default_env, Capability};
 
 use std::path::PathBuf;
 use std::env;
-use std::fs;
 
 use helper::RCLReadlineHelper;
 
@@ -53,13 +52,14 @@ fn main() -> Result<(), Error> {
 
     debug!("args: {:?}", args);
 
-    // Initial global env
+    // Initial global env, Lisp code run from the command line may use the file system
     let env = generate_default_env();
+    env.borrow_mut().grant_capability(Capability::FileSystem);
 
-    // Load files
+    // Load files like `load` does, so that they can require modules next to them
     for path in args.load {
-        if let Ok(file) = fs::File::open(&path) {
-            interpret(file, &env)?;
+        if path.is_file() {
+            load(&path, &env, false, false).map_err(|e| anyhow!("{}", e))?;
         } else {
             error!("Failed to open file: {:?}", &path);
         }
@@ -67,8 +67,8 @@ fn main() -> Result<(), Error> {
 
     if !args.eval.is_empty() {
         for path in args.eval {
-            if let Ok(file) = fs::File::open(&path) {
-                interpret(file, &env)?;
+            if path.is_file() {
+                load(&path, &env, false, false).map_err(|e| anyhow!("{}", e))?;
             } else {
                 error!("Failed to evaluate file: {:?}", &path);
                 bail!("Failed to evaluate file: {:?}", &path);
@@ -88,8 +88,9 @@ fn main() -> Result<(), Error> {
 
     loop {
         let input = editor.readline("* ")?;
-        match interpret(input.as_bytes(), &env) {
-            Ok(res) => println!("{}", res),
+        // Results are printed like `prin1` does, through print-object methods
+        match interpret(input.as_bytes(), &env).and_then(|res| print_string(&env, &res, true)) {
+            Ok(printed) => println!("{}", printed),
             Err(e) => eprintln!("Encountered error: {}", e),
         }
     }
@@ -1,6 +1,12 @@
 use std::ops::{Add, Sub, Mul, Div};
 
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, BINARY_PARAMETERS, rcenv_get};
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, BINARY_PARAMETERS, rcenv_get};
+
+use super::values::primary_value;
+
+builtin_parameters! {
+    static ref FLOOR_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Integer(1))]);
+}
 
 macro_rules! create_arithmetic_struct {
     ($struct:ident, $name:expr, $op:ident) => (
@@ -29,7 +35,70 @@ macro_rules! create_arithmetic_struct {
     );
 }
 
+/// Sum of two numbers, contagious to floats
+pub(crate) fn add(x: &Object, y: &Object) -> Result<Object, EvalError> {
+    match (x, y) {
+        (Object::Integer(x), Object::Integer(y)) => Ok(Object::Integer(x + y)),
+        (Object::Float(x), Object::Float(y)) => Ok(Object::Float(x + y)),
+        (Object::Integer(x), Object::Float(y)) | (Object::Float(y), Object::Integer(x)) => Ok(Object::Float(*x as f64 + y)),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+pub(crate) fn negate(x: &Object) -> Result<Object, EvalError> {
+    match x {
+        Object::Integer(x) => Ok(Object::Integer(-x)),
+        Object::Float(x) => Ok(Object::Float(-x)),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
 create_arithmetic_struct!(ObjectAdd, "+", add);
 create_arithmetic_struct!(ObjectSub, "-", sub);
 create_arithmetic_struct!(ObjectMul, "*", mul);
 create_arithmetic_struct!(ObjectDiv, "/", div);
+
+pub struct ObjectFloor;
+
+impl BuiltinFunc for ObjectFloor {
+    fn get_parameters(&self) -> &Params {
+        &FLOOR_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "floor"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // Returns the quotient and the remainder
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let x = rcenv_get!(env, "X")?;
+        let y = rcenv_get!(env, "Y")?;
+        match (x, y) {
+            (Object::Integer(_), Object::Integer(0)) => Err(EvalError::DivisionByZero),
+            (Object::Integer(x), Object::Integer(y)) => {
+                let mut q = x / y;
+                if x % y != 0 && ((x < 0) != (y < 0)) {
+                    q -= 1;
+                }
+                Ok(vec![Object::Integer(q), Object::Integer(x - q * y)])
+            },
+            (x, y) => {
+                let (x, y) = match (x, y) {
+                    (Object::Float(x), Object::Float(y)) => (x, y),
+                    (Object::Integer(x), Object::Float(y)) => (x as f64, y),
+                    (Object::Float(x), Object::Integer(y)) => (x, y as f64),
+                    _ => return Err(EvalError::ParameterTypeMismatched),
+                };
+                if y == 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                let q = (x / y).floor();
+                Ok(vec![Object::Integer(q as isize), Object::Float(x - q * y)])
+            },
+        }
+    }
+}
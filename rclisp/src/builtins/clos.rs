@@ -0,0 +1,614 @@
+use std::rc::Rc;
+
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, List, Params, Parameter, REST_PARAMETERS, UNARY_PARAMETERS, BINARY_PARAMETERS, TERNARY_PARAMETERS, rcenv_get, guard_obj, symbol, list_items, caller_env};
+
+use crate::eval::{Eval, apply};
+use crate::package::designator_name;
+use crate::types::{Lambda, UserLambda, Instance, Class, Generic, Method, Qualifier, Specializer, SlotDef, Stream};
+use crate::types::class::STANDARD_OBJECT;
+use crate::env::FunctionName;
+use super::lambda::function_name;
+use super::types::declared_lambda;
+
+builtin_parameters! {
+    static ref DEFCLASS_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain("Z"), Parameter::plain_rest("W")]);
+    static ref DEFGENERIC_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref DEFMETHOD_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref MAKE_INSTANCE_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::rest("Y")]);
+}
+
+/// Make sure the generic function exists and is bound to its name
+fn ensure_generic(env: &RcEnv, name: &FunctionName) {
+    let generics = env.borrow().get_generics();
+    if generics.borrow().contains_key(name) {
+        return;
+    }
+    generics.borrow_mut().insert(name.clone(), Generic::default());
+    let dispatch = GenericDispatch { name: name.to_string(), function: name.clone() };
+    env.borrow_mut().insert_global_function(name, Object::from(dispatch));
+}
+
+fn add_method(env: &RcEnv, name: &FunctionName, method: Method) -> Result<(), EvalError> {
+    ensure_generic(env, name);
+    let generics = env.borrow().get_generics();
+    let mut generics = generics.borrow_mut();
+    let generic = generics.get_mut(name).unwrap();
+    if !generic.congruent(method.specializers.len()) {
+        return Err(EvalError::IncongruentLambdaList(name.to_string()));
+    }
+    generic.add_method(method);
+    Ok(())
+}
+
+/// Error for a slot without a value, which is unbound if the class has it and missing otherwise
+fn slot_error(env: &RcEnv, instance: &Instance, slot: String) -> EvalError {
+    let class = instance.get_class();
+    let classes = env.borrow().get_classes();
+    if classes.borrow().effective_slots(&class).iter().any(|s| s.name == slot) {
+        EvalError::UnboundSlot(slot)
+    } else {
+        EvalError::MissingSlot(slot, class)
+    }
+}
+
+// Methods taking part in one call, in the order they run
+#[derive(Debug)]
+struct EffectiveMethod {
+    name: String,
+    around: Vec<Object>,
+    before: Vec<Object>,
+    primary: Vec<Object>,
+    after: Vec<Object>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Position {
+    Around(usize),
+    Primary(usize),
+}
+
+impl EffectiveMethod {
+    fn has_method(&self, position: Position) -> bool {
+        match position {
+            Position::Around(_) => true,
+            Position::Primary(i) => i < self.primary.len(),
+        }
+    }
+
+    fn invoke(self: &Rc<Self>, env: &RcEnv, position: Position, args: Vec<Object>) -> Result<Object, EvalError> {
+        match position {
+            Position::Around(i) if i < self.around.len() => {
+                self.call_method(env, &self.around[i], Position::Around(i + 1), args)
+            },
+            Position::Around(_) => {
+                for method in self.before.iter() {
+                    apply(method.clone(), args.clone(), env)?;
+                }
+                let ret = self.invoke(env, Position::Primary(0), args.clone())?;
+                for method in self.after.iter() {
+                    apply(method.clone(), args.clone(), env)?;
+                }
+                Ok(ret)
+            },
+            Position::Primary(i) if i < self.primary.len() => {
+                self.call_method(env, &self.primary[i], Position::Primary(i + 1), args)
+            },
+            Position::Primary(_) => Err(EvalError::NoNextMethod(self.name.clone())),
+        }
+    }
+
+    /// Run a method with `call-next-method` and `next-method-p` bound in its lexical environment
+    fn call_method(self: &Rc<Self>, env: &RcEnv, method: &Object, next: Position, args: Vec<Object>) -> Result<Object, EvalError> {
+        let method = match *guard_obj!(method.clone(), Lambda)? {
+            Lambda::Named(name, lambda) => Lambda::Named(name, self.bind_next_method(env, lambda, next, &args)),
+            Lambda::Unnamed(lambda) => Lambda::Unnamed(self.bind_next_method(env, lambda, next, &args)),
+            builtin => builtin,
+        };
+        apply(method.into(), args, env)
+    }
+
+    fn bind_next_method(self: &Rc<Self>, env: &RcEnv, lambda: UserLambda, next: Position, args: &[Object]) -> UserLambda {
+        let frame = Env::inherit(&lambda.get_env().unwrap_or_else(|| env.clone())).wrap();
+        frame.borrow_mut().insert_str("CALL-NEXT-METHOD", Object::from(NextMethod {
+            method: self.clone(),
+            next,
+            args: args.to_vec(),
+        }));
+        frame.borrow_mut().insert_str("NEXT-METHOD-P", Object::from(NextMethodP {
+            method: self.clone(),
+            next,
+        }));
+        lambda.with_env(&frame)
+    }
+}
+
+/// Call a generic function with evaluated arguments
+pub fn call_generic(env: &RcEnv, name: &FunctionName, args: Vec<Object>) -> Result<Object, EvalError> {
+    let methods = {
+        let generics = env.borrow().get_generics();
+        let generics = generics.borrow();
+        let generic = generics.get(name).ok_or_else(|| EvalError::UnboundVariable(name.to_string()))?;
+        let classes = env.borrow().get_classes();
+        let classes = classes.borrow();
+        generic.applicable_methods(&classes, &args)
+    };
+    let select = |qualifier: Qualifier| -> Vec<Object> {
+        methods.iter().filter(|m| m.qualifier == qualifier).map(|m| m.function.clone()).collect()
+    };
+    let mut after = select(Qualifier::After);
+    after.reverse();
+    let method = Rc::new(EffectiveMethod {
+        name: name.to_string(),
+        around: select(Qualifier::Around),
+        before: select(Qualifier::Before),
+        primary: select(Qualifier::Primary),
+        after,
+    });
+    if method.primary.is_empty() {
+        return Err(EvalError::NoApplicableMethod(name.to_string()));
+    }
+    method.invoke(env, Position::Around(0), args)
+}
+
+/// Whether the generic function has a method applicable to the arguments
+pub fn generic_applicable_p(env: &RcEnv, name: &FunctionName, args: &[Object]) -> bool {
+    let generics = env.borrow().get_generics();
+    let generics = generics.borrow();
+    let classes = env.borrow().get_classes();
+    let classes = classes.borrow();
+    generics.get(name)
+        .map(|g| !g.applicable_methods(&classes, args).is_empty())
+        .unwrap_or(false)
+}
+
+pub struct GenericDispatch {
+    name: String,
+    function: FunctionName,
+}
+
+impl BuiltinFunc for GenericDispatch {
+    fn get_parameters(&self) -> &Params {
+        &REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let args = list_items(rcenv_get!(env, "X")?);
+        call_generic(&caller_env(env), &self.function, args)
+    }
+}
+
+struct NextMethod {
+    method: Rc<EffectiveMethod>,
+    next: Position,
+    args: Vec<Object>,
+}
+
+impl BuiltinFunc for NextMethod {
+    fn get_parameters(&self) -> &Params {
+        &REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "call-next-method"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let args = match rcenv_get!(env, "X") {
+            Ok(Object::Nil) | Err(_) => self.args.clone(),
+            Ok(args) => list_items(args),
+        };
+        self.method.invoke(&caller_env(env), self.next, args)
+    }
+}
+
+struct NextMethodP {
+    method: Rc<EffectiveMethod>,
+    next: Position,
+}
+
+impl BuiltinFunc for NextMethodP {
+    fn get_parameters(&self) -> &Params {
+        &super::EMPTY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "next-method-p"
+    }
+
+    fn eval(&self, _env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(if self.method.has_method(self.next) { Object::T } else { Object::Nil })
+    }
+}
+
+struct SlotReader {
+    name: String,
+    slot: String,
+}
+
+impl BuiltinFunc for SlotReader {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let instance = guard_obj!(rcenv_get!(env, "X")?, Instance)?;
+        instance.get_slot(&self.slot).ok_or_else(|| EvalError::UnboundSlot(self.slot.clone()))
+    }
+}
+
+struct SlotWriter {
+    name: String,
+    slot: String,
+}
+
+impl BuiltinFunc for SlotWriter {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let value = rcenv_get!(env, "X")?;
+        let instance = guard_obj!(rcenv_get!(env, "Y")?, Instance)?;
+        instance.set_slot(&self.slot, value.clone());
+        Ok(value)
+    }
+}
+
+/// Define a class from `defclass` style slot specifiers, along with its slot accessors
+pub fn define_class(env: &RcEnv, name: &str, supers: Vec<String>, slot_specs: Object) -> Result<(), EvalError> {
+    let mut slots = vec![];
+    let mut readers = vec![];
+    let mut writers = vec![];
+    for spec in list_items(slot_specs) {
+        let (slot_name, options) = match spec {
+            Object::List(l) => l.unpack(),
+            obj => (obj, Object::Nil),
+        };
+        let slot_name = guard_obj!(slot_name, Symbol)?;
+        let mut slot = SlotDef { name: slot_name.clone(), initargs: vec![], initform: None };
+        let mut options = list_items(options).into_iter();
+        while let (Some(key), Some(value)) = (options.next(), options.next()) {
+            match designator_name(&key).as_deref() {
+                Some("INITARG") => slot.initargs.push(guard_obj!(value, Symbol)?),
+                Some("INITFORM") => slot.initform = Some(value),
+                Some("READER") => readers.push((guard_obj!(value, Symbol)?, slot_name.clone())),
+                Some("WRITER") => writers.push((function_name(value)?.0, slot_name.clone())),
+                Some("ACCESSOR") => {
+                    let accessor = guard_obj!(value, Symbol)?;
+                    writers.push((FunctionName::Setf(accessor.clone()), slot_name.clone()));
+                    readers.push((accessor, slot_name.clone()));
+                },
+                _ => (),
+            }
+        }
+        slots.push(slot);
+    }
+
+    let class = Class::new(name, supers, slots);
+    env.borrow().get_classes().borrow_mut().define(class)?;
+
+    for (reader, slot) in readers {
+        define_reader(env, &reader, name, &slot)?;
+    }
+    for (writer, slot) in writers {
+        add_method(env, &writer, Method {
+            qualifier: Qualifier::Primary,
+            specializers: vec![Specializer::Class("T".into()), Specializer::Class(name.into())],
+            function: Object::from(SlotWriter { name: writer.to_string(), slot }),
+        })?;
+    }
+    Ok(())
+}
+
+/// Reader method for a slot of a class
+pub fn define_reader(env: &RcEnv, reader: &str, class: &str, slot: &str) -> Result<(), EvalError> {
+    add_method(env, &FunctionName::Symbol(reader.into()), Method {
+        qualifier: Qualifier::Primary,
+        specializers: vec![Specializer::Class(class.into())],
+        function: Object::from(SlotReader { name: reader.into(), slot: slot.into() }),
+    })
+}
+
+pub struct ObjectDefclass;
+
+impl BuiltinFunc for ObjectDefclass {
+    fn get_parameters(&self) -> &Params {
+        &DEFCLASS_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defclass"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let mut supers = vec![];
+        for s in list_items(rcenv_get!(env, "Y")?) {
+            supers.push(guard_obj!(s, Symbol)?);
+        }
+        if supers.is_empty() {
+            supers.push(STANDARD_OBJECT.into());
+        }
+
+        let slot_specs = rcenv_get!(env, "Z")?;
+        define_class(env, &name, supers, slot_specs)?;
+        Ok(symbol(name))
+    }
+}
+
+/// Create an instance from evaluated initargs, initforms are evaluated in `env`
+pub fn make_instance(env: &RcEnv, name: &str, initargs: Vec<Object>) -> Result<Object, EvalError> {
+    if !initargs.len().is_multiple_of(2) {
+        return Err(EvalError::ParameterTypeMismatched);
+    }
+    let slots = {
+        let classes = env.borrow().get_classes();
+        let classes = classes.borrow();
+        classes.get(name).ok_or_else(|| EvalError::ClassNotFound(name.into()))?;
+        classes.effective_slots(name)
+    };
+    let initargs: Vec<(Object, Object)> = initargs.chunks(2).map(|p| (p[0].clone(), p[1].clone())).collect();
+    for (key, _) in initargs.iter() {
+        let key = guard_obj!(key.clone(), Symbol)?;
+        if !slots.iter().any(|s| s.initargs.contains(&key)) {
+            return Err(EvalError::UnknownKeyword(key.trim_start_matches(':').into()));
+        }
+    }
+
+    let instance = Instance::new(name);
+    for slot in slots {
+        let given = initargs.iter()
+            .find(|(key, _)| matches!(key, Object::Symbol(k) if slot.initargs.contains(k)));
+        if let Some((_, value)) = given {
+            instance.set_slot(&slot.name, value.clone());
+        } else if let Some(form) = slot.initform {
+            instance.set_slot(&slot.name, form.eval(env)?);
+        }
+    }
+    Ok(instance.into())
+}
+
+pub struct ObjectMakeInstance;
+
+impl BuiltinFunc for ObjectMakeInstance {
+    fn get_parameters(&self) -> &Params {
+        &MAKE_INSTANCE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "make-instance"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let initargs = list_items(rcenv_get!(env, "Y")?);
+        make_instance(&caller_env(env), &name, initargs)
+    }
+}
+
+pub struct ObjectSlotValue;
+
+impl BuiltinFunc for ObjectSlotValue {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "slot-value"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let slot = guard_obj!(rcenv_get!(env, "Y")?, Symbol)?;
+        match rcenv_get!(env, "X")? {
+            Object::Instance(i) => i.get_slot(&slot).ok_or_else(|| slot_error(env, &i, slot)),
+            Object::Struct(s) => {
+                let name = designator_name(&Object::Symbol(slot.clone())).unwrap_or_default();
+                s.get_slot(name).ok_or_else(|| EvalError::MissingSlot(slot, s.get_name()))
+            },
+            _ => Err(EvalError::ParameterTypeMismatched),
+        }
+    }
+}
+
+pub struct ObjectSetfSlotValue;
+
+impl BuiltinFunc for ObjectSetfSlotValue {
+    fn get_parameters(&self) -> &Params {
+        &TERNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "(setf slot-value)"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let value = rcenv_get!(env, "X")?;
+        let slot = guard_obj!(rcenv_get!(env, "Z")?, Symbol)?;
+        match rcenv_get!(env, "Y")? {
+            Object::Instance(i) => {
+                if i.get_slot(&slot).is_none() {
+                    if let error @ EvalError::MissingSlot(_, _) = slot_error(env, &i, slot.clone()) {
+                        return Err(error);
+                    }
+                }
+                i.set_slot(&slot, value.clone());
+            },
+            Object::Struct(s) => {
+                let name = designator_name(&Object::Symbol(slot.clone())).unwrap_or_default();
+                if !s.set_slot(name, value.clone()) {
+                    return Err(EvalError::MissingSlot(slot, s.get_name()));
+                }
+            },
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        }
+        Ok(value)
+    }
+}
+
+pub struct ObjectDefgeneric;
+
+impl BuiltinFunc for ObjectDefgeneric {
+    fn get_parameters(&self) -> &Params {
+        &DEFGENERIC_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defgeneric"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let (name, _) = function_name(rcenv_get!(env, "X")?)?;
+        let required = list_items(rcenv_get!(env, "Y")?).iter()
+            .take_while(|p| !matches!(p, Object::Symbol(s) if s.starts_with('&')))
+            .count();
+        ensure_generic(env, &name);
+        let generics = env.borrow().get_generics();
+        let mut generics = generics.borrow_mut();
+        let generic = generics.get_mut(&name).unwrap();
+        // Without methods, a new lambda list replaces the previous one
+        if generic.methods.is_empty() {
+            generic.required = Some(required);
+        } else if !generic.congruent(required) {
+            return Err(EvalError::IncongruentLambdaList(name.to_string()));
+        }
+        Ok(Object::from(name))
+    }
+}
+
+pub struct ObjectDefmethod;
+
+impl BuiltinFunc for ObjectDefmethod {
+    fn get_parameters(&self) -> &Params {
+        &DEFMETHOD_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defmethod"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let (name, _) = function_name(rcenv_get!(env, "X")?)?;
+        let mut rest = guard_obj!(rcenv_get!(env, "Y")?, List)?;
+
+        // Qualifiers come before the lambda list
+        let mut qualifier = Qualifier::Primary;
+        while let Object::Symbol(s) = rest.car() {
+            qualifier = match s.as_str() {
+                ":BEFORE" => Qualifier::Before,
+                ":AFTER" => Qualifier::After,
+                ":AROUND" => Qualifier::Around,
+                _ => return Err(EvalError::ParameterTypeMismatched),
+            };
+            rest = guard_obj!(rest.cdr(), List)?;
+        }
+        let (lambda_list, body) = rest.unpack();
+        let body = match body {
+            Object::List(l) => l.to_list(),
+            _ => List::EndsWith(Object::Nil),
+        };
+
+        // Specialized parameters are (name class) or (name (eql form))
+        let caller = caller_env(env);
+        let mut parameters = vec![];
+        let mut specializers = vec![];
+        let mut required = true;
+        for param in list_items(lambda_list) {
+            match param {
+                Object::Symbol(s) => {
+                    if s.starts_with('&') {
+                        required = false;
+                    } else if required {
+                        specializers.push(Specializer::Class("T".into()));
+                    }
+                    parameters.push(Object::Symbol(s));
+                },
+                Object::List(l) if required => {
+                    let (var, spec) = l.unpack();
+                    let spec = guard_obj!(spec, List)?.car();
+                    specializers.push(match spec {
+                        Object::Symbol(class) => Specializer::Class(class),
+                        Object::List(eql) => Specializer::Eql(guard_obj!(eql.cdr(), List)?.car().eval(&caller)?),
+                        _ => return Err(EvalError::ParameterTypeMismatched),
+                    });
+                    parameters.push(var);
+                },
+                spec @ Object::List(_) => parameters.push(spec),
+                _ => return Err(EvalError::ParameterTypeMismatched),
+            }
+        }
+        let params = Params::try_from(match Object::from(parameters) {
+            Object::List(l) => l.to_list(),
+            _ => List::EndsWith(Object::Nil),
+        })?;
+        let function = Object::from(Lambda::Named(name.to_string(), declared_lambda(params, body)?.with_env(&caller)));
+        add_method(env, &name, Method { qualifier, specializers, function })?;
+        Ok(Object::from(name))
+    }
+}
+
+/// Print to a stream through a `print-object` method if there is one for the object
+pub fn print_object(env: &RcEnv, obj: &Object, stream: &Stream) -> Result<bool, EvalError> {
+    let args = vec![obj.clone(), stream.clone().into()];
+    let name = FunctionName::Symbol("PRINT-OBJECT".into());
+    if generic_applicable_p(env, &name, &args) {
+        call_generic(env, &name, args)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EvalError, Object};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_congruent_methods() {
+        let (_, run) = interpreter();
+        run("(defgeneric area (shape &optional scale))").unwrap();
+        run("(defmethod area ((s integer) &optional (scale 1)) (* s scale))").unwrap();
+        assert_eq!(run("(area 3)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(defmethod area ((s integer) extra) s)"), Err(EvalError::IncongruentLambdaList("AREA".into())));
+        assert!(run("(defmethod area (s t2) s)").is_err());
+        assert_eq!(run("(area 2 4)"), Ok(Object::Integer(8)));
+        // The first method fixes the lambda list of an implicit generic function
+        run("(defmethod perimeter ((s integer)) (* 4 s))").unwrap();
+        assert!(run("(defgeneric perimeter (a b))").is_err());
+        assert!(run("(defmethod perimeter () 0)").is_err());
+        assert_eq!(run("(perimeter 2)"), Ok(Object::Integer(8)));
+        // Accessors take one argument, writers two
+        run("(defclass box () ((size :accessor box-size :initform 1)))").unwrap();
+        assert!(run("(defmethod box-size ((b box) extra) extra)").is_err());
+        assert!(run("(defmethod (setf box-size) (value (b box)) value)").is_ok());
+    }
+
+    #[test]
+    fn test_missing_slots() {
+        let (_, run) = interpreter();
+        run("(defclass point () ((x :initarg :x) y))").unwrap();
+        run("(defvar *p* (make-instance 'point :x 1))").unwrap();
+        assert_eq!(run("(slot-value *p* 'x)"), Ok(Object::Integer(1)));
+        // A declared slot without a value is unbound, any other is missing
+        assert_eq!(run("(handler-case (slot-value *p* 'y) (unbound-slot (c) (cell-error-name c)))"), run("'y"));
+        assert_eq!(run("(slot-value *p* 'z)"), Err(EvalError::MissingSlot("Z".into(), "POINT".into())));
+        assert_eq!(run("(handler-case (slot-value *p* 'z) (unbound-slot () 'unbound) (error () 'missing))"), run("'missing"));
+        assert_eq!(run("(progn (setf (slot-value *p* 'y) 2) (slot-value *p* 'y))"), Ok(Object::Integer(2)));
+        assert!(run("(setf (slot-value *p* 'z) 3)").is_err());
+        run("(defstruct pair a b)").unwrap();
+        assert_eq!(run("(slot-value (make-pair) 'c)"), Err(EvalError::MissingSlot("C".into(), "PAIR".into())));
+        assert!(run("(setf (slot-value (make-pair) 'c) 1)").is_err());
+    }
+}
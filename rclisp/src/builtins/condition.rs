@@ -0,0 +1,759 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, List, Params, Parameter, PLAIN_REST_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env, symbol, cons, nil};
+
+use crate::eval::{Eval, apply, apply_values, eval_body_values, new_frame_id};
+use crate::package::designator_name;
+use crate::types::{Lambda, Handler, HandlerAction, Restart, Stream, quote};
+use crate::types::class::CONDITION;
+use super::clos::{define_class, make_instance};
+use super::format::format;
+use super::print::print_string;
+use super::stream::{standard_stream, stream_error, write_str, ERROR_OUTPUT};
+use super::lambda::{lambda_list, body_forms};
+use super::types::{typep, declared_lambda};
+use super::values::primary_value;
+
+builtin_parameters! {
+    static ref DEFINE_CONDITION_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain("Z"), Parameter::plain_rest("W")]);
+    static ref SIGNAL_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::rest("Y")]);
+    static ref FORM_CLAUSES_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref CHECK_TYPE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::optional("Z", Object::Nil)]);
+}
+
+/// Whether an object is an instance of a condition class
+pub fn condition_p(env: &RcEnv, obj: &Object) -> bool {
+    match obj {
+        Object::Instance(i) => env.borrow().get_classes().borrow().subclass_p(i.get_class(), CONDITION),
+        _ => false,
+    }
+}
+
+/// Condition standing for a failure of the interpreter itself
+fn builtin_condition(env: &RcEnv, err: &EvalError) -> Result<Object, EvalError> {
+    let (class, initargs) = match err {
+        EvalError::UnboundVariable(s) => ("UNBOUND-VARIABLE", vec![symbol(":NAME"), symbol(s)]),
+        EvalError::UnboundSlot(s) => ("UNBOUND-SLOT", vec![symbol(":NAME"), symbol(s)]),
+        EvalError::ParameterTypeMismatched => ("TYPE-ERROR", vec![]),
+        EvalError::UnmatchedNumberOfParameters(_, _) | EvalError::PatternMismatch(_, _) | EvalError::UnknownKeyword(_) | EvalError::IllegalFunctionCall => ("PROGRAM-ERROR", vec![]),
+        EvalError::PackageNotFound(p) | EvalError::SymbolNotExternal(p, _) => ("PACKAGE-ERROR", vec![symbol(":PACKAGE"), Object::String(p.clone())]),
+        EvalError::ParseError(..) => ("PARSE-ERROR", vec![]),
+        EvalError::DivisionByZero => ("DIVISION-BY-ZERO", vec![]),
+        EvalError::StreamError(_) => ("STREAM-ERROR", vec![]),
+        EvalError::EndOfFile => ("END-OF-FILE", vec![]),
+        EvalError::FileError(p, _) => ("FILE-ERROR", vec![symbol(":PATHNAME"), Object::String(p.clone())]),
+        EvalError::RestartNotFound(_) | EvalError::UnknownBlock(_) | EvalError::UnknownTag(_) | EvalError::NoCatchTag(_) => ("CONTROL-ERROR", vec![]),
+        _ => ("ERROR", vec![]),
+    };
+    let condition = make_instance(env, class, initargs)?;
+    // Builtin conditions report the message of the original error
+    if let Object::Instance(i) = &condition {
+        i.set_slot("FORMAT-CONTROL", Object::String(err.to_string().replace('~', "~~")));
+    }
+    Ok(condition)
+}
+
+/// Signal an error raised by the interpreter, running the handlers in the current dynamic context.
+/// This happens once, where the error leaves the extent of a handler, restart, catch tag or
+/// cleanup form, and turns it into a condition. Errors which are already conditions or transfers
+/// of control, and every error when no handler is established, are returned as is.
+pub fn signal_error(err: EvalError, env: &RcEnv) -> EvalError {
+    if matches!(err, EvalError::Condition(_, _) | EvalError::Exit(_, _)) || env.borrow().get_conditions().borrow().handlers.is_empty() {
+        return err;
+    }
+    let condition = match builtin_condition(env, &err) {
+        Ok(c) => c,
+        Err(_) => return err,
+    };
+    if let Err(e) = signal(env, &condition) {
+        return e;
+    }
+    EvalError::Condition(condition, err.to_string())
+}
+
+/// Run the applicable handlers from the innermost one, returns normally if all of them decline
+pub fn signal(env: &RcEnv, condition: &Object) -> Result<(), EvalError> {
+    let conditions = env.borrow().get_conditions();
+    let clusters = conditions.borrow().handlers.clone();
+    for (i, cluster) in clusters.iter().enumerate().rev() {
+        for handler in cluster.iter() {
+            if !typep(env, condition, &handler.type_spec)? {
+                continue;
+            }
+            match &handler.action {
+                HandlerAction::Exit(id, index) => {
+                    return Err(EvalError::Exit(*id, vec![Object::Integer(*index as isize), condition.clone()]));
+                },
+                HandlerAction::Call(function) => {
+                    // A handler runs with only the handlers outside its own cluster active
+                    let saved = std::mem::replace(&mut conditions.borrow_mut().handlers, clusters[..i].to_vec());
+                    let ret = apply(function.clone(), vec![condition.clone()], env).map_err(|e| signal_error(e, env));
+                    conditions.borrow_mut().handlers = saved;
+                    ret?;
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Signal a condition as an error, returning the error to propagate if no handler takes over
+pub fn error(env: &RcEnv, condition: Object) -> EvalError {
+    if let Err(e) = signal(env, &condition) {
+        return e;
+    }
+    match report(env, &condition) {
+        Ok(r) => EvalError::Condition(condition, r),
+        Err(e) => e,
+    }
+}
+
+/// Signal a condition as a warning, its report is printed unless a handler muffles it
+fn signal_warning(env: &RcEnv, condition: &Object) -> Result<(), EvalError> {
+    let id = new_frame_id();
+    let restart = Restart { name: "MUFFLE-WARNING".into(), id, index: 0 };
+    match with_restarts(env, vec![restart], || signal(env, condition)) {
+        Ok(()) => {
+            let stream = standard_stream(env, ERROR_OUTPUT)?;
+            stream.fresh_line().map_err(stream_error)?;
+            write_str(&stream, &format!("WARNING: {}\n", report(env, condition)?))
+        },
+        Err(EvalError::Exit(target, _)) if target == id => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Signal a SIMPLE-WARNING with the given format control and arguments
+pub fn warn(env: &RcEnv, control: &str, args: Vec<Object>) -> Result<(), EvalError> {
+    let condition = make_condition(env, "SIMPLE-WARNING", Object::String(control.into()), args)?;
+    signal_warning(env, &condition)
+}
+
+/// Text describing a condition
+pub fn report(env: &RcEnv, condition: &Object) -> Result<String, EvalError> {
+    let instance = guard_obj!(condition.clone(), Instance)?;
+    let precedence = env.borrow().get_classes().borrow().precedence_list(instance.get_class());
+    let reporter = {
+        let conditions = env.borrow().get_conditions();
+        let conditions = conditions.borrow();
+        precedence.iter().find_map(|c| conditions.reports.get(c).cloned())
+    };
+    match reporter {
+        Some(Object::String(s)) => return Ok(s),
+        // The report function writes to a string stream, or returns its text
+        Some(function) => {
+            let stream = Stream::string_output();
+            let ret = apply(function, vec![condition.clone(), stream.clone().into()], env)?;
+            let text = stream.take_string();
+            return match ret {
+                _ if !text.is_empty() => Ok(text),
+                Object::String(s) => Ok(s),
+                o => print_string(env, &o, false),
+            };
+        },
+        None => (),
+    }
+    if let Some(Object::String(control)) = instance.get_slot("FORMAT-CONTROL") {
+        let args = list_items(instance.get_slot("FORMAT-ARGUMENTS").unwrap_or(Object::Nil));
+        return format(env, &control, &args);
+    }
+    Ok(format!("Condition of type {} was signaled", instance.get_class()))
+}
+
+/// TYPE-ERROR reported with the given format control and arguments
+fn type_error_condition(env: &RcEnv, datum: Object, expected: Object, control: &str, args: Vec<Object>) -> Result<Object, EvalError> {
+    let condition = make_instance(env, "TYPE-ERROR", vec![
+        symbol(":DATUM"), datum,
+        symbol(":EXPECTED-TYPE"), expected,
+    ])?;
+    if let Object::Instance(i) = &condition {
+        i.set_slot("FORMAT-CONTROL", Object::String(control.into()));
+        i.set_slot("FORMAT-ARGUMENTS", Object::from(args));
+    }
+    Ok(condition)
+}
+
+/// Signal a TYPE-ERROR for a value, naming the place it was read from if any
+pub fn type_error(env: &RcEnv, datum: Object, expected: Object, place: Option<Object>) -> EvalError {
+    let (control, args) = match place {
+        Some(place) => ("The value of ~s is ~s, which is not of type ~s.", vec![place, datum.clone(), expected.clone()]),
+        None => ("The value ~s is not of type ~s.", vec![datum.clone(), expected.clone()]),
+    };
+    match type_error_condition(env, datum, expected, control, args) {
+        Ok(condition) => error(env, condition),
+        Err(e) => e,
+    }
+}
+
+/// Condition designated by the arguments of `error`, `signal` and `warn`
+fn make_condition(env: &RcEnv, default: &str, datum: Object, args: Vec<Object>) -> Result<Object, EvalError> {
+    match datum {
+        Object::String(_) => make_instance(env, default, vec![
+            symbol(":FORMAT-CONTROL"), datum,
+            symbol(":FORMAT-ARGUMENTS"), Object::from(args),
+        ]),
+        Object::Symbol(name) => make_instance(env, &name, args),
+        obj if condition_p(env, &obj) => Ok(obj),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Run `body` with a handler cluster established
+fn with_handlers<T, F: FnOnce() -> Result<T, EvalError>>(env: &RcEnv, cluster: Vec<Handler>, body: F) -> Result<T, EvalError> {
+    let conditions = env.borrow().get_conditions();
+    let depth = conditions.borrow().handlers.len();
+    conditions.borrow_mut().handlers.push(cluster);
+    let ret = body().map_err(|e| signal_error(e, env));
+    conditions.borrow_mut().handlers.truncate(depth);
+    ret
+}
+
+/// Run `body` with restarts established
+fn with_restarts<T, F: FnOnce() -> Result<T, EvalError>>(env: &RcEnv, restarts: Vec<Restart>, body: F) -> Result<T, EvalError> {
+    let conditions = env.borrow().get_conditions();
+    let depth = conditions.borrow().restarts.len();
+    conditions.borrow_mut().restarts.extend(restarts);
+    let ret = body().map_err(|e| signal_error(e, env));
+    conditions.borrow_mut().restarts.truncate(depth);
+    ret
+}
+
+/// Values carried by a transfer of control to the frame `id`
+fn exit_payload(result: &Result<Vec<Object>, EvalError>, id: usize) -> Option<(usize, Vec<Object>)> {
+    match result {
+        Err(EvalError::Exit(target, payload)) if *target == id => {
+            let index = match payload.first() {
+                Some(Object::Integer(i)) => *i as usize,
+                _ => 0,
+            };
+            Some((index, payload[1..].to_vec()))
+        },
+        _ => None,
+    }
+}
+
+/// Function made of a clause's parameter list and body, closing over the environment of the form
+fn clause_function(env: &RcEnv, params: Object, body: Object) -> Result<Object, EvalError> {
+    let lambda = declared_lambda(lambda_list(params)?, body_forms(body))?;
+    Ok(Lambda::Unnamed(lambda.with_env(env)).into())
+}
+
+pub struct ObjectDefineCondition;
+
+impl BuiltinFunc for ObjectDefineCondition {
+    fn get_parameters(&self) -> &Params {
+        &DEFINE_CONDITION_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "define-condition"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let mut supers = vec![];
+        for s in list_items(rcenv_get!(env, "Y")?) {
+            supers.push(guard_obj!(s, Symbol)?);
+        }
+        if supers.is_empty() {
+            supers.push(CONDITION.into());
+        }
+        let slot_specs = rcenv_get!(env, "Z")?;
+        define_class(env, &name, supers, slot_specs)?;
+
+        let caller = caller_env(env);
+        for option in list_items(rcenv_get!(env, "W")?) {
+            let (key, value) = guard_obj!(option, List)?.unpack();
+            if designator_name(&key).as_deref() == Some("REPORT") {
+                let reporter = match guard_obj!(value, List)?.car() {
+                    Object::String(s) => Object::String(s),
+                    form => form.eval(&caller)?,
+                };
+                env.borrow().get_conditions().borrow_mut().reports.insert(name.clone(), reporter);
+            }
+        }
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectMakeCondition;
+
+impl BuiltinFunc for ObjectMakeCondition {
+    fn get_parameters(&self) -> &Params {
+        &SIGNAL_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "make-condition"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let initargs = list_items(rcenv_get!(env, "Y")?);
+        make_instance(&caller_env(env), &name, initargs)
+    }
+}
+
+pub struct ObjectError;
+
+impl BuiltinFunc for ObjectError {
+    fn get_parameters(&self) -> &Params {
+        &SIGNAL_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "error"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let args = list_items(rcenv_get!(env, "Y")?);
+        let datum = rcenv_get!(env, "X")?;
+        let condition = make_condition(&caller, "SIMPLE-ERROR", datum, args)?;
+        Err(error(&caller, condition))
+    }
+}
+
+pub struct ObjectSignal;
+
+impl BuiltinFunc for ObjectSignal {
+    fn get_parameters(&self) -> &Params {
+        &SIGNAL_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "signal"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let args = list_items(rcenv_get!(env, "Y")?);
+        let datum = rcenv_get!(env, "X")?;
+        let condition = make_condition(&caller, "SIMPLE-CONDITION", datum, args)?;
+        signal(&caller, &condition)?;
+        Ok(Object::Nil)
+    }
+}
+
+pub struct ObjectWarn;
+
+impl BuiltinFunc for ObjectWarn {
+    fn get_parameters(&self) -> &Params {
+        &SIGNAL_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "warn"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let args = list_items(rcenv_get!(env, "Y")?);
+        let datum = rcenv_get!(env, "X")?;
+        let condition = make_condition(&caller, "SIMPLE-WARNING", datum, args)?;
+        signal_warning(&caller, &condition)?;
+        Ok(Object::Nil)
+    }
+}
+
+pub struct ObjectHandlerBind;
+
+impl ObjectHandlerBind {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let mut cluster = vec![];
+        for binding in list_items(rcenv_get!(env, "X")?) {
+            let (type_spec, rest) = guard_obj!(binding, List)?.unpack();
+            let function = guard_obj!(rest, List)?.car().eval(&caller)?;
+            cluster.push(Handler { type_spec, action: HandlerAction::Call(function) });
+        }
+        let body = match rcenv_get!(env, "Y")? {
+            Object::List(l) => l.to_list(),
+            _ => List::EndsWith(Object::Nil),
+        };
+        with_handlers(&caller, cluster, || eval_body_values(body, &caller))
+    }
+}
+
+impl BuiltinFunc for ObjectHandlerBind {
+    fn get_parameters(&self) -> &Params {
+        &FORM_CLAUSES_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "handler-bind"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectHandlerCase;
+
+impl ObjectHandlerCase {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let form = rcenv_get!(env, "X")?;
+        let id = new_frame_id();
+        let mut clauses = vec![];
+        let mut cluster = vec![];
+        let mut no_error = None;
+        for (i, clause) in list_items(rcenv_get!(env, "Y")?).into_iter().enumerate() {
+            let (type_spec, rest) = guard_obj!(clause, List)?.unpack();
+            let (params, body) = guard_obj!(rest, List)?.unpack();
+            clauses.push(clause_function(&caller, params, body)?);
+            if matches!(&type_spec, Object::Symbol(s) if s == ":NO-ERROR") {
+                no_error = Some(i);
+            } else {
+                cluster.push(Handler { type_spec, action: HandlerAction::Exit(id, i) });
+            }
+        }
+
+        let result = with_handlers(&caller, cluster, || form.eval_values(&caller));
+        if let Some((index, payload)) = exit_payload(&result, id) {
+            let function = clauses[index].clone();
+            // The variable of the clause is optional
+            let args = if function_arity(&function) == 0 { vec![] } else { payload };
+            return apply_values(function, args, &caller);
+        }
+        match (result, no_error) {
+            (Ok(values), Some(index)) => apply_values(clauses[index].clone(), values, &caller),
+            (result, _) => result,
+        }
+    }
+}
+
+fn function_arity(function: &Object) -> usize {
+    match function {
+        Object::Lambda(l) => l.get_parameters().len(),
+        _ => 0,
+    }
+}
+
+impl BuiltinFunc for ObjectHandlerCase {
+    fn get_parameters(&self) -> &Params {
+        &FORM_CLAUSES_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "handler-case"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectIgnoreErrors;
+
+impl ObjectIgnoreErrors {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let body = match rcenv_get!(env, "X")? {
+            Object::List(l) => l.to_list(),
+            _ => List::EndsWith(Object::Nil),
+        };
+        let id = new_frame_id();
+        let handler = Handler { type_spec: symbol("ERROR"), action: HandlerAction::Exit(id, 0) };
+        let result = with_handlers(&caller, vec![handler], || eval_body_values(body, &caller));
+        match exit_payload(&result, id) {
+            Some((_, condition)) => Ok(vec![Object::Nil, primary_value(condition)]),
+            None => result,
+        }
+    }
+}
+
+impl BuiltinFunc for ObjectIgnoreErrors {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "ignore-errors"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectRestartCase;
+
+impl ObjectRestartCase {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let form = rcenv_get!(env, "X")?;
+        let id = new_frame_id();
+        let mut clauses = vec![];
+        let mut restarts = vec![];
+        for (index, clause) in list_items(rcenv_get!(env, "Y")?).into_iter().enumerate() {
+            let (name, rest) = guard_obj!(clause, List)?.unpack();
+            let (params, mut body) = guard_obj!(rest, List)?.unpack();
+            // Skip options such as :report before the body
+            while let Object::List(l) = body.clone() {
+                let (key, rest) = l.unpack();
+                match key {
+                    Object::Symbol(k) if k.starts_with(':') => body = guard_obj!(rest, List)?.cdr(),
+                    _ => break,
+                }
+            }
+            clauses.push(clause_function(&caller, params, body)?);
+            restarts.push(Restart { name: guard_obj!(name, Symbol)?, id, index });
+        }
+
+        let result = with_restarts(&caller, restarts, || form.eval_values(&caller));
+        match exit_payload(&result, id) {
+            Some((index, args)) => apply_values(clauses[index].clone(), args, &caller),
+            None => result,
+        }
+    }
+}
+
+impl BuiltinFunc for ObjectRestartCase {
+    fn get_parameters(&self) -> &Params {
+        &FORM_CLAUSES_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "restart-case"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectInvokeRestart;
+
+impl BuiltinFunc for ObjectInvokeRestart {
+    fn get_parameters(&self) -> &Params {
+        &SIGNAL_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "invoke-restart"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let restart = env.borrow().get_conditions().borrow().find_restart(&name)
+            .ok_or(EvalError::RestartNotFound(name))?;
+        let mut payload = vec![Object::Integer(restart.index as isize)];
+        payload.append(&mut list_items(rcenv_get!(env, "Y")?));
+        Err(EvalError::Exit(restart.id, payload))
+    }
+}
+
+pub struct ObjectAssert;
+
+impl BuiltinFunc for ObjectAssert {
+    fn get_parameters(&self) -> &Params {
+        &FORM_CLAUSES_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "assert"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let test = rcenv_get!(env, "X")?;
+        // (assert test-form [(place*) [datum argument*]])
+        let mut rest = list_items(rcenv_get!(env, "Y")?).into_iter().skip(1);
+        let datum = rest.next();
+        let args: Vec<Object> = rest.collect();
+        loop {
+            if test.clone().eval(&caller)? != Object::Nil {
+                return Ok(Object::Nil);
+            }
+            let condition = match datum.clone() {
+                Some(datum) => {
+                    let mut values = vec![];
+                    for arg in args.iter() {
+                        values.push(arg.clone().eval(&caller)?);
+                    }
+                    make_condition(&caller, "SIMPLE-ERROR", datum.eval(&caller)?, values)?
+                },
+                None => make_condition(&caller, "SIMPLE-ERROR", Object::String("The assertion ~s failed.".into()), vec![test.clone()])?,
+            };
+            let id = new_frame_id();
+            let restart = Restart { name: "CONTINUE".into(), id, index: 0 };
+            match with_restarts(&caller, vec![restart], || Err::<(), _>(error(&caller, condition))) {
+                Err(EvalError::Exit(target, _)) if target == id => continue,
+                Err(e) => return Err(e),
+                Ok(()) => unreachable!(),
+            }
+        }
+    }
+}
+
+pub struct ObjectCheckType;
+
+impl BuiltinFunc for ObjectCheckType {
+    fn get_parameters(&self) -> &Params {
+        &CHECK_TYPE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "check-type"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let place = rcenv_get!(env, "X")?;
+        let type_spec = rcenv_get!(env, "Y")?;
+        let description = match rcenv_get!(env, "Z")? {
+            Object::String(s) => s,
+            _ => format!("of type {}", type_spec),
+        };
+        loop {
+            let value = place.clone().eval(&caller)?;
+            if typep(&caller, &value, &type_spec)? {
+                return Ok(Object::Nil);
+            }
+            let args = vec![place.clone(), value.clone(), Object::String(description.clone())];
+            let condition = type_error_condition(&caller, value, type_spec.clone(), "The value of ~s is ~s, which is not ~a.", args)?;
+            let id = new_frame_id();
+            let restart = Restart { name: "STORE-VALUE".into(), id, index: 0 };
+            let result = with_restarts(&caller, vec![restart], || Err::<Vec<Object>, _>(error(&caller, condition)));
+            match exit_payload(&result, id) {
+                Some((_, args)) => {
+                    let new_value = primary_value(args);
+                    cons(symbol("SETF"), cons(place.clone(), cons(quote(new_value), nil()))).eval(&caller)?;
+                },
+                None => return result.map(primary_value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::eval::EvalError;
+    use crate::builtins::test::interpreter;
+    use super::{builtin_condition, condition_p, report, signal_error};
+
+    #[test]
+    fn test_handler_case() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(handler-case (error \"boom\") (error (c) (simple-condition-format-control c)))"), Ok(Object::String("boom".into())));
+        assert_eq!(run("(handler-case (+ 1 2) (error () 0))"), Ok(Object::Integer(3)));
+        // The first clause whose type matches is chosen
+        assert_eq!(run("(handler-case (signal 'warning) (error () 'error) (warning () 'warning))"), run("'warning"));
+        assert_eq!(run("(handler-case (car 1) (type-error () 'type-error))"), run("'type-error"));
+    }
+
+    #[test]
+    fn test_handler_bind() {
+        let (_, run) = interpreter();
+        run("(defvar *seen* nil)").unwrap();
+        // A handler declines by returning, the next one up then sees the condition
+        let declining = "(handler-case (handler-bind ((error (lambda (c) (setq *seen* t)))) (error \"boom\")) (error () 'outer))";
+        assert_eq!(run(declining), run("'outer"));
+        assert_eq!(run("*seen*"), Ok(Object::T));
+        // Signals return NIL when every handler declines
+        assert_eq!(run("(handler-bind ((warning (lambda (c) c))) (signal 'warning))"), Ok(Object::Nil));
+        // A handler handles by transferring control
+        let handling = "(block done (handler-bind ((error (lambda (c) (return-from done 'handled)))) (error \"boom\")))";
+        assert_eq!(run(handling), run("'handled"));
+    }
+
+    #[test]
+    fn test_restarts() {
+        let (_, run) = interpreter();
+        let restart = "(restart-case (invoke-restart 'use 5) (use (x) (* x 2)))";
+        assert_eq!(run(restart), Ok(Object::Integer(10)));
+        assert_eq!(run("(restart-case 1 (use (x) x))"), Ok(Object::Integer(1)));
+        let from_handler = "(handler-bind ((error (lambda (c) (invoke-restart 'skip)))) (restart-case (error \"boom\") (skip () 'skipped)))";
+        assert_eq!(run(from_handler), run("'skipped"));
+        assert!(run("(invoke-restart 'missing)").is_err());
+    }
+
+    #[test]
+    fn test_define_condition() {
+        let (_, run) = interpreter();
+        run("(define-condition app-error (error) ((code :initarg :code :reader app-error-code)))").unwrap();
+        run("(define-condition disk-error (app-error) ())").unwrap();
+        assert_eq!(run("(handler-case (error 'disk-error :code 3) (app-error (c) (app-error-code c)))"), Ok(Object::Integer(3)));
+        assert_eq!(run("(handler-case (error 'disk-error :code 3) (error () 'error))"), run("'error"));
+        assert_eq!(run("(typep (make-condition 'disk-error) 'app-error)"), Ok(Object::T));
+        assert_eq!(run("(typep (make-condition 'app-error) 'disk-error)"), Ok(Object::Nil));
+    }
+
+    #[test]
+    fn test_ignore_errors() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(ignore-errors (+ 1 2))"), Ok(Object::Integer(3)));
+        assert_eq!(run("(ignore-errors (error \"boom\"))"), Ok(Object::Nil));
+        assert_eq!(run("(multiple-value-bind (v c) (ignore-errors (error \"boom\")) (list v (typep c 'simple-error)))"), run("'(nil t)"));
+        // Conditions which are not errors go through
+        assert_eq!(run("(ignore-errors (signal 'warning) 1)"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_unhandled_error() {
+        let (_, run) = interpreter();
+        match run("(error \"boom ~a\" 1)") {
+            Err(EvalError::Condition(_, message)) => assert_eq!(message, "boom 1"),
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(matches!(run("(handler-case (error \"boom\") (warning () 'warning))"), Err(EvalError::Condition(..))));
+        // Builtin errors stay as they are unless a handler is established around them
+        assert_eq!(run("(car 1)"), Err(EvalError::ParameterTypeMismatched));
+        assert_eq!(run("(let ((x 1)) (unwind-protect (+ x y) (setq x 2)))"), Err(EvalError::UnboundVariable("Y".into())));
+        match run("(handler-case (car 1) (warning () 'warning))") {
+            Err(EvalError::Condition(c, message)) => {
+                assert_eq!(message, EvalError::ParameterTypeMismatched.to_string());
+                assert!(matches!(c, Object::Instance(i) if i.get_class() == "TYPE-ERROR"));
+            },
+            result => panic!("unexpected {:?}", result),
+        }
+        // The environment is still usable afterwards
+        assert_eq!(run("(+ 1 2)"), Ok(Object::Integer(3)));
+    }
+
+    #[test]
+    fn test_builtin_error_signaling() {
+        let (_, run) = interpreter();
+        // Each handler sees a builtin error once, with the restarts around it still active
+        run("(defvar *calls* 0)").unwrap();
+        assert_eq!(run("(handler-case (handler-bind ((type-error (lambda (c) (incf *calls*)))) (handler-bind ((error (lambda (c) nil))) (list (car 1)))) (error () *calls*))"), Ok(Object::Integer(1)));
+        assert_eq!(run("(handler-bind ((type-error (lambda (c) (invoke-restart 'use-value 5)))) (restart-case (list (car 1)) (use-value (v) v)))"), Ok(Object::Integer(5)));
+        assert_eq!(run("(handler-case (unwind-protect (car 1) (incf *calls*)) (type-error () *calls*))"), Ok(Object::Integer(2)));
+        // Errors in a handler are signaled to the handlers outside of its cluster only
+        assert_eq!(run("(handler-case (handler-bind ((type-error (lambda (c) (car 2)))) (car 1)) (type-error () 'outer))"), run("'outer"));
+    }
+
+    #[test]
+    fn test_builtin_conditions() {
+        let (env, run) = interpreter();
+        let condition = builtin_condition(&env, &EvalError::UnboundVariable("X".into())).unwrap();
+        assert!(condition_p(&env, &condition));
+        assert!(!condition_p(&env, &Object::Integer(1)));
+        assert_eq!(report(&env, &condition), Ok(EvalError::UnboundVariable("X".into()).to_string()));
+        // Format directives in the message of an error are printed as they are
+        let condition = builtin_condition(&env, &EvalError::UnknownBlock("~a".into())).unwrap();
+        assert_eq!(report(&env, &condition), Ok(EvalError::UnknownBlock("~a".into()).to_string()));
+        assert!(report(&env, &Object::Integer(1)).is_err());
+        // Without handlers errors are left alone, conditions and exits always are
+        assert_eq!(signal_error(EvalError::DivisionByZero, &env), EvalError::DivisionByZero);
+        assert_eq!(signal_error(EvalError::Exit(0, vec![]), &env), EvalError::Exit(0, vec![]));
+        assert_eq!(run("(handler-case (floor 1 0) (arithmetic-error (c) (type-of c)))"), run("'division-by-zero"));
+    }
+}
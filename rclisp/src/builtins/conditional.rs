@@ -1,16 +1,43 @@
-use lazy_static::lazy_static;
 use log::trace;
 
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, rcenv_get, guard_obj};
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, List, rcenv_get, guard_obj, list_items, caller_env};
 
-use crate::eval::Eval;
+use crate::types::quote;
+use crate::eval::{Eval, eval_body, eval_body_values};
 
-lazy_static! {
-    static ref COND_PARAMETERS: Params = Params::from(vec![Parameter::rest("X")]);
+builtin_parameters! {
+    static ref COND_PARAMETERS: Params = Params::from(vec![Parameter::plain_rest("X")]);
+    static ref IF_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref WHEN_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
 }
 
 pub struct ObjectCond;
 
+impl ObjectCond {
+    /// Body of the first clause whose test is true
+    fn select_clause(&self, env: &RcEnv) -> Result<Option<List>, EvalError> {
+        let caller = caller_env(env);
+        let mut lst = rcenv_get!(env, "X")?;
+        trace!("X: {:?}", lst);
+        while lst != Object::Nil {
+            let (clause, cdr) = guard_obj!(lst, List)?.unpack();
+            trace!("current clause: {}", clause);
+            let (cond, body) = guard_obj!(clause, List)?.unpack();
+            let result = cond.eval(&caller)?;
+            if result != Object::Nil {
+                trace!("running: {}", body);
+                return Ok(Some(match body {
+                    Object::List(l) => l.to_list(),
+                    // A clause without body returns the value of its test
+                    _ => List::EndsWith(quote(result)),
+                }));
+            }
+            lst = cdr;
+        }
+        Ok(None)
+    }
+}
+
 impl BuiltinFunc for ObjectCond {
     fn get_parameters(&self) -> &Params {
         &COND_PARAMETERS
@@ -21,18 +48,101 @@ impl BuiltinFunc for ObjectCond {
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let mut lst = rcenv_get!(env, "X")?;
-        trace!("X: {:?}", lst);
-        while lst != Object::Nil {
-            let (clause, cdr) = guard_obj!(lst, List)?.unpack();
-            trace!("current clause: {}", clause);
-            let (cond, body) = guard_obj!(clause, List)?.unpack();
-            if cond.eval(env)? == Object::T {
-                trace!("running: {}", body);
-                return body.eval(env);
-            }
-            lst = cdr;
+        match self.select_clause(env)? {
+            Some(body) => eval_body(body, &caller_env(env)),
+            None => Ok(Object::Nil),
+        }
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        match self.select_clause(env)? {
+            Some(body) => eval_body_values(body, &caller_env(env)),
+            None => Ok(vec![Object::Nil]),
+        }
+    }
+}
+
+pub struct ObjectIf;
+
+impl ObjectIf {
+    /// Form of the branch selected by the test
+    fn select_branch(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let test = rcenv_get!(env, "X")?;
+        let otherwise = match list_items(rcenv_get!(env, "Z")?).as_slice() {
+            [] => Object::Nil,
+            [form] => form.clone(),
+            forms => return Err(EvalError::UnmatchedNumberOfParameters(3, 2 + forms.len())),
+        };
+        if test.eval(&caller_env(env))? != Object::Nil {
+            Ok(rcenv_get!(env, "Y")?)
+        } else {
+            Ok(otherwise)
         }
-        Ok(Object::Nil)
     }
-}
\ No newline at end of file
+}
+
+impl BuiltinFunc for ObjectIf {
+    fn get_parameters(&self) -> &Params {
+        &IF_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "if"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        self.select_branch(env)?.eval(&caller_env(env))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.select_branch(env)?.eval_values(&caller_env(env))
+    }
+}
+
+macro_rules! generate_one_armed_conditionals {
+    ($struct:ident, $name:expr, $run_when:expr) => (
+        pub struct $struct;
+
+        impl $struct {
+            /// Body to run, if the test has the expected truth value
+            fn select_body(&self, env: &RcEnv) -> Result<Option<List>, EvalError> {
+                let test = rcenv_get!(env, "X")?;
+                let result = test.eval(&caller_env(env))? != Object::Nil;
+                if result != $run_when {
+                    return Ok(None);
+                }
+                match rcenv_get!(env, "Y")? {
+                    Object::List(l) => Ok(Some(l.to_list())),
+                    _ => Ok(None),
+                }
+            }
+        }
+
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &WHEN_PARAMETERS
+            }
+
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                match self.select_body(env)? {
+                    Some(body) => eval_body(body, &caller_env(env)),
+                    None => Ok(Object::Nil),
+                }
+            }
+
+            fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+                match self.select_body(env)? {
+                    Some(body) => eval_body_values(body, &caller_env(env)),
+                    None => Ok(vec![Object::Nil]),
+                }
+            }
+        }
+    );
+}
+
+generate_one_armed_conditionals!(ObjectWhen, "when", true);
+generate_one_armed_conditionals!(ObjectUnless, "unless", false);
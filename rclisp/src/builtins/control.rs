@@ -0,0 +1,446 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, List, Params, Parameter, PLAIN_REST_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env};
+
+use crate::env::DynamicBindings;
+use crate::eval::{Eval, eval_body, eval_body_values, new_frame_id, destructure};
+use crate::types::equality::eql;
+use super::lambda::body_forms;
+use super::condition::signal_error;
+use super::values::primary_value;
+use super::types::declare;
+
+builtin_parameters! {
+    static ref LET_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref GO_PARAMETERS: Params = Params::from(vec![Parameter::plain("X")]);
+    static ref DESTRUCTURING_BIND_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::normal("Y"), Parameter::plain_rest("Z")]);
+    static ref CATCH_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::plain_rest("Y")]);
+}
+
+pub struct ObjectProgn;
+
+impl BuiltinFunc for ObjectProgn {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "progn"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        eval_body(body_forms(rcenv_get!(env, "X")?), &caller_env(env))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        eval_body_values(body_forms(rcenv_get!(env, "X")?), &caller_env(env))
+    }
+}
+
+/// Variable and initial form of a `let` binding, which is `var`, `(var)` or `(var form)`
+fn binding_spec(binding: Object) -> Result<(String, Object), EvalError> {
+    match binding {
+        Object::Symbol(name) => Ok((name, Object::Nil)),
+        Object::List(l) => {
+            let (name, rest) = l.unpack();
+            let name = guard_obj!(name, Symbol)?;
+            match list_items(rest).as_slice() {
+                [] => Ok((name, Object::Nil)),
+                [form] => Ok((name, form.clone())),
+                _ => Err(EvalError::ParameterTypeMismatched),
+            }
+        },
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Bind the variables of a `let` form in a new frame, returning the body to run in it while the bindings are alive.
+/// With `sequential`, each initial form sees the variables bound before it.
+fn bind_variables(env: &RcEnv, sequential: bool) -> Result<(List, RcEnv, DynamicBindings), EvalError> {
+    let caller = caller_env(env);
+    let frame = Env::inherit(&caller).wrap();
+    let mut bindings = DynamicBindings::new(&frame);
+    let specs = list_items(rcenv_get!(env, "X")?).into_iter()
+        .map(binding_spec)
+        .collect::<Result<Vec<_>, _>>()?;
+    if sequential {
+        for (name, form) in specs {
+            let value = form.eval(&frame)?;
+            bindings.bind(&frame, name, value)?;
+        }
+    } else {
+        let mut values = vec![];
+        for (name, form) in specs {
+            values.push((name, form.eval(&caller)?));
+        }
+        for (name, value) in values {
+            bindings.bind(&frame, name, value)?;
+        }
+    }
+    let body = declare(body_forms(rcenv_get!(env, "Y")?), &frame)?;
+    Ok((body, frame, bindings))
+}
+
+macro_rules! generate_let_forms {
+    ($struct:ident, $name:expr, $sequential:expr) => (
+        pub struct $struct;
+
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &LET_PARAMETERS
+            }
+
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                let (body, frame, _bindings) = bind_variables(env, $sequential)?;
+                eval_body(body, &frame)
+            }
+
+            fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+                let (body, frame, _bindings) = bind_variables(env, $sequential)?;
+                eval_body_values(body, &frame)
+            }
+        }
+    );
+}
+
+generate_let_forms!(ObjectLet, "let", false);
+generate_let_forms!(ObjectLetStar, "let*", true);
+
+/// Bind the lambda list of a `destructuring-bind` form to the value in a new frame
+fn bind_pattern(env: &RcEnv) -> Result<(List, RcEnv, DynamicBindings), EvalError> {
+    let pattern = Params::destructuring(rcenv_get!(env, "X")?)?;
+    let value = rcenv_get!(env, "Y")?;
+    let frame = Env::inherit(&caller_env(env)).wrap();
+    let mut bindings = DynamicBindings::new(&frame);
+    destructure(&pattern, value, &frame, &mut bindings)?;
+    let body = declare(body_forms(rcenv_get!(env, "Z")?), &frame)?;
+    Ok((body, frame, bindings))
+}
+
+pub struct ObjectDestructuringBind;
+
+impl BuiltinFunc for ObjectDestructuringBind {
+    fn get_parameters(&self) -> &Params {
+        &DESTRUCTURING_BIND_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "destructuring-bind"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let (body, frame, _bindings) = bind_pattern(env)?;
+        eval_body(body, &frame)
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let (body, frame, _bindings) = bind_pattern(env)?;
+        eval_body_values(body, &frame)
+    }
+}
+
+/// Name under which the frame id of a lexically visible block is stored
+pub(crate) fn block_key(name: &Object) -> String {
+    format!("(BLOCK {})", name)
+}
+
+/// Name under which the frame id of the tagbody defining a tag is stored
+fn tag_key(tag: &Object) -> String {
+    format!("(GO {})", tag)
+}
+
+fn is_tag(statement: &Object) -> bool {
+    matches!(statement, Object::Symbol(_) | Object::Integer(_))
+}
+
+/// Frame id stored under a key in the lexical environment
+fn lexical_frame(env: &RcEnv, key: &str) -> Option<usize> {
+    match env.borrow().get_str(key) {
+        Ok(Object::Integer(id)) => Some(id as usize),
+        _ => None,
+    }
+}
+
+/// Values carried by a transfer of control to the frame `id`, other results are returned as is
+pub(crate) fn catch_exit(result: Result<Vec<Object>, EvalError>, id: usize) -> Result<Vec<Object>, EvalError> {
+    match result {
+        Err(EvalError::Exit(target, values)) if target == id => Ok(values),
+        ret => ret,
+    }
+}
+
+/// Values of the optional result form of `return-from` and `throw`
+fn result_values(env: &RcEnv, forms: Object, expected: usize) -> Result<Vec<Object>, EvalError> {
+    match list_items(forms).as_slice() {
+        [] => Ok(vec![Object::Nil]),
+        [form] => form.clone().eval_values(env),
+        forms => Err(EvalError::UnmatchedNumberOfParameters(expected, expected - 1 + forms.len())),
+    }
+}
+
+pub struct ObjectBlock;
+
+impl ObjectBlock {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let name = rcenv_get!(env, "X")?;
+        if !matches!(name, Object::Symbol(_) | Object::Nil) {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        let body = body_forms(rcenv_get!(env, "Y")?);
+        let id = new_frame_id();
+        let frame = Env::inherit(&caller_env(env)).wrap();
+        frame.borrow_mut().insert_str(block_key(&name), Object::Integer(id as isize));
+        catch_exit(eval_body_values(body, &frame), id)
+    }
+}
+
+impl BuiltinFunc for ObjectBlock {
+    fn get_parameters(&self) -> &Params {
+        &LET_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "block"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+/// Leave the innermost lexically visible block with the name
+fn return_from(env: &RcEnv, name: Object, forms: Object) -> Result<Object, EvalError> {
+    let caller = caller_env(env);
+    let id = lexical_frame(&caller, &block_key(&name)).ok_or_else(|| EvalError::UnknownBlock(name.to_string()))?;
+    let values = result_values(&caller, forms, 2)?;
+    Err(EvalError::Exit(id, values))
+}
+
+pub struct ObjectReturnFrom;
+
+impl BuiltinFunc for ObjectReturnFrom {
+    fn get_parameters(&self) -> &Params {
+        &LET_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "return-from"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = rcenv_get!(env, "X")?;
+        let forms = rcenv_get!(env, "Y")?;
+        return_from(env, name, forms)
+    }
+}
+
+pub struct ObjectReturn;
+
+impl BuiltinFunc for ObjectReturn {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "return"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let forms = rcenv_get!(env, "X")?;
+        return_from(env, Object::Nil, forms)
+    }
+}
+
+/// Run statements as the body of a `tagbody`, in a frame of `env` where its tags are visible
+pub(crate) fn run_tagbody(statements: &[Object], env: &RcEnv) -> Result<(), EvalError> {
+    let id = new_frame_id();
+    let frame = Env::inherit(env).wrap();
+    for tag in statements.iter().filter(|s| is_tag(s)) {
+        frame.borrow_mut().insert_str(tag_key(tag), Object::Integer(id as isize));
+    }
+    let mut next = 0;
+    while let Some(statement) = statements.get(next) {
+        next += 1;
+        if is_tag(statement) {
+            continue;
+        }
+        match statement.clone().eval(&frame) {
+            // Resume after the tag that was jumped to
+            Err(EvalError::Exit(target, tag)) if target == id => {
+                next = statements.iter()
+                    .position(|s| is_tag(s) && tag.first().is_some_and(|t| eql(s, t)))
+                    .ok_or_else(|| EvalError::UnknownTag(Object::from(tag).to_string()))? + 1;
+            },
+            Err(e) => return Err(e),
+            Ok(_) => (),
+        }
+    }
+    Ok(())
+}
+
+pub struct ObjectTagbody;
+
+impl BuiltinFunc for ObjectTagbody {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "tagbody"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let statements = list_items(rcenv_get!(env, "X")?);
+        run_tagbody(&statements, &caller_env(env))?;
+        Ok(Object::Nil)
+    }
+}
+
+pub struct ObjectGo;
+
+impl BuiltinFunc for ObjectGo {
+    fn get_parameters(&self) -> &Params {
+        &GO_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "go"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let tag = rcenv_get!(env, "X")?;
+        let id = lexical_frame(&caller_env(env), &tag_key(&tag)).ok_or_else(|| EvalError::UnknownTag(tag.to_string()))?;
+        Err(EvalError::Exit(id, vec![tag]))
+    }
+}
+
+pub struct ObjectCatch;
+
+impl ObjectCatch {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let tag = rcenv_get!(env, "X")?;
+        let body = body_forms(rcenv_get!(env, "Y")?);
+        let id = new_frame_id();
+        let catchers = env.borrow().get_catchers();
+        let depth = catchers.borrow().len();
+        catchers.borrow_mut().push((tag, id));
+        let ret = eval_body_values(body, &caller).map_err(|e| signal_error(e, &caller));
+        catchers.borrow_mut().truncate(depth);
+        catch_exit(ret, id)
+    }
+}
+
+impl BuiltinFunc for ObjectCatch {
+    fn get_parameters(&self) -> &Params {
+        &CATCH_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "catch"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectThrow;
+
+impl BuiltinFunc for ObjectThrow {
+    fn get_parameters(&self) -> &Params {
+        &CATCH_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "throw"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let tag = rcenv_get!(env, "X")?;
+        let forms = rcenv_get!(env, "Y")?;
+        let values = result_values(&caller_env(env), forms, 2)?;
+        let catchers = env.borrow().get_catchers();
+        let id = catchers.borrow().iter().rev()
+            .find(|(t, _)| eql(t, &tag))
+            .map(|(_, id)| *id)
+            .ok_or_else(|| EvalError::NoCatchTag(tag.to_string()))?;
+        Err(EvalError::Exit(id, values))
+    }
+}
+
+pub struct ObjectUnwindProtect;
+
+impl ObjectUnwindProtect {
+    // Cleanup forms run whether the protected form returns, fails or transfers control
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let form = rcenv_get!(env, "X")?;
+        let cleanup = body_forms(rcenv_get!(env, "Y")?);
+        let ret = form.eval_values(&caller).map_err(|e| signal_error(e, &caller));
+        eval_body(cleanup, &caller)?;
+        ret
+    }
+}
+
+impl BuiltinFunc for ObjectUnwindProtect {
+    fn get_parameters(&self) -> &Params {
+        &LET_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "unwind-protect"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_let_forms() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(let ((x 1)) (let ((x 2) (y x)) y))"), Ok(Object::Integer(1)));
+        assert_eq!(run("(let* ((x 1) (y (+ x 1))) y)"), Ok(Object::Integer(2)));
+        assert_eq!(run("(progn (defvar *v* 1) (defun v () *v*) (let ((*v* 2)) (v)))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(v)"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_non_local_exits() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(defun f (x) (when x (return-from f 1)) 2) (f t)"), Ok(Object::Integer(1)));
+        assert_eq!(run("(let ((n 0)) (tagbody top (setq n (+ n 1)) (unless (eql n 3) (go top))) n)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(catch 'done (progn (throw 'done 3) 4))"), Ok(Object::Integer(3)));
+        assert_eq!(run("(defvar *c* 0) (catch 'x (unwind-protect (throw 'x 1) (setq *c* 5))) *c*"), Ok(Object::Integer(5)));
+        assert!(run("(funcall (block b (lambda () (return-from b 1))))").is_err());
+    }
+
+    #[test]
+    fn test_destructuring_bind() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(destructuring-bind (a (b . c) &optional (d 4)) '(1 (2 3)) (cons a (cons b (cons c (cons d nil)))))"), run("'(1 2 (3) 4)"));
+        assert_eq!(run("(destructuring-bind (a (&key x (y 5)) &rest r) '(1 (:x 2) 3) (cons a (cons x (cons y r))))"), run("'(1 2 5 3)"));
+        assert_eq!(run("(destructuring-bind (a (b c)) '(1 (2)) b)").unwrap_err().to_string(), "(2) does not match the lambda list (B C)");
+        assert!(run("(destructuring-bind (a) '(1 2) a)").is_err());
+    }
+}
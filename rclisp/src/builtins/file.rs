@@ -0,0 +1,402 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, Params, Parameter, UNARY_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env};
+use super::list::{sequence_keywords, keyword_value};
+use super::lambda::body_forms;
+use super::stream::stream_error;
+use super::types::boolean;
+use super::values::primary_value;
+
+use crate::env::{Capability, DynamicBindings};
+use crate::eval::{Eval, eval_body_values};
+use crate::package::designator_name;
+use crate::types::{Stream, Direction};
+
+builtin_parameters! {
+    static ref OPEN_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::rest("Y")]);
+    static ref WITH_OPEN_FILE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref RENAME_FILE_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y")]);
+}
+
+/// Fail unless the host lets Lisp code access its file system
+pub(crate) fn check_file_system(env: &RcEnv) -> Result<(), EvalError> {
+    match env.borrow().has_capability(Capability::FileSystem) {
+        true => Ok(()),
+        false => Err(EvalError::CapabilityDenied("File system".into())),
+    }
+}
+
+pub(crate) fn file_error<S: AsRef<str>>(path: S, e: io::Error) -> EvalError {
+    EvalError::FileError(path.as_ref().into(), e.to_string())
+}
+
+/// Namestring of a pathname designator, a file stream standing for the file it was opened on
+pub(crate) fn pathname(obj: Object) -> Result<String, EvalError> {
+    match obj {
+        Object::String(s) => Ok(s),
+        Object::Stream(s) => s.get_pathname().ok_or(EvalError::ParameterTypeMismatched),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Absolute name of an existing file with every link resolved
+pub(crate) fn truename<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    Ok(fs::canonicalize(path)?.to_string_lossy().into_owned())
+}
+
+fn keyword(keywords: &[(String, Object)], name: &str, default: &str) -> Result<String, EvalError> {
+    match keyword_value(keywords, name) {
+        Some(value) => designator_name(&value).ok_or(EvalError::ParameterTypeMismatched),
+        None => Ok(default.into()),
+    }
+}
+
+/// Open a file as `open` does with its keyword arguments, `NIL` when told to give up quietly
+fn open(path: &str, keywords: Object) -> Result<Object, EvalError> {
+    let keywords = sequence_keywords(keywords, &["DIRECTION", "IF-EXISTS", "IF-DOES-NOT-EXIST", "ELEMENT-TYPE", "EXTERNAL-FORMAT"])?;
+    let direction = keyword(&keywords, "DIRECTION", "INPUT")?;
+    let if_exists = keyword(&keywords, "IF-EXISTS", "ERROR")?;
+    let exists = Path::new(path).exists();
+    let if_does_not_exist = match direction.as_str() {
+        "OUTPUT" if !matches!(if_exists.as_str(), "OVERWRITE" | "APPEND") => keyword(&keywords, "IF-DOES-NOT-EXIST", "CREATE")?,
+        "PROBE" => keyword(&keywords, "IF-DOES-NOT-EXIST", "NIL")?,
+        _ => keyword(&keywords, "IF-DOES-NOT-EXIST", "ERROR")?,
+    };
+    let error = |reason: &str| EvalError::FileError(path.into(), reason.into());
+    if !exists {
+        match if_does_not_exist.as_str() {
+            "NIL" => return Ok(Object::Nil),
+            "ERROR" => return Err(error("file does not exist")),
+            "CREATE" => {},
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        }
+    } else if Path::new(path).is_dir() {
+        return Err(error("file is a directory"));
+    }
+    match direction.as_str() {
+        "INPUT" | "PROBE" => {
+            if !exists {
+                File::create(path).map_err(|e| file_error(path, e))?;
+            }
+            let file = File::open(path).map_err(|e| file_error(path, e))?;
+            let stream = Stream::input(Box::new(BufReader::new(file))).with_pathname(path);
+            // A probe only checks the file, its stream is already closed
+            if direction == "PROBE" {
+                stream.close().map_err(stream_error)?;
+            }
+            Ok(stream.into())
+        },
+        "OUTPUT" => {
+            let mut options = OpenOptions::new();
+            options.write(true).create(!exists);
+            if exists {
+                match if_exists.as_str() {
+                    "NIL" => return Ok(Object::Nil),
+                    "ERROR" => return Err(error("file already exists")),
+                    "SUPERSEDE" | "NEW-VERSION" | "RENAME" | "RENAME-AND-DELETE" => { options.truncate(true); },
+                    "APPEND" => { options.append(true); },
+                    "OVERWRITE" => {},
+                    _ => return Err(EvalError::ParameterTypeMismatched),
+                }
+            }
+            let file = options.open(path).map_err(|e| file_error(path, e))?;
+            Ok(Stream::output(Box::new(BufWriter::new(file))).with_pathname(path).into())
+        },
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Whether a name matches a pattern where `*` stands for any characters and `?` for one
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => wildcard_match(&pattern[1..], name) || (!name.is_empty() && wildcard_match(pattern, &name[1..])),
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Entries of a directory sorted by name, the empty path being the current directory
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let mut ret: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => vec![],
+    };
+    ret.sort();
+    ret
+}
+
+/// Files under `base` matching the remaining components, `**` matching any number of directories
+fn expand(base: PathBuf, components: &[String], ret: &mut Vec<PathBuf>) {
+    let Some((first, rest)) = components.split_first() else {
+        if base.exists() {
+            ret.push(base);
+        }
+        return;
+    };
+    if first == "**" {
+        expand(base.clone(), rest, ret);
+        for dir in entries(&base).into_iter().filter(|e| e.is_dir()) {
+            expand(dir, components, ret);
+        }
+    } else if first.contains(['*', '?']) {
+        let pattern: Vec<char> = first.chars().collect();
+        for entry in entries(&base) {
+            let name: Vec<char> = entry.file_name().unwrap_or_default().to_string_lossy().chars().collect();
+            if wildcard_match(&pattern, &name) {
+                expand(entry, rest, ret);
+            }
+        }
+    } else {
+        expand(base.join(first), rest, ret);
+    }
+}
+
+pub struct ObjectOpen;
+
+impl BuiltinFunc for ObjectOpen {
+    fn get_parameters(&self) -> &Params {
+        &OPEN_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "open"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        check_file_system(env)?;
+        open(&pathname(rcenv_get!(env, "X")?)?, rcenv_get!(env, "Y")?)
+    }
+}
+
+pub struct ObjectWithOpenFile;
+
+impl BuiltinFunc for ObjectWithOpenFile {
+    fn get_parameters(&self) -> &Params {
+        &WITH_OPEN_FILE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "with-open-file"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // The stream is closed however the body is left
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        check_file_system(env)?;
+        let caller = caller_env(env);
+        let spec = list_items(rcenv_get!(env, "X")?);
+        let (var, path) = match spec.as_slice() {
+            [var, path, ..] => (guard_obj!(var.clone(), Symbol)?, pathname(path.clone().eval(&caller)?)?),
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        };
+        let mut options = vec![];
+        for option in spec[2..].iter() {
+            options.push(option.clone().eval(&caller)?);
+        }
+        let stream = open(&path, Object::from(options))?;
+        let frame = Env::inherit(&caller).wrap();
+        let mut bindings = DynamicBindings::new(&frame);
+        bindings.bind(&frame, &var, stream.clone())?;
+        let ret = eval_body_values(body_forms(rcenv_get!(env, "Y")?), &frame);
+        drop(bindings);
+        if let Object::Stream(s) = stream {
+            s.close().map_err(stream_error)?;
+        }
+        ret
+    }
+}
+
+pub struct ObjectFileLength;
+
+impl BuiltinFunc for ObjectFileLength {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "file-length"
+    }
+
+    // Pending output is flushed first so that it is counted
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        check_file_system(env)?;
+        let stream = guard_obj!(rcenv_get!(env, "X")?, Stream)?;
+        let path = stream.get_pathname().ok_or(EvalError::ParameterTypeMismatched)?;
+        if stream.is_open() && stream.get_direction() == Direction::Output {
+            stream.flush().map_err(stream_error)?;
+        }
+        let metadata = fs::metadata(&path).map_err(|e| file_error(&path, e))?;
+        Ok(Object::Integer(metadata.len() as isize))
+    }
+}
+
+pub struct ObjectProbeFile;
+
+impl BuiltinFunc for ObjectProbeFile {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "probe-file"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        check_file_system(env)?;
+        match truename(pathname(rcenv_get!(env, "X")?)?) {
+            Ok(name) => Ok(Object::String(name)),
+            Err(_) => Ok(Object::Nil),
+        }
+    }
+}
+
+pub struct ObjectDirectory;
+
+impl BuiltinFunc for ObjectDirectory {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "directory"
+    }
+
+    // Truenames of the files matching the wildcards, in order
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        check_file_system(env)?;
+        let path = pathname(rcenv_get!(env, "X")?)?;
+        let components: Vec<String> = Path::new(&path).components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let mut found = vec![];
+        expand(PathBuf::new(), &components, &mut found);
+        let mut names: Vec<String> = found.iter().filter_map(|p| truename(p).ok()).collect();
+        names.sort();
+        names.dedup();
+        Ok(Object::from(names.into_iter().map(Object::String).collect::<Vec<_>>()))
+    }
+}
+
+pub struct ObjectDeleteFile;
+
+impl BuiltinFunc for ObjectDeleteFile {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "delete-file"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        check_file_system(env)?;
+        let path = pathname(rcenv_get!(env, "X")?)?;
+        fs::remove_file(&path).map_err(|e| file_error(&path, e))?;
+        Ok(Object::T)
+    }
+}
+
+pub struct ObjectRenameFile;
+
+impl BuiltinFunc for ObjectRenameFile {
+    fn get_parameters(&self) -> &Params {
+        &RENAME_FILE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "rename-file"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // The new name, then the truenames of the file before and after
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        check_file_system(env)?;
+        let from = pathname(rcenv_get!(env, "X")?)?;
+        let to = pathname(rcenv_get!(env, "Y")?)?;
+        let old = truename(&from).map_err(|e| file_error(&from, e))?;
+        fs::rename(&from, &to).map_err(|e| file_error(&from, e))?;
+        let new = truename(&to).map_err(|e| file_error(&to, e))?;
+        Ok(vec![Object::String(to), Object::String(old), Object::String(new)])
+    }
+}
+
+pub struct ObjectEnsureDirectoriesExist;
+
+impl BuiltinFunc for ObjectEnsureDirectoriesExist {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "ensure-directories-exist"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // Names ending with a slash are directories themselves, otherwise the file's directory is created
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        check_file_system(env)?;
+        let path = pathname(rcenv_get!(env, "X")?)?;
+        let dir = match path.ends_with('/') {
+            true => Path::new(&path),
+            false => Path::new(&path).parent().unwrap_or(Path::new("")),
+        };
+        let created = !dir.as_os_str().is_empty() && !dir.exists();
+        if created {
+            fs::create_dir_all(dir).map_err(|e| file_error(&path, e))?;
+        }
+        Ok(vec![Object::String(path.clone()), boolean(created)])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env::temp_dir;
+    use std::fs;
+
+    use crate::{Object, Capability};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_files() {
+        let (env, run) = interpreter();
+        let dir = temp_dir().join(format!("rclisp-test-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.to_string_lossy().into_owned();
+        let run = |source: &str| run(&source.replace("$D", &root));
+        assert!(run("(probe-file \"$D\")").is_err());
+        env.borrow_mut().grant_capability(Capability::FileSystem);
+        let string = |s: &str| Ok(Object::String(s.into()));
+        assert_eq!(run("(nth-value 1 (ensure-directories-exist \"$D/a/b.txt\"))"), Ok(Object::T));
+        run("(with-open-file (s \"$D/a/b.txt\" :direction :output) (write-line \"one\" s) (write-string \"two\" s))").unwrap();
+        assert!(run("(open \"$D/a/b.txt\" :direction :output)").is_err());
+        assert_eq!(run("(open \"$D/a/b.txt\" :direction :output :if-exists nil)"), Ok(Object::Nil));
+        run("(with-open-file (s \"$D/a/b.txt\" :direction :output :if-exists :append) (write-line \"!\" s))").unwrap();
+        assert_eq!(run("(with-open-file (s \"$D/a/b.txt\") (cons (read-line s) (multiple-value-list (read-line s))))"), run("'(\"one\" \"two!\" nil)"));
+        assert_eq!(run("(with-open-file (s \"$D/a/b.txt\") (file-length s))"), Ok(Object::Integer(9)));
+        assert_eq!(run("(open \"$D/missing\" :if-does-not-exist nil)"), Ok(Object::Nil));
+        assert!(run("(handler-case (open \"$D/missing\") (file-error (e) (file-error-pathname e)))").is_ok());
+        run("(rename-file \"$D/a/b.txt\" \"$D/a/c.lisp\")").unwrap();
+        assert_eq!(run("(probe-file \"$D/a/b.txt\")"), Ok(Object::Nil));
+        let truename = fs::canonicalize(dir.join("a/c.lisp")).unwrap().to_string_lossy().into_owned();
+        assert_eq!(run("(car (directory \"$D/**/*.lisp\"))"), string(&truename));
+        assert_eq!(run("(directory \"$D/a/*.txt\")"), Ok(Object::Nil));
+        assert_eq!(run("(delete-file \"$D/a/c.lisp\")"), Ok(Object::T));
+        env.borrow_mut().revoke_capability(Capability::FileSystem);
+        assert!(run("(probe-file \"$D\")").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,946 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, rcenv_get, guard_obj, list_items};
+use super::print::print_string;
+use super::stream::{output_stream, write_str};
+
+builtin_parameters! {
+    static ref FORMAT_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y"), Parameter::rest("Z")]);
+}
+
+fn format_error<S: AsRef<str>>(message: S) -> EvalError {
+    EvalError::FormatError(message.as_ref().to_string())
+}
+
+/// Prefix parameter of a directive as written in the control string
+#[derive(Clone, Debug)]
+enum Param {
+    Default,
+    Integer(isize),
+    Character(char),
+    Argument, // V, taken from the next argument
+    Count,    // #, the number of remaining arguments
+}
+
+/// Prefix parameter once the arguments it refers to have been taken
+#[derive(Clone, Copy, Debug)]
+enum Value {
+    Integer(isize),
+    Character(char),
+}
+
+#[derive(Clone, Debug)]
+struct Spec {
+    name: char,
+    params: Vec<Param>,
+    colon: bool,
+    at: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Text(String),
+    Directive(Spec),
+    Conditional(Spec, Vec<Vec<Node>>, bool), // (Spec, Clauses, Whether the last clause is the default)
+    Iteration(Spec, Vec<Node>, bool),        // (Spec, Body, Whether the body runs at least once)
+    Case(Spec, Vec<Node>),
+    Justification(Spec, Vec<Vec<Node>>),     // (Spec, Segments)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(control: &str) -> Self {
+        Parser { chars: control.chars().collect(), pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn param(&mut self) -> Result<Option<Param>, EvalError> {
+        let param = match self.peek() {
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' => {
+                let mut digits = String::from(c);
+                self.pos += 1;
+                while let Some(d) = self.peek().filter(char::is_ascii_digit) {
+                    digits.push(d);
+                    self.pos += 1;
+                }
+                Param::Integer(digits.parse().map_err(|_| format_error(format!("Invalid parameter {}", digits)))?)
+            },
+            Some('\'') => {
+                self.pos += 1;
+                Param::Character(self.next().ok_or_else(|| format_error("Unterminated directive"))?)
+            },
+            Some('v' | 'V') => {
+                self.pos += 1;
+                Param::Argument
+            },
+            Some('#') => {
+                self.pos += 1;
+                Param::Count
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(param))
+    }
+
+    /// Parameters, modifiers and name of a directive, after its tilde
+    fn spec(&mut self) -> Result<Spec, EvalError> {
+        let mut params = vec![];
+        loop {
+            let param = self.param()?;
+            if self.peek() == Some(',') {
+                self.pos += 1;
+                params.push(param.unwrap_or(Param::Default));
+            } else {
+                params.extend(param);
+                break;
+            }
+        }
+        let (mut colon, mut at) = (false, false);
+        loop {
+            match self.peek() {
+                Some(':') => colon = true,
+                Some('@') => at = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let name = self.next().ok_or_else(|| format_error("Unterminated directive"))?;
+        Ok(Spec { name: name.to_ascii_uppercase(), params, colon, at })
+    }
+
+    /// Nodes up to one of the closing directives in `ends`, which is returned, or up to
+    /// the end of the control string if there are none
+    fn nodes(&mut self, ends: &[char]) -> Result<(Vec<Node>, Option<Spec>), EvalError> {
+        let mut nodes = vec![];
+        let mut text = String::new();
+        while let Some(c) = self.next() {
+            if c != '~' {
+                text.push(c);
+                continue;
+            }
+            let spec = self.spec()?;
+            if spec.name == '\n' {
+                // A tilde before a newline ignores it and the whitespace following it
+                if spec.at {
+                    text.push('\n');
+                }
+                while !spec.colon && self.peek().is_some_and(|c| c != '\n' && c.is_whitespace()) {
+                    self.pos += 1;
+                }
+                continue;
+            }
+            if !text.is_empty() {
+                nodes.push(Node::Text(std::mem::take(&mut text)));
+            }
+            if ends.contains(&spec.name) {
+                return Ok((nodes, Some(spec)));
+            }
+            let node = match spec.name {
+                '[' => self.conditional(spec)?,
+                '{' => {
+                    let (body, end) = self.nodes(&['}'])?;
+                    let once = end.is_some_and(|e| e.colon);
+                    Node::Iteration(spec, body, once)
+                },
+                '(' => Node::Case(spec, self.nodes(&[')'])?.0),
+                '<' => {
+                    let mut segments = vec![];
+                    loop {
+                        let (segment, end) = self.nodes(&[';', '>'])?;
+                        segments.push(segment);
+                        if end.is_some_and(|e| e.name == '>') {
+                            break;
+                        }
+                    }
+                    Node::Justification(spec, segments)
+                },
+                ']' | '}' | ')' | '>' | ';' => return Err(format_error(format!("Unmatched directive ~{}", spec.name))),
+                _ => Node::Directive(spec),
+            };
+            nodes.push(node);
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+        match ends.last() {
+            Some(end) => Err(format_error(format!("Missing directive ~{}", end))),
+            None => Ok((nodes, None)),
+        }
+    }
+
+    fn conditional(&mut self, spec: Spec) -> Result<Node, EvalError> {
+        let mut clauses = vec![];
+        let mut default = false;
+        loop {
+            let (clause, end) = self.nodes(&[';', ']'])?;
+            clauses.push(clause);
+            match end {
+                Some(end) if end.name == ';' => default = end.colon,
+                _ => break,
+            }
+        }
+        Ok(Node::Conditional(spec, clauses, default))
+    }
+}
+
+fn parse(control: &str) -> Result<Vec<Node>, EvalError> {
+    Ok(Parser::new(control).nodes(&[])?.0)
+}
+
+/// Arguments being consumed by the directives
+struct Args {
+    items: Vec<Object>,
+    pos: usize,
+    // Sublists left after this one in a `~:{` iteration, which `~:^` looks at
+    outer_remaining: usize,
+}
+
+impl Args {
+    fn new(items: Vec<Object>) -> Self {
+        Args { items, pos: 0, outer_remaining: 0 }
+    }
+
+    fn next(&mut self) -> Result<Object, EvalError> {
+        let arg = self.items.get(self.pos).cloned().ok_or_else(|| format_error("No more arguments"))?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    fn remaining(&self) -> usize {
+        self.items.len().saturating_sub(self.pos)
+    }
+
+    fn goto(&mut self, pos: isize) -> Result<(), EvalError> {
+        match usize::try_from(pos) {
+            Ok(pos) if pos <= self.items.len() => {
+                self.pos = pos;
+                Ok(())
+            },
+            _ => Err(format_error("Argument index out of range")),
+        }
+    }
+}
+
+/// Whether the directives went through to the end or were cut short by `~^`
+enum Flow {
+    Continue,
+    Escape(bool), // Whether it was `~:^`, which leaves the whole iteration
+}
+
+fn resolve(params: &[Param], args: &mut Args) -> Result<Vec<Option<Value>>, EvalError> {
+    let mut ret = vec![];
+    for param in params {
+        ret.push(match param {
+            Param::Default => None,
+            Param::Integer(n) => Some(Value::Integer(*n)),
+            Param::Character(c) => Some(Value::Character(*c)),
+            Param::Count => Some(Value::Integer(args.remaining() as isize)),
+            Param::Argument => match args.next()? {
+                Object::Nil => None,
+                Object::Integer(n) => Some(Value::Integer(n)),
+                Object::String(s) if s.chars().count() == 1 => s.chars().next().map(Value::Character),
+                _ => return Err(format_error("Parameter must be an integer or a character")),
+            },
+        });
+    }
+    Ok(ret)
+}
+
+fn integer(params: &[Option<Value>], i: usize, default: isize) -> Result<isize, EvalError> {
+    match params.get(i) {
+        Some(Some(Value::Integer(n))) => Ok(*n),
+        Some(Some(Value::Character(_))) => Err(format_error("Parameter must be an integer")),
+        _ => Ok(default),
+    }
+}
+
+fn character(params: &[Option<Value>], i: usize, default: char) -> Result<char, EvalError> {
+    match params.get(i) {
+        Some(Some(Value::Character(c))) => Ok(*c),
+        Some(Some(Value::Integer(_))) => Err(format_error("Parameter must be a character")),
+        _ => Ok(default),
+    }
+}
+
+fn column(out: &str) -> usize {
+    out.rsplit('\n').next().map_or(0, |line| line.chars().count())
+}
+
+/// Pad text to at least `mincol` columns, adding `minpad` padding characters first and
+/// then `colinc` of them at a time, on the left with `left`
+fn pad(text: String, mincol: isize, colinc: isize, minpad: isize, padchar: char, left: bool) -> String {
+    let len = text.chars().count() as isize;
+    let mut padding = minpad.max(0);
+    while len + padding < mincol {
+        padding += colinc.max(1);
+    }
+    let padding: String = std::iter::repeat_n(padchar, padding as usize).collect();
+    if left { padding + &text } else { text + &padding }
+}
+
+/// Digits of an integer in a radix, grouped with `comma` every `interval` digits if given
+fn integer_string(n: isize, radix: u32, comma: Option<(char, usize)>, sign: bool) -> String {
+    let mut digits = vec![];
+    let mut m = n.unsigned_abs();
+    loop {
+        digits.push(std::char::from_digit((m % radix as usize) as u32, radix).unwrap_or('?').to_ascii_uppercase());
+        m /= radix as usize;
+        if m == 0 {
+            break;
+        }
+    }
+    let mut ret = String::new();
+    if n < 0 {
+        ret.push('-');
+    } else if sign {
+        ret.push('+');
+    }
+    for (i, d) in digits.iter().enumerate().rev() {
+        ret.push(*d);
+        if let Some((c, interval)) = comma {
+            if i > 0 && interval > 0 && i % interval == 0 {
+                ret.push(c);
+            }
+        }
+    }
+    ret
+}
+
+const ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+const SCALES: [&str; 7] = ["", " thousand", " million", " billion", " trillion", " quadrillion", " quintillion"];
+
+fn below_thousand(n: usize) -> String {
+    let mut words = vec![];
+    if n >= 100 {
+        words.push(format!("{} hundred", ONES[n / 100]));
+    }
+    match n % 100 {
+        0 => (),
+        r if r < 20 => words.push(ONES[r].to_string()),
+        r if r % 10 == 0 => words.push(TENS[r / 10].to_string()),
+        r => words.push(format!("{}-{}", TENS[r / 10], ONES[r % 10])),
+    }
+    words.join(" ")
+}
+
+/// English name of an integer, as printed by `~R`
+fn cardinal(n: isize) -> String {
+    if n == 0 {
+        return ONES[0].into();
+    }
+    let mut groups = vec![];
+    let mut m = n.unsigned_abs();
+    while m > 0 {
+        groups.push(m % 1000);
+        m /= 1000;
+    }
+    let words: Vec<String> = groups.iter().enumerate().rev()
+        .filter(|(_, g)| **g > 0)
+        .map(|(i, g)| format!("{}{}", below_thousand(*g), SCALES[i]))
+        .collect();
+    let words = words.join(" ");
+    if n < 0 { format!("negative {}", words) } else { words }
+}
+
+/// English ordinal of an integer, as printed by `~:R`
+fn ordinal(n: isize) -> String {
+    let words = cardinal(n);
+    let split = words.rfind([' ', '-']).map_or(0, |i| i + 1);
+    let (head, last) = words.split_at(split);
+    let last = match last {
+        "one" => "first".into(),
+        "two" => "second".into(),
+        "three" => "third".into(),
+        "five" => "fifth".into(),
+        "eight" => "eighth".into(),
+        "nine" => "ninth".into(),
+        "twelve" => "twelfth".into(),
+        w if w.ends_with('y') => format!("{}ieth", &w[..w.len() - 1]),
+        w => format!("{}th", w),
+    };
+    format!("{}{}", head, last)
+}
+
+/// Roman numeral of an integer, `old` ones never subtract
+fn roman(n: isize, old: bool) -> Result<String, EvalError> {
+    let numerals: &[(isize, &str)] = if old {
+        &[(1000, "M"), (500, "D"), (100, "C"), (50, "L"), (10, "X"), (5, "V"), (1, "I")]
+    } else {
+        &[(1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+          (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I")]
+    };
+    if n < 1 || n > if old { 4999 } else { 3999 } {
+        return Err(format_error(format!("{} cannot be written in Roman numerals", n)));
+    }
+    let mut ret = String::new();
+    let mut m = n;
+    for (value, numeral) in numerals {
+        while m >= *value {
+            ret.push_str(numeral);
+            m -= value;
+        }
+    }
+    Ok(ret)
+}
+
+fn float_value(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::Integer(n) => Some(*n as f64),
+        Object::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Shortest decimal digits of a float, always with a decimal point
+fn float_string(f: f64) -> String {
+    let s = f.to_string();
+    if s.contains(['.', 'e', 'i', 'N']) { s } else { format!("{}.0", s) }
+}
+
+/// Fixed format floating point, `~w,d,k,overflowchar,padcharF`
+fn fixed(f: f64, params: &[Option<Value>], sign: bool) -> Result<String, EvalError> {
+    let width = params.first().copied().flatten().map(|_| integer(params, 0, 0)).transpose()?;
+    let digits = params.get(1).copied().flatten().map(|_| integer(params, 1, 0)).transpose()?;
+    let scale = integer(params, 2, 0)?;
+    let f = f * 10f64.powi(scale as i32);
+    let sign = if f.is_sign_negative() { "-" } else if sign { "+" } else { "" };
+    let f = f.abs();
+    let mut text = match (width, digits) {
+        (_, Some(d)) => format!("{:.*}", d.max(0) as usize, f),
+        (Some(w), None) => {
+            let shortest = float_string(f);
+            let whole = format!("{:.0}", f.trunc()).len() + 1 + sign.len();
+            let available = (w.max(0) as usize).saturating_sub(whole).max(1);
+            match shortest.split_once('.') {
+                Some((_, decimals)) if decimals.len() > available => format!("{:.*}", available, f),
+                _ => shortest,
+            }
+        },
+        (None, None) => float_string(f),
+    };
+    text.insert_str(0, sign);
+    overflow_or_pad(text, width, params, 3)
+}
+
+/// Exponential format floating point, `~w,d,e,k,overflowchar,padchar,exptcharE`
+fn exponential(f: f64, params: &[Option<Value>], sign: bool) -> Result<String, EvalError> {
+    let width = params.first().copied().flatten().map(|_| integer(params, 0, 0)).transpose()?;
+    let digits = params.get(1).copied().flatten().map(|_| integer(params, 1, 0)).transpose()?;
+    let exponent_digits = integer(params, 2, 1)?.max(1) as usize;
+    let exponent_char = character(params, 6, 'e')?;
+    let text = match digits {
+        Some(d) => format!("{:.*e}", d.max(0) as usize, f.abs()),
+        None => format!("{:e}", f.abs()),
+    };
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let mantissa = if mantissa.contains('.') { mantissa.to_string() } else { format!("{}.0", mantissa) };
+    let exponent: isize = exponent.parse().unwrap_or(0);
+    let sign = if f.is_sign_negative() { "-" } else if sign { "+" } else { "" };
+    let text = format!("{}{}{}{}{:0>width$}", sign, mantissa, exponent_char,
+        if exponent < 0 { '-' } else { '+' }, exponent.abs(), width = exponent_digits);
+    overflow_or_pad(text, width, params, 4)
+}
+
+/// Pad a number on the left to its width, or fill the width with the overflow character
+/// if it does not fit and there is one. `overflow` is the index of that parameter, the
+/// padding character follows it.
+fn overflow_or_pad(text: String, width: Option<isize>, params: &[Option<Value>], overflow: usize) -> Result<String, EvalError> {
+    let Some(width) = width else {
+        return Ok(text);
+    };
+    let len = text.chars().count() as isize;
+    if len > width {
+        if let Some(Some(Value::Character(c))) = params.get(overflow) {
+            return Ok(std::iter::repeat_n(*c, width.max(0) as usize).collect());
+        }
+        return Ok(text);
+    }
+    Ok(pad(text, width, 1, 0, character(params, overflow + 1, ' ')?, true))
+}
+
+/// Monetary format, `~d,n,w,padchar$`
+fn monetary(f: f64, params: &[Option<Value>], colon: bool, at: bool) -> Result<String, EvalError> {
+    let digits = integer(params, 0, 2)?.max(0) as usize;
+    let whole_digits = integer(params, 1, 1)?.max(0) as usize;
+    let width = integer(params, 2, 0)?;
+    let padchar = character(params, 3, ' ')?;
+    let sign = if f.is_sign_negative() { "-" } else if at { "+" } else { "" };
+    let text = format!("{:.*}", digits, f.abs());
+    let whole = text.split('.').next().unwrap_or("").len();
+    let number = format!("{}{}", "0".repeat(whole_digits.saturating_sub(whole)), text);
+    if colon {
+        // The sign comes before the padding
+        Ok(format!("{}{}", sign, pad(number, width - sign.len() as isize, 1, 0, padchar, true)))
+    } else {
+        Ok(pad(format!("{}{}", sign, number), width, 1, 0, padchar, true))
+    }
+}
+
+fn character_name(c: char) -> String {
+    match c {
+        ' ' => "Space".into(),
+        '\n' => "Newline".into(),
+        '\t' => "Tab".into(),
+        '\r' => "Return".into(),
+        c => c.to_string(),
+    }
+}
+
+/// Text with the case conversion of `~( ~)`
+fn convert_case(text: &str, colon: bool, at: bool) -> String {
+    match (colon, at) {
+        (true, true) => text.to_uppercase(),
+        (false, false) => text.to_lowercase(),
+        (capitalize_all, _) => {
+            let mut ret = String::new();
+            let mut in_word = false;
+            let mut first_word = true;
+            for c in text.chars() {
+                if c.is_alphanumeric() {
+                    if !in_word && (capitalize_all || first_word) {
+                        ret.extend(c.to_uppercase());
+                    } else {
+                        ret.extend(c.to_lowercase());
+                    }
+                    if !in_word {
+                        first_word = false;
+                    }
+                    in_word = true;
+                } else {
+                    ret.push(c);
+                    in_word = false;
+                }
+            }
+            ret
+        },
+    }
+}
+
+/// Segments of `~< ~>` spread over the width, `colon` and `at` add padding before the
+/// first and after the last segment. A single segment is justified to the right.
+fn justify(segments: Vec<String>, params: &[Option<Value>], colon: bool, at: bool) -> Result<String, EvalError> {
+    let mincol = integer(params, 0, 0)?;
+    let colinc = integer(params, 1, 1)?.max(1);
+    let minpad = integer(params, 2, 0)?.max(0);
+    let padchar = character(params, 3, ' ')?;
+    let before = colon || (segments.len() == 1 && !at);
+    let gaps = segments.len().saturating_sub(1) + before as usize + at as usize;
+    let text_len: isize = segments.iter().map(|s| s.chars().count() as isize).sum();
+    let mut width = mincol;
+    while width < text_len + minpad * gaps as isize {
+        width += colinc;
+    }
+    let padding = (width - text_len).max(0) as usize;
+    let gap = |i: usize| {
+        let n = padding.checked_div(gaps).map_or(0, |q| q + usize::from(i >= gaps - padding % gaps));
+        std::iter::repeat_n(padchar, n).collect::<String>()
+    };
+    let mut ret = String::new();
+    let mut i = 0;
+    if before {
+        ret.push_str(&gap(i));
+        i += 1;
+    }
+    let count = segments.len();
+    for (j, segment) in segments.into_iter().enumerate() {
+        ret.push_str(&segment);
+        if j + 1 < count || at {
+            ret.push_str(&gap(i));
+            i += 1;
+        }
+    }
+    Ok(ret)
+}
+
+fn run(env: &RcEnv, nodes: &[Node], args: &mut Args, out: &mut String) -> Result<Flow, EvalError> {
+    for node in nodes {
+        let flow = match node {
+            Node::Text(text) => {
+                out.push_str(text);
+                Flow::Continue
+            },
+            Node::Directive(spec) => directive(env, spec, args, out)?,
+            Node::Conditional(spec, clauses, default) => conditional(env, spec, clauses, *default, args, out)?,
+            Node::Iteration(spec, body, once) => {
+                iteration(env, spec, body, *once, args, out)?;
+                Flow::Continue
+            },
+            Node::Case(spec, body) => {
+                let start = out.len();
+                let flow = run(env, body, args, out)?;
+                let converted = convert_case(&out[start..], spec.colon, spec.at);
+                out.replace_range(start.., &converted);
+                flow
+            },
+            Node::Justification(spec, segments) => {
+                let params = resolve(&spec.params, args)?;
+                let mut texts = vec![];
+                for segment in segments {
+                    let start = out.len();
+                    let flow = run(env, segment, args, out)?;
+                    let text = out.split_off(start);
+                    if let Flow::Escape(_) = flow {
+                        break;
+                    }
+                    texts.push(text);
+                }
+                out.push_str(&justify(texts, &params, spec.colon, spec.at)?);
+                Flow::Continue
+            },
+        };
+        if let Flow::Escape(_) = flow {
+            return Ok(flow);
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn directive(env: &RcEnv, spec: &Spec, args: &mut Args, out: &mut String) -> Result<Flow, EvalError> {
+    let params = resolve(&spec.params, args)?;
+    let repeat = |c: char, out: &mut String| -> Result<(), EvalError> {
+        out.extend(std::iter::repeat_n(c, integer(&params, 0, 1)?.max(0) as usize));
+        Ok(())
+    };
+    match spec.name {
+        'A' | 'S' => {
+            let arg = args.next()?;
+            let text = match (&arg, spec.colon) {
+                (Object::Nil, true) => "()".into(),
+                _ => print_string(env, &arg, spec.name == 'S')?,
+            };
+            let mincol = integer(&params, 0, 0)?;
+            let colinc = integer(&params, 1, 1)?;
+            let minpad = integer(&params, 2, 0)?;
+            out.push_str(&pad(text, mincol, colinc, minpad, character(&params, 3, ' ')?, spec.at));
+        },
+        'D' | 'B' | 'O' | 'X' | 'R' => {
+            let (radix, params) = match spec.name {
+                'D' => (Some(10), &params[..]),
+                'B' => (Some(2), &params[..]),
+                'O' => (Some(8), &params[..]),
+                'X' => (Some(16), &params[..]),
+                _ => match params.first() {
+                    Some(Some(_)) => (Some(integer(&params, 0, 10)?), &params[1..]),
+                    _ => (None, &params[params.len().min(1)..]),
+                },
+            };
+            let arg = args.next()?;
+            let text = match (radix, arg) {
+                (Some(radix), Object::Integer(n)) => {
+                    if !(2..=36).contains(&radix) {
+                        return Err(format_error(format!("Invalid radix {}", radix)));
+                    }
+                    let comma = spec.colon.then(|| -> Result<_, EvalError> {
+                        Ok((character(params, 2, ',')?, integer(params, 3, 3)?.max(1) as usize))
+                    }).transpose()?;
+                    integer_string(n, radix as u32, comma, spec.at)
+                },
+                (None, Object::Integer(n)) => match (spec.colon, spec.at) {
+                    (false, false) => cardinal(n),
+                    (true, false) => ordinal(n),
+                    (at_old, true) => roman(n, at_old)?,
+                },
+                (_, arg) => print_string(env, &arg, false)?,
+            };
+            out.push_str(&pad(text, integer(params, 0, 0)?, 1, 0, character(params, 1, ' ')?, true));
+        },
+        'F' | 'E' | '$' => {
+            let arg = args.next()?;
+            let Some(f) = float_value(&arg) else {
+                out.push_str(&print_string(env, &arg, false)?);
+                return Ok(Flow::Continue);
+            };
+            out.push_str(&match spec.name {
+                'F' => fixed(f, &params, spec.at)?,
+                'E' => exponential(f, &params, spec.at)?,
+                _ => monetary(f, &params, spec.colon, spec.at)?,
+            });
+        },
+        'C' => {
+            let c = match args.next()? {
+                Object::String(s) if s.chars().count() == 1 => s.chars().next().unwrap_or(' '),
+                _ => return Err(EvalError::ParameterTypeMismatched),
+            };
+            match (spec.colon, spec.at) {
+                (true, _) => out.push_str(&character_name(c)),
+                (false, true) => out.push_str(&format!("#\\{}", character_name(c))),
+                (false, false) => out.push(c),
+            }
+        },
+        'P' => {
+            if spec.colon {
+                args.goto(args.pos as isize - 1)?;
+            }
+            let plural = args.next()? != Object::Integer(1);
+            match (spec.at, plural) {
+                (true, true) => out.push_str("ies"),
+                (true, false) => out.push('y'),
+                (false, true) => out.push('s'),
+                (false, false) => (),
+            }
+        },
+        '%' => repeat('\n', out)?,
+        '&' => {
+            let n = integer(&params, 0, 1)?;
+            if n > 0 && !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.extend(std::iter::repeat_n('\n', (n - 1).max(0) as usize));
+        },
+        '~' => repeat('~', out)?,
+        '|' => repeat('\x0c', out)?,
+        'T' => {
+            let current = column(out) as isize;
+            let (first, colinc) = (integer(&params, 0, 1)?, integer(&params, 1, 1)?);
+            let target = if spec.at {
+                let target = current + first.max(0);
+                if colinc > 0 { (target + colinc - 1) / colinc * colinc } else { target }
+            } else if current < first {
+                first
+            } else if colinc > 0 {
+                first + ((current - first) / colinc + 1) * colinc
+            } else {
+                current
+            };
+            out.extend(std::iter::repeat_n(' ', (target - current).max(0) as usize));
+        },
+        '*' => {
+            let pos = args.pos as isize;
+            match (spec.colon, spec.at) {
+                (_, true) => args.goto(integer(&params, 0, 0)?)?,
+                (true, false) => args.goto(pos - integer(&params, 0, 1)?)?,
+                (false, false) => args.goto(pos + integer(&params, 0, 1)?)?,
+            }
+        },
+        '^' => {
+            let values = params.iter().map(|p| match p {
+                Some(Value::Integer(n)) => Ok(*n),
+                Some(Value::Character(c)) => Ok(*c as isize),
+                None => Err(format_error("Missing parameter of ~^")),
+            }).collect::<Result<Vec<_>, _>>()?;
+            let escape = match values.as_slice() {
+                [] if spec.colon => args.outer_remaining == 0,
+                [] => args.remaining() == 0,
+                [a] => *a == 0,
+                [a, b] => a == b,
+                [a, b, c, ..] => a <= b && b <= c,
+            };
+            if escape {
+                return Ok(Flow::Escape(spec.colon));
+            }
+        },
+        d => return Err(format_error(format!("Unknown directive ~{}", d))),
+    }
+    Ok(Flow::Continue)
+}
+
+fn conditional(env: &RcEnv, spec: &Spec, clauses: &[Vec<Node>], default: bool, args: &mut Args, out: &mut String) -> Result<Flow, EvalError> {
+    let params = resolve(&spec.params, args)?;
+    let clause = if spec.at {
+        // The argument is left for the clause when it is true
+        if args.next()? == Object::Nil {
+            return Ok(Flow::Continue);
+        }
+        args.goto(args.pos as isize - 1)?;
+        clauses.first()
+    } else if spec.colon {
+        clauses.get(if args.next()? == Object::Nil { 0 } else { 1 })
+    } else {
+        let index = match params.first() {
+            Some(Some(_)) => integer(&params, 0, 0)?,
+            _ => guard_obj!(args.next()?, Integer)?,
+        };
+        match usize::try_from(index).ok().and_then(|i| clauses.get(i)) {
+            Some(clause) if !default || index < clauses.len() as isize - 1 => Some(clause),
+            _ if default => clauses.last(),
+            _ => None,
+        }
+    };
+    match clause {
+        Some(clause) => run(env, clause, args, out),
+        None => Ok(Flow::Continue),
+    }
+}
+
+fn iteration(env: &RcEnv, spec: &Spec, body: &[Node], once: bool, args: &mut Args, out: &mut String) -> Result<(), EvalError> {
+    let params = resolve(&spec.params, args)?;
+    let limit = integer(&params, 0, isize::MAX)?;
+    // An empty body takes the control string from the arguments
+    let parsed;
+    let body = if body.is_empty() {
+        parsed = parse(&guard_obj!(args.next()?, String)?)?;
+        &parsed[..]
+    } else {
+        body
+    };
+    let mut count = 0;
+    if spec.colon {
+        let mut sublists = if spec.at {
+            let rest = args.items[args.pos..].to_vec();
+            args.pos = args.items.len();
+            Args::new(rest)
+        } else {
+            Args::new(list_items(args.next()?))
+        };
+        while count < limit && (sublists.remaining() > 0 || (once && count == 0)) {
+            let items = if sublists.remaining() > 0 { list_items(sublists.next()?) } else { vec![] };
+            let mut sub = Args::new(items);
+            sub.outer_remaining = sublists.remaining();
+            count += 1;
+            if let Flow::Escape(true) = run(env, body, &mut sub, out)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+    let mut own;
+    let items = if spec.at {
+        args
+    } else {
+        own = Args::new(list_items(args.next()?));
+        &mut own
+    };
+    while count < limit && (items.remaining() > 0 || (once && count == 0)) {
+        let pos = items.pos;
+        count += 1;
+        if let Flow::Escape(_) = run(env, body, items, out)? {
+            break;
+        }
+        // A body that takes no arguments would go on forever
+        if items.pos == pos {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Expand a format control string with its arguments
+pub fn format(env: &RcEnv, control: &str, args: &[Object]) -> Result<String, EvalError> {
+    format_at(env, control, args, 0)
+}
+
+/// Expand a format control string for output starting at a column, which `~&` and `~T`
+/// take into account
+fn format_at(env: &RcEnv, control: &str, args: &[Object], column: usize) -> Result<String, EvalError> {
+    let nodes = parse(control)?;
+    // Spaces stand for the text already on the line
+    let mut out = " ".repeat(column);
+    run(env, &nodes, &mut Args::new(args.to_vec()), &mut out)?;
+    Ok(out.split_off(column))
+}
+
+pub struct ObjectFormat;
+
+impl BuiltinFunc for ObjectFormat {
+    fn get_parameters(&self) -> &Params {
+        &FORMAT_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "format"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let destination = rcenv_get!(env, "X")?;
+        let control = guard_obj!(rcenv_get!(env, "Y")?, String)?;
+        let args = list_items(rcenv_get!(env, "Z")?);
+        if destination == Object::Nil {
+            return Ok(Object::String(format(env, &control, &args)?));
+        }
+        let stream = output_stream(destination, env)?;
+        write_str(&stream, &format_at(env, &control, &args, stream.get_column())?)?;
+        Ok(Object::Nil)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format, Object};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_format_basic() {
+        let (env, _) = interpreter();
+        let format = |control: &str, args: &[Object]| format(&env, control, args);
+        let args = vec![Object::String("a".into()), Object::String("b".into()), Object::Integer(3)];
+        assert_eq!(format("~a ~s~&~d~%~~", &args), Ok("a \"b\"\n3\n~".into()));
+        assert!(format("~a", &[]).is_err());
+    }
+
+    #[test]
+    fn test_format_directives() {
+        let (env, _) = interpreter();
+        let format = |control: &str, args: &[Object]| format(&env, control, args);
+        let int = Object::Integer;
+        let string = |s: &str| Object::String(s.into());
+        assert_eq!(format("~5a|~5@a|~5,,,'*s", &[string("ab"), string("ab"), string("ab")]), Ok("ab   |   ab|\"ab\"*".into()));
+        assert_eq!(format("~:d ~@d ~8,'0b ~o ~x ~3r", &[int(1234567), int(5), int(5), int(8), int(255), int(5)]), Ok("1,234,567 +5 00000101 10 FF 12".into()));
+        assert_eq!(format("~r, ~:r, ~@r, ~:@r", &[int(1234), int(22), int(1994), int(4)]), Ok("one thousand two hundred thirty-four, twenty-second, MCMXCIV, IIII".into()));
+        assert_eq!(format("~,2f ~f ~8,3f ~e ~,2e ~$ ~@$", &[Object::Float(1.23456), int(2), Object::Float(-1.5), Object::Float(1234.5), Object::Float(0.001234), Object::Float(1.23456), Object::Float(2.5)]),
+            Ok("1.23 2.0   -1.500 1.2345e+3 1.23e-3 1.23 +2.50".into()));
+        assert_eq!(format("~d file~:p, ~d pon~:@p, ~c~:c", &[int(2), int(1), string("x"), string(" ")]), Ok("2 files, 1 pony, xSpace".into()));
+        assert_eq!(format("a~10tb~&~2%c", &[]), Ok("a         b\n\n\nc".into()));
+    }
+
+    #[test]
+    fn test_format_constructs() {
+        let (env, _) = interpreter();
+        let format = |control: &str, args: &[Object]| format(&env, control, args);
+        let int = Object::Integer;
+        let list = |v: Vec<Object>| Object::from(v);
+        assert_eq!(format("~[zero~;one~:;many~] ~:[no~;yes~] ~@[<~a>~]~@[~a~]", &[int(5), Object::T, int(1), Object::Nil]), Ok("many yes <1>".into()));
+        assert_eq!(format("~{~a~^, ~}", &[list(vec![int(1), int(2), int(3)])]), Ok("1, 2, 3".into()));
+        assert_eq!(format("~:{(~a ~a)~}", &[list(vec![list(vec![int(1), int(2)]), list(vec![int(3), int(4)])])]), Ok("(1 2)(3 4)".into()));
+        assert_eq!(format("~@{~a~^-~}", &[int(1), int(2)]), Ok("1-2".into()));
+        assert_eq!(format("~(Hello World~) ~:(hello world~) ~@(hello WORLD~) ~:@(up~)", &[]), Ok("hello world Hello World Hello world UP".into()));
+        assert_eq!(format("~10<left~;right~>|~10<right~>|~10:@<mid~>", &[]), Ok("left right|     right|   mid    ".into()));
+        assert_eq!(format("one~\n    two", &[]), Ok("onetwo".into()));
+        assert!(format("~{~a", &[]).is_err());
+    }
+
+    #[test]
+    fn test_format_from_source() {
+        let (_, run) = interpreter();
+        let string = |s: &str| Ok(Object::String(s.into()));
+        // Clause separators inside a string are not comments
+        assert_eq!(run("(format nil \"~[zero~;one~]\" 1)"), string("one"));
+        assert_eq!(run("(format nil \"~:[no~;yes~]\" nil)"), string("no"));
+        assert_eq!(run("(format nil \"~8<a~;b~>\")"), string("a      b"));
+        assert_eq!(run("(format nil \"~[a~;b~]\" 0) (format nil \"~a\" 'rest)"), string("REST"));
+        assert_eq!(run("(format nil \"say \\\"~a\\\"; ~s\" 1 \"a\\\"b\")"), string("say \"1\"; \"a\\\"b\""));
+        assert!(run("(format nil \"~[a~;b\")").is_err());
+    }
+
+    #[test]
+    fn test_format_print_object() {
+        let (_, run) = interpreter();
+        run("(defclass point () ((x :initarg :x)))").unwrap();
+        run("(defmethod print-object ((p point) stream) (format stream \"#<POINT ~a>\" (slot-value p 'x)))").unwrap();
+        run("(defvar *p* (make-instance 'point :x 1))").unwrap();
+        let string = |s: &str| Ok(Object::String(s.into()));
+        assert_eq!(run("(format nil \"~a\" *p*)"), string("#<POINT 1>"));
+        assert_eq!(run("(format nil \"~s\" (cons *p* (cons \"s\" (cons (cons *p* 2) nil))))"), string("(#<POINT 1> \"s\" (#<POINT 1> . 2))"));
+        assert_eq!(run("(format nil \"~a\" (cons *p* (cons \"s\" nil)))"), string("(#<POINT 1> s)"));
+        assert_eq!(run("(format nil \"~12a|~12@a|\" *p* *p*)"), string("#<POINT 1>  |  #<POINT 1>|"));
+        assert_eq!(run("(format nil \"~14:@<~a~>|~d\" *p* *p*)"), string("  #<POINT 1>  |#<POINT 1>"));
+        assert_eq!(run("(with-output-to-string (s) (princ *p* s))"), string("#<POINT 1>"));
+        run("(define-condition odd-point (error) ((p :initarg :p)) (:report (lambda (c s) (format s \"odd ~a\" (slot-value c 'p)))))").unwrap();
+        assert_eq!(run("(handler-case (error 'odd-point :p *p*) (error (c) (format nil \"~a\" c)))"), string("odd #<POINT 1>"));
+        assert_eq!(run("(handler-case (error \"bad ~a\" *p*) (error (c) (format nil \"~a\" c)))"), string("bad #<POINT 1>"));
+    }
+}
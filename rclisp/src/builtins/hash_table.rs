@@ -0,0 +1,182 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, REST_PARAMETERS, UNARY_PARAMETERS, BINARY_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env};
+
+use crate::eval::apply;
+use crate::package::designator_name;
+use crate::types::{HashTable, Lambda};
+use super::values::primary_value;
+
+builtin_parameters! {
+    static ref GETHASH_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y"), Parameter::optional("Z", Object::Nil)]);
+    static ref SETF_GETHASH_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y"), Parameter::normal("Z"), Parameter::optional("W", Object::Nil)]);
+}
+
+const HASH_TABLE_TESTS: &[&str] = &["EQ", "EQL", "EQUAL", "EQUALP"];
+
+/// Name of the test function given to `make-hash-table`
+fn test_name(obj: &Object) -> Result<String, EvalError> {
+    let name = match obj {
+        Object::Lambda(l) => match l.as_ref() {
+            Lambda::Builtin(b) => b.inner.get_name().to_uppercase(),
+            Lambda::Named(n, _) => n.clone(),
+            Lambda::Unnamed(_) | Lambda::Macro(_, _) => return Err(EvalError::ParameterTypeMismatched),
+        },
+        o => designator_name(o).ok_or(EvalError::ParameterTypeMismatched)?,
+    };
+    if HASH_TABLE_TESTS.contains(&name.as_str()) {
+        Ok(name)
+    } else {
+        Err(EvalError::ParameterTypeMismatched)
+    }
+}
+
+pub struct ObjectMakeHashTable;
+
+impl BuiltinFunc for ObjectMakeHashTable {
+    fn get_parameters(&self) -> &Params {
+        &REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "make-hash-table"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let args = list_items(rcenv_get!(env, "X")?);
+        if !args.len().is_multiple_of(2) {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        let mut test = "EQL".to_string();
+        for pair in args.chunks(2) {
+            match designator_name(&pair[0]).as_deref() {
+                Some("TEST") => test = test_name(&pair[1])?,
+                Some("SIZE") => {
+                    guard_obj!(pair[1].clone(), Integer)?;
+                },
+                _ => return Err(EvalError::UnknownKeyword(pair[0].to_string().trim_start_matches(':').into())),
+            }
+        }
+        Ok(HashTable::new(test).into())
+    }
+}
+
+pub struct ObjectGethash;
+
+impl BuiltinFunc for ObjectGethash {
+    fn get_parameters(&self) -> &Params {
+        &GETHASH_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "gethash"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // Returns the value and whether the key was present
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let key = rcenv_get!(env, "X")?;
+        let table = guard_obj!(rcenv_get!(env, "Y")?, HashTable)?;
+        match table.get(&key) {
+            Some(value) => Ok(vec![value, Object::T]),
+            None => Ok(vec![rcenv_get!(env, "Z")?, Object::Nil]),
+        }
+    }
+}
+
+// Setf function of GETHASH
+pub struct ObjectSetfGethash;
+
+impl BuiltinFunc for ObjectSetfGethash {
+    fn get_parameters(&self) -> &Params {
+        &SETF_GETHASH_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "(setf gethash)"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let value = rcenv_get!(env, "X")?;
+        let key = rcenv_get!(env, "Y")?;
+        let table = guard_obj!(rcenv_get!(env, "Z")?, HashTable)?;
+        table.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+pub struct ObjectRemhash;
+
+impl BuiltinFunc for ObjectRemhash {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "remhash"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let key = rcenv_get!(env, "X")?;
+        let table = guard_obj!(rcenv_get!(env, "Y")?, HashTable)?;
+        Ok(if table.remove(&key) { Object::T } else { Object::Nil })
+    }
+}
+
+pub struct ObjectClrhash;
+
+impl BuiltinFunc for ObjectClrhash {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "clrhash"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let table = guard_obj!(rcenv_get!(env, "X")?, HashTable)?;
+        table.clear();
+        Ok(table.into())
+    }
+}
+
+pub struct ObjectHashTableCount;
+
+impl BuiltinFunc for ObjectHashTableCount {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "hash-table-count"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let table = guard_obj!(rcenv_get!(env, "X")?, HashTable)?;
+        Ok(Object::Integer(table.len() as isize))
+    }
+}
+
+pub struct ObjectMaphash;
+
+impl BuiltinFunc for ObjectMaphash {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "maphash"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let function = rcenv_get!(env, "X")?;
+        let table = guard_obj!(rcenv_get!(env, "Y")?, HashTable)?;
+        let caller = caller_env(env);
+        for (key, value) in table.entries() {
+            apply(function.clone(), vec![key, value], &caller)?;
+        }
+        Ok(Object::Nil)
+    }
+}
@@ -0,0 +1,229 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, Params, Parameter, rcenv_get, guard_obj, list_items, caller_env};
+
+use crate::env::DynamicBindings;
+use crate::eval::{Eval, eval_body_values, new_frame_id};
+use super::lambda::body_forms;
+use super::control::{block_key, catch_exit, run_tagbody};
+use super::values::primary_value;
+
+builtin_parameters! {
+    static ref DOLIST_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref DO_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+}
+
+/// Frame of the caller's environment with a block named NIL, and the id to leave it
+fn nil_block(env: &RcEnv) -> (RcEnv, usize) {
+    let id = new_frame_id();
+    let frame = Env::inherit(&caller_env(env)).wrap();
+    frame.borrow_mut().insert_str(block_key(&Object::Nil), Object::Integer(id as isize));
+    (frame, id)
+}
+
+/// Variable, form and optional result form of `(var form [result])`
+fn iteration_spec(spec: Object) -> Result<(String, Object, Object), EvalError> {
+    let (var, rest) = guard_obj!(spec, List)?.unpack();
+    let var = guard_obj!(var, Symbol)?;
+    match list_items(rest).as_slice() {
+        [form] => Ok((var, form.clone(), Object::Nil)),
+        [form, result] => Ok((var, form.clone(), result.clone())),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Run the body once with the variable bound to the value
+fn iterate(block: &RcEnv, var: &str, value: Object, body: &[Object]) -> Result<(), EvalError> {
+    let frame = Env::inherit(block).wrap();
+    let mut bindings = DynamicBindings::new(&frame);
+    bindings.bind(&frame, var, value)?;
+    run_tagbody(body, &frame)
+}
+
+/// Value of the result form with the variable bound to the value
+fn iteration_result(block: &RcEnv, var: &str, value: Object, result: Object) -> Result<Vec<Object>, EvalError> {
+    let frame = Env::inherit(block).wrap();
+    let mut bindings = DynamicBindings::new(&frame);
+    bindings.bind(&frame, var, value)?;
+    result.eval_values(&frame)
+}
+
+pub struct ObjectDolist;
+
+impl ObjectDolist {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let (var, form, result) = iteration_spec(rcenv_get!(env, "X")?)?;
+        let body = list_items(rcenv_get!(env, "Y")?);
+        let (block, id) = nil_block(env);
+        let ret = (|| {
+            for element in list_items(form.eval(&block)?) {
+                iterate(&block, &var, element, &body)?;
+            }
+            iteration_result(&block, &var, Object::Nil, result)
+        })();
+        catch_exit(ret, id)
+    }
+}
+
+impl BuiltinFunc for ObjectDolist {
+    fn get_parameters(&self) -> &Params {
+        &DOLIST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "dolist"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectDotimes;
+
+impl ObjectDotimes {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let (var, form, result) = iteration_spec(rcenv_get!(env, "X")?)?;
+        let body = list_items(rcenv_get!(env, "Y")?);
+        let (block, id) = nil_block(env);
+        let ret = (|| {
+            let count = guard_obj!(form.eval(&block)?, Integer)?;
+            for i in 0..count {
+                iterate(&block, &var, Object::Integer(i), &body)?;
+            }
+            iteration_result(&block, &var, Object::Integer(count.max(0)), result)
+        })();
+        catch_exit(ret, id)
+    }
+}
+
+impl BuiltinFunc for ObjectDotimes {
+    fn get_parameters(&self) -> &Params {
+        &DOLIST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "dotimes"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+/// Variable, initial form and optional step form of `var`, `(var [init [step]])`
+fn do_spec(spec: Object) -> Result<(String, Object, Option<Object>), EvalError> {
+    match spec {
+        Object::Symbol(var) => Ok((var, Object::Nil, None)),
+        Object::List(l) => {
+            let (var, rest) = l.unpack();
+            let var = guard_obj!(var, Symbol)?;
+            match list_items(rest).as_slice() {
+                [] => Ok((var, Object::Nil, None)),
+                [init] => Ok((var, init.clone(), None)),
+                [init, step] => Ok((var, init.clone(), Some(step.clone()))),
+                _ => Err(EvalError::ParameterTypeMismatched),
+            }
+        },
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Run a `do` loop in a NIL block. With `sequential`, each initial and step form sees the
+/// variables bound or stepped before it, otherwise they are all evaluated first.
+fn run_do(env: &RcEnv, sequential: bool) -> Result<Vec<Object>, EvalError> {
+    let specs = list_items(rcenv_get!(env, "X")?).into_iter()
+        .map(do_spec)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (test, result) = guard_obj!(rcenv_get!(env, "Y")?, List)?.unpack();
+    let result = body_forms(result);
+    let body = list_items(rcenv_get!(env, "Z")?);
+    let (block, id) = nil_block(env);
+    let frame = Env::inherit(&block).wrap();
+    let mut bindings = DynamicBindings::new(&frame);
+    let ret = (|| {
+        let mut values = vec![];
+        for (var, init, _) in specs.iter() {
+            let value = init.clone().eval(if sequential { &frame } else { &block })?;
+            if sequential {
+                bindings.bind(&frame, var, value)?;
+            } else {
+                values.push((var, value));
+            }
+        }
+        for (var, value) in values {
+            bindings.bind(&frame, var, value)?;
+        }
+        while test.clone().eval(&frame)? == Object::Nil {
+            run_tagbody(&body, &frame)?;
+            let mut steps = vec![];
+            for (var, _, step) in specs.iter() {
+                if let Some(step) = step {
+                    let value = step.clone().eval(&frame)?;
+                    if sequential {
+                        frame.borrow_mut().set_str(var, value)?;
+                    } else {
+                        steps.push((var, value));
+                    }
+                }
+            }
+            for (var, value) in steps {
+                frame.borrow_mut().set_str(var, value)?;
+            }
+        }
+        eval_body_values(result, &frame)
+    })();
+    drop(bindings);
+    catch_exit(ret, id)
+}
+
+macro_rules! generate_do_forms {
+    ($struct:ident, $name:expr, $sequential:expr) => (
+        pub struct $struct;
+
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &DO_PARAMETERS
+            }
+
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                Ok(primary_value(run_do(env, $sequential)?))
+            }
+
+            fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+                run_do(env, $sequential)
+            }
+        }
+    );
+}
+
+generate_do_forms!(ObjectDo, "do", false);
+generate_do_forms!(ObjectDoStar, "do*", true);
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_iteration_forms() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(let ((r nil)) (dolist (x '(1 2 3) r) (setq r (cons x r))))"), run("'(3 2 1)"));
+        assert_eq!(run("(dolist (x '(1 2 3)) (when (eql x 2) (return x)))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(let ((s 0)) (dotimes (i 4 (cons i s)) (setq s (+ s i))))"), run("'(4 . 6)"));
+        assert_eq!(run("(do ((i 0 (+ i 1)) (acc nil (cons i acc))) ((eql i 3) acc))"), run("'(2 1 0)"));
+        assert_eq!(run("(do* ((i 0 (+ i 1)) (j i i)) ((eql i 2) j))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(do ((i 0 (+ i 1))) ((eql i 5)) (when (eql i 3) (return (values i t))))"), Ok(Object::Integer(3)));
+        assert_eq!(run("(let ((n 0)) (dotimes (i 3) (when (eql i 1) (go end)) (setq n (+ n 1)) end) n)"), Ok(Object::Integer(2)));
+    }
+}
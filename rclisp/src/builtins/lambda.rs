@@ -1,14 +1,68 @@
-use lazy_static::lazy_static;
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, List, Params, Parameter, UNARY_PARAMETERS, symbol, cons, nil, guard_obj, rcenv_get, caller_env, list_items};
+use super::values::primary_value;
 
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, symbol, cons, guard_obj, rcenv_get};
+use crate::env::{global_env, FunctionName};
+use crate::types::Lambda;
+use crate::eval::{Eval, eval_body, eval_body_values, apply, apply_values};
+use crate::package::designator_name;
+use super::types::{is_declaration, declared_lambda};
 
-use crate::types::{Lambda, UserLambda};
-use crate::eval::Eval;
+builtin_parameters! {
+    static ref DEFUN_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref LAMBDA_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref FUNCTION_PARAMETERS: Params = Params::from(vec![Parameter::plain("X")]);
+    static ref FUNCALL_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::rest("Y")]);
+    static ref COMPILE_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil)]);
+}
+
+/// Parameters of a lambda list, `()` reads as NIL
+pub(crate) fn lambda_list(obj: Object) -> Result<Params, EvalError> {
+    match obj {
+        Object::List(l) => Ok(Params::try_from(l.to_list())?),
+        Object::Nil => Ok(Params::from(vec![])),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Forms of a body, which may be empty
+pub(crate) fn body_forms(obj: Object) -> List {
+    match obj {
+        Object::List(l) => l.to_list(),
+        _ => List::EndsWith(Object::Nil),
+    }
+}
+
+/// Function name and block name of a function name, which is `name` or `(setf name)`
+pub(crate) fn function_name(name: Object) -> Result<(FunctionName, String), EvalError> {
+    match name {
+        Object::Symbol(s) => Ok((FunctionName::Symbol(s.clone()), s)),
+        Object::List(l) => {
+            let (head, rest) = l.unpack();
+            match (designator_name(&head).as_deref(), rest) {
+                (Some("SETF"), Object::List(rest)) => {
+                    let accessor = guard_obj!(rest.car(), Symbol)?;
+                    Ok((FunctionName::Setf(accessor.clone()), accessor))
+                },
+                _ => Err(EvalError::ParameterTypeMismatched),
+            }
+        },
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Body running in a block of the given name, the declarations staying in front of the block
+pub(crate) fn block_body(block: &str, body: Object) -> List {
+    let mut forms: Vec<Object> = body_forms(body).into_iter().collect();
+    let rest = forms.split_off(forms.iter().take_while(|f| is_declaration(f)).count());
+    forms.push(cons(symbol("BLOCK"), cons(symbol(block), Object::from(rest))));
+    body_forms(Object::from(forms))
+}
 
-lazy_static! {
-    static ref DEFUN_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::rest("Z")]);
-    static ref LAMBDA_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y")]);
-    static ref FUNCALL_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::plain("Y")]);
+/// Function whose body runs in a block named after it, closing over `env`
+pub(crate) fn block_function(name: &str, block: &str, params: Object, body: Object, env: &RcEnv) -> Result<Object, EvalError> {
+    let p = lambda_list(params.clone())?;
+    let lambda = declared_lambda(p, block_body(block, body))?.with_env(env).with_lambda_list(params);
+    Ok(Lambda::Named(name.to_string(), lambda).into())
 }
 
 pub struct ObjectDefun;
@@ -23,15 +77,10 @@ impl BuiltinFunc for ObjectDefun {
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
-        let params = *guard_obj!(rcenv_get!(env, "Y")?, List)?;
-        let body = *guard_obj!(rcenv_get!(env, "Z")?, List)?;
+        let (name, block) = function_name(rcenv_get!(env, "X")?)?;
+        let lambda = block_function(&name.to_string(), &block, rcenv_get!(env, "Y")?, rcenv_get!(env, "Z")?, &caller_env(env))?;
 
-        let p = Params::try_from(params)?;
-
-        let lambda = Object::Lambda(Box::new(Lambda::Named(name.clone(), UserLambda::new(p, body))));
-        
-        env.borrow_mut().insert_global(&symbol(name), lambda);
+        env.borrow_mut().insert_global_function(&name, lambda);
 
         Ok(Object::Nil)
     }
@@ -50,30 +99,342 @@ impl BuiltinFunc for ObjectLambda {
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
         // trace!("{:#?}", env);
-        let params = *guard_obj!(rcenv_get!(env, "X")?, List)?;
-        let body = *guard_obj!(rcenv_get!(env, "Y")?, List)?;
+        let params = rcenv_get!(env, "X")?;
+        let p = lambda_list(params.clone())?;
+        let body = body_forms(rcenv_get!(env, "Y")?);
+
+        let lambda = declared_lambda(p, body)?.with_env(&caller_env(env)).with_lambda_list(params);
+        Ok(Object::Lambda(Box::new(Lambda::Unnamed(lambda))))
+    }
+}
+
+/// Define local functions in a new frame, returning the body to run in it.
+/// With `recursive`, the functions can see each other and themselves.
+fn bind_functions(env: &RcEnv, recursive: bool) -> Result<(List, RcEnv), EvalError> {
+    let caller = caller_env(env);
+    let frame = Env::inherit(&caller).wrap();
+    let closure = if recursive { &frame } else { &caller };
+    let mut functions = vec![];
+    for definition in list_items(rcenv_get!(env, "X")?) {
+        let (name, rest) = guard_obj!(definition, List)?.unpack();
+        let (params, body) = guard_obj!(rest, List)?.unpack();
+        let (key, block) = function_name(name)?;
+        let function = block_function(&key.to_string(), &block, params, body, closure)?;
+        functions.push((key, function));
+    }
+    for (key, function) in functions {
+        frame.borrow_mut().insert_function(&key, function);
+    }
+    Ok((body_forms(rcenv_get!(env, "Y")?), frame))
+}
+
+macro_rules! generate_local_function_forms {
+    ($struct:ident, $name:expr, $recursive:expr) => (
+        pub struct $struct;
+
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &LAMBDA_PARAMETERS
+            }
+
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                let (body, frame) = bind_functions(env, $recursive)?;
+                eval_body(body, &frame)
+            }
+
+            fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+                let (body, frame) = bind_functions(env, $recursive)?;
+                eval_body_values(body, &frame)
+            }
+        }
+    );
+}
+
+generate_local_function_forms!(ObjectFlet, "flet", false);
+generate_local_function_forms!(ObjectLabels, "labels", true);
+
+pub struct ObjectFunction;
+
+impl BuiltinFunc for ObjectFunction {
+    fn get_parameters(&self) -> &Params {
+        &FUNCTION_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "function"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let name = rcenv_get!(env, "X")?;
+        let function = match name {
+            Object::Symbol(s) => caller.borrow().get_str(s)?,
+            Object::List(l) => {
+                let (head, rest) = l.unpack();
+                match designator_name(&head).as_deref() {
+                    Some("LAMBDA") => cons(head, rest).eval(&caller)?,
+                    Some("SETF") => {
+                        let accessor = guard_obj!(guard_obj!(rest, List)?.car(), Symbol)?;
+                        caller.borrow().get_function(&FunctionName::Setf(accessor))?
+                    },
+                    _ => return Err(EvalError::IllegalFunctionCall),
+                }
+            },
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        };
+        match function {
+            Object::Lambda(_) => Ok(function),
+            _ => Err(EvalError::ParameterTypeMismatched),
+        }
+    }
+}
+
+/// Function object or symbol naming a function
+pub(crate) fn function_designator(obj: Object) -> Result<Object, EvalError> {
+    match obj {
+        Object::Lambda(_) => Ok(obj),
+        Object::Symbol(ref s) if !s.starts_with(':') => Ok(obj),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Function and arguments of `funcall`, or of `apply` whose last argument is a list of more arguments
+fn call_arguments(env: &RcEnv, spread: bool) -> Result<(Object, Vec<Object>), EvalError> {
+    let function = function_designator(rcenv_get!(env, "X")?)?;
+    let mut args = list_items(rcenv_get!(env, "Y")?);
+    if spread {
+        match args.pop() {
+            Some(Object::List(l)) => args.extend(l.to_list()),
+            Some(Object::Nil) => (),
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        }
+    }
+    Ok((function, args))
+}
+
+macro_rules! generate_call_forms {
+    ($struct:ident, $name:expr, $spread:expr) => (
+        pub struct $struct;
 
-        let p = Params::try_from(params)?;
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &FUNCALL_PARAMETERS
+            }
 
-        Ok(Object::Lambda(Box::new(Lambda::Unnamed(UserLambda::new(p, body)))))
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                let (function, args) = call_arguments(env, $spread)?;
+                apply(function, args, &caller_env(env))
+            }
+
+            fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+                let (function, args) = call_arguments(env, $spread)?;
+                apply_values(function, args, &caller_env(env))
+            }
+        }
+    );
+}
+
+generate_call_forms!(ObjectFuncall, "funcall", false);
+generate_call_forms!(ObjectApply, "apply", true);
+
+pub struct ObjectEval;
+
+impl BuiltinFunc for ObjectEval {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "eval"
+    }
+
+    // The form sees global definitions only, not the lexical bindings around the call
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        rcenv_get!(env, "X")?.eval(&global_env(env))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        rcenv_get!(env, "X")?.eval_values(&global_env(env))
     }
 }
 
-pub struct ObjectFuncall;
+pub struct ObjectCompile;
 
-impl BuiltinFunc for ObjectFuncall {
+impl BuiltinFunc for ObjectCompile {
     fn get_parameters(&self) -> &Params {
-        &FUNCALL_PARAMETERS
+        &COMPILE_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
-        "funcall"
+        "compile"
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let lambda = *guard_obj!(rcenv_get!(env, "X")?, Lambda)?;
-        // trace!("Running lambda: {}", lambda);
-        let params = *guard_obj!(rcenv_get!(env, "Y")?, List)?;
-        cons(lambda.into(), params.into()).eval(env)
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // Functions are interpreted, so compiling only makes a function of a lambda expression.
+    // Named definitions are installed globally and the name is returned, followed by the
+    // warnings-p and failure-p values which are always NIL.
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let global = global_env(env);
+        let name = rcenv_get!(env, "X")?;
+        let function = match rcenv_get!(env, "Y")? {
+            Object::Nil if name == Object::Nil => return Err(EvalError::ParameterTypeMismatched),
+            Object::Nil => {
+                let (key, _) = function_name(name.clone())?;
+                global.borrow().get_function(&key)?
+            },
+            Object::Lambda(l) => Object::Lambda(l),
+            form => form.eval(&global)?,
+        };
+        if !matches!(function, Object::Lambda(_)) {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        if name == Object::Nil {
+            return Ok(vec![function, Object::Nil, Object::Nil]);
+        }
+        let (key, _) = function_name(name.clone())?;
+        global.borrow_mut().insert_global_function(&key, function);
+        Ok(vec![name, Object::Nil, Object::Nil])
+    }
+}
+
+/// Name of a function as an object, setf functions being named by a list
+fn name_object(name: &str) -> Object {
+    match name.strip_prefix("(SETF ").and_then(|s| s.strip_suffix(')')) {
+        Some(accessor) => cons(symbol("SETF"), cons(symbol(accessor), nil())),
+        None => symbol(name),
+    }
+}
+
+pub struct ObjectFunctionLambdaExpression;
+
+impl BuiltinFunc for ObjectFunctionLambdaExpression {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "function-lambda-expression"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // The lambda expression, whether the function closes over local bindings and its name.
+    // Builtins have no lambda expression.
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let function = guard_obj!(rcenv_get!(env, "X")?, Lambda)?;
+        let (lambda, name) = match *function {
+            Lambda::Unnamed(l) => (l, Object::Nil),
+            Lambda::Named(name, l) | Lambda::Macro(name, l) => (l, name_object(&name)),
+            Lambda::Builtin(_) => return Ok(vec![Object::Nil, Object::Nil, Object::Nil]),
+        };
+        let closure = match lambda.get_env() {
+            Some(e) if e.borrow().get_parent().is_some() => Object::T,
+            _ => Object::Nil,
+        };
+        let expression = match lambda.get_lambda_list() {
+            Some(params) => {
+                let params = params.clone();
+                let mut body: Vec<Object> = lambda.get_declarations().iter().map(|d| {
+                    let spec = cons(symbol("TYPE"), cons(d.type_spec.clone(), Object::from(d.variables.clone())));
+                    cons(symbol("DECLARE"), cons(spec, nil()))
+                }).collect();
+                body.extend(lambda.get_body());
+                cons(symbol("LAMBDA"), cons(params, Object::from(body)))
+            },
+            None => Object::Nil,
+        };
+        Ok(vec![expression, closure, name])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_ordinary_lambda_lists() {
+        let (_, run) = interpreter();
+        run("(defun f (a &optional (b (+ a 1) b-p) &rest r &key (c 10) ((:dee d) 20) &aux (s (+ a b))) (cons a (cons b (cons b-p (cons r (cons c (cons d (cons s nil))))))))").unwrap();
+        assert_eq!(run("(f 1)"), run("'(1 2 nil nil 10 20 3)"));
+        assert_eq!(run("(f 1 2 :c 3 :dee 4)"), run("'(1 2 t (:c 3 :dee 4) 3 4 3)"));
+        assert_eq!(run("(f 1 2 :e 3 :allow-other-keys t)"), run("'(1 2 t (:e 3 :allow-other-keys t) 10 20 3)"));
+        assert!(run("(f 1 2 :e 3)").is_err());
+        assert!(run("(f)").is_err());
+        assert_eq!(run("(defun g (&key x &allow-other-keys) x) (g :y 1 :x 2)"), Ok(Object::Integer(2)));
+        assert!(run("(defun h (a) a) (h 1 2)").is_err());
+        assert!(run("(defun bad (&key &optional x) x)").is_err());
+    }
+
+    #[test]
+    fn test_local_functions() {
+        let (_, run) = interpreter();
+        run("(defun twice (x) (* x 2))").unwrap();
+        assert_eq!(run("(flet ((twice (x) (+ x 1)) (other (x) (twice x))) (cons (twice 1) (other 1)))"), run("'(2 . 2)"));
+        assert_eq!(run("(labels ((ev (n) (if (eql n 0) t (od (- n 1)))) (od (n) (if (eql n 0) nil (ev (- n 1))))) (ev 10))"), Ok(Object::T));
+        assert_eq!(run("(let ((k 3)) (flet ((add (x) (+ x k))) (funcall #'add 4)))"), Ok(Object::Integer(7)));
+        assert_eq!(run("(flet ((f () (return-from f 5) 6)) (f))"), Ok(Object::Integer(5)));
+    }
+
+    #[test]
+    fn test_closures() {
+        let (_, run) = interpreter();
+        run("(defun make-counter () (let ((n 0)) (lambda () (setq n (+ n 1)))))").unwrap();
+        run("(defvar *a* (make-counter)) (defvar *b* (make-counter))").unwrap();
+        assert_eq!(run("(funcall *a*) (funcall *a*) (funcall *a*)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(funcall *b*)"), Ok(Object::Integer(1)));
+        run("(defun adder (k) (lambda (x) (+ x k)))").unwrap();
+        assert_eq!(run("(mapcar (adder 10) '(1 2))"), run("'(11 12)"));
+        assert_eq!(run("(funcall (adder 1) (funcall (adder 2) 3))"), Ok(Object::Integer(6)));
+        assert_eq!(run("(let* ((x 1) (f (lambda () x))) (setq x 5) (funcall f))"), Ok(Object::Integer(5)));
+        run("(defvar *get*) (defvar *set*)").unwrap();
+        run("(let ((v 1)) (setq *get* (lambda () v)) (setq *set* (lambda (n) (setq v n))))").unwrap();
+        run("(funcall *set* 42)").unwrap();
+        assert_eq!(run("(funcall *get*)"), Ok(Object::Integer(42)));
+    }
+
+    #[test]
+    fn test_funcall_apply() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(funcall #'+ 1 2)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(funcall 'cons 1 2)"), run("'(1 . 2)"));
+        assert_eq!(run("(apply #'cons 1 '(2))"), run("'(1 . 2)"));
+        assert_eq!(run("(apply (lambda (a b &rest r) (cons (+ a b) r)) 1 2 '(3 4))"), run("'(3 3 4)"));
+        assert_eq!(run("(apply (lambda (&rest r) r) nil)"), Ok(Object::Nil));
+        assert_eq!(run("(multiple-value-list (funcall #'floor 7 2))"), run("'(3 1)"));
+        assert!(run("(apply #'cons 1 2)").is_err());
+    }
+
+    #[test]
+    fn test_eval_compile() {
+        let (_, run) = interpreter();
+        run("(defvar *x* 1)").unwrap();
+        assert_eq!(run("(let ((y 2)) (eval (cons '+ (cons '*x* (cons y nil)))))"), Ok(Object::Integer(3)));
+        assert!(run("(let ((y 2)) (eval 'y))").is_err());
+        assert_eq!(run("(multiple-value-list (eval '(floor 7 2)))"), run("'(3 1)"));
+        assert_eq!(run("(funcall (compile nil '(lambda (x) (* x 2))) 4)"), Ok(Object::Integer(8)));
+        assert_eq!(run("(compile 'twice (lambda (x) (* x 2)))"), run("'twice"));
+        assert_eq!(run("(twice 5)"), Ok(Object::Integer(10)));
+        assert_eq!(run("(function-lambda-expression (lambda (a &optional b) (cons a b)))"), run("'(lambda (a &optional b) (cons a b))"));
+        run("(defun f (x) x)").unwrap();
+        assert_eq!(run("(multiple-value-list (function-lambda-expression #'f))"), run("'((lambda (x) (block f x)) nil f)"));
+        assert_eq!(run("(nth-value 1 (function-lambda-expression (let ((k 1)) (lambda () k))))"), Ok(Object::T));
+        assert_eq!(run("(function-lambda-expression #'car)"), Ok(Object::Nil));
+        assert_eq!(run("(cons (functionp #'f) (functionp 'f))"), run("'(t)"));
+        run("(defconstant +c+ 1)").unwrap();
+        assert_eq!(run("(mapcar #'constantp '(1 \"s\" :k +c+ 'x *x* (f 1)))"), run("'(t t t t t nil nil)"));
     }
 }
@@ -1,6 +1,13 @@
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, UNARY_PARAMETERS, BINARY_PARAMETERS, rcenv_get, guard_obj};
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, UNARY_PARAMETERS, BINARY_PARAMETERS, REST_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env};
+use super::lambda::function_designator;
 
 use crate::types::cons;
+use crate::eval::apply;
+use crate::package::designator_name;
+
+builtin_parameters! {
+    static ref MAP_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y"), Parameter::rest("Z")]);
+}
 
 pub struct ObjectCons;
 
@@ -20,6 +27,51 @@ impl BuiltinFunc for ObjectCons {
     }
 }
 
+pub struct ObjectList;
+
+impl BuiltinFunc for ObjectList {
+    fn get_parameters(&self) -> &Params {
+        &REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "list"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(rcenv_get!(env, "X")?)
+    }
+}
+
+pub struct ObjectAppend;
+
+impl BuiltinFunc for ObjectAppend {
+    fn get_parameters(&self) -> &Params {
+        &REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "append"
+    }
+
+    // The last argument is shared as the tail of the result, the others are copied
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let mut lists = list_items(rcenv_get!(env, "X")?);
+        let mut ret = lists.pop().unwrap_or(Object::Nil);
+        for list in lists.into_iter().rev() {
+            let items: Vec<Object> = match list {
+                Object::List(l) => l.into_iter().collect(),
+                Object::Nil => vec![],
+                _ => return Err(EvalError::ParameterTypeMismatched),
+            };
+            for item in items.into_iter().rev() {
+                ret = cons(item, ret);
+            }
+        }
+        Ok(ret)
+    }
+}
+
 pub struct ObjectCar;
 
 impl BuiltinFunc for ObjectCar {
@@ -53,3 +105,250 @@ impl BuiltinFunc for ObjectCdr {
         Ok(x.cdr())
     }
 }
+
+pub struct ObjectNth;
+
+impl BuiltinFunc for ObjectNth {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "nth"
+    }
+
+    // Past the end of the list it is NIL
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let n = guard_obj!(rcenv_get!(env, "X")?, Integer)?;
+        let n = usize::try_from(n).map_err(|_| EvalError::ParameterTypeMismatched)?;
+        Ok(list_items(rcenv_get!(env, "Y")?).into_iter().nth(n).unwrap_or(Object::Nil))
+    }
+}
+
+/// Results of calling a function on successive elements of the lists, or on their successive tails
+/// with `tails`, until the shortest list runs out
+fn map_lists(env: &RcEnv, tails: bool) -> Result<Vec<Object>, EvalError> {
+    let function = function_designator(rcenv_get!(env, "X")?)?;
+    let mut lists = vec![rcenv_get!(env, "Y")?];
+    lists.append(&mut list_items(rcenv_get!(env, "Z")?));
+    let caller = caller_env(env);
+    let mut results = vec![];
+    while lists.iter().all(|l| matches!(l, Object::List(_))) {
+        let mut args = vec![];
+        for list in lists.iter_mut() {
+            let (car, cdr) = guard_obj!(std::mem::replace(list, Object::Nil), List)?.unpack();
+            args.push(if tails { cons(car, cdr.clone()) } else { car });
+            *list = cdr;
+        }
+        results.push(apply(function.clone(), args, &caller)?);
+    }
+    Ok(results)
+}
+
+pub struct ObjectMapcar;
+
+impl BuiltinFunc for ObjectMapcar {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "mapcar"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(map_lists(env, false)?.into())
+    }
+}
+
+pub struct ObjectMapc;
+
+impl BuiltinFunc for ObjectMapc {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "mapc"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        map_lists(env, false)?;
+        Ok(rcenv_get!(env, "Y")?)
+    }
+}
+
+pub struct ObjectMapcan;
+
+impl BuiltinFunc for ObjectMapcan {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "mapcan"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let mut items = vec![];
+        for result in map_lists(env, false)? {
+            if let Object::List(l) = result {
+                items.extend(l.to_list());
+            }
+        }
+        Ok(items.into())
+    }
+}
+
+pub struct ObjectMaplist;
+
+impl BuiltinFunc for ObjectMaplist {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "maplist"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(map_lists(env, true)?.into())
+    }
+}
+
+/// Keyword arguments of a sequence function, which must be among `allowed`
+pub(crate) fn sequence_keywords(obj: Object, allowed: &[&str]) -> Result<Vec<(String, Object)>, EvalError> {
+    let args = list_items(obj);
+    if !args.len().is_multiple_of(2) {
+        return Err(EvalError::ParameterTypeMismatched);
+    }
+    let mut ret = vec![];
+    for pair in args.chunks(2) {
+        match designator_name(&pair[0]) {
+            Some(name) if allowed.contains(&name.as_str()) => ret.push((name, pair[1].clone())),
+            _ => return Err(EvalError::UnknownKeyword(pair[0].to_string().trim_start_matches(':').into())),
+        }
+    }
+    Ok(ret)
+}
+
+pub(crate) fn keyword_value(keywords: &[(String, Object)], name: &str) -> Option<Object> {
+    keywords.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())
+}
+
+/// Element a sequence function works on, transformed by its `:key` function if there is one
+pub(crate) fn key_of(key: &Option<Object>, element: &Object, env: &RcEnv) -> Result<Object, EvalError> {
+    match key {
+        Some(Object::Nil) | None => Ok(element.clone()),
+        Some(function) => apply(function_designator(function.clone())?, vec![element.clone()], env),
+    }
+}
+
+/// Elements of the list for which the predicate is `keep`
+fn filter(env: &RcEnv, keep: bool) -> Result<Object, EvalError> {
+    let predicate = function_designator(rcenv_get!(env, "X")?)?;
+    let keywords = sequence_keywords(rcenv_get!(env, "Z")?, &["KEY"])?;
+    let key = keyword_value(&keywords, "KEY");
+    let caller = caller_env(env);
+    let mut ret = vec![];
+    for element in list_items(rcenv_get!(env, "Y")?) {
+        let test = apply(predicate.clone(), vec![key_of(&key, &element, &caller)?], &caller)?;
+        if (test != Object::Nil) == keep {
+            ret.push(element);
+        }
+    }
+    Ok(ret.into())
+}
+
+pub struct ObjectRemoveIf;
+
+impl BuiltinFunc for ObjectRemoveIf {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "remove-if"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        filter(env, false)
+    }
+}
+
+pub struct ObjectRemoveIfNot;
+
+impl BuiltinFunc for ObjectRemoveIfNot {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "remove-if-not"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        filter(env, true)
+    }
+}
+
+pub struct ObjectReduce;
+
+impl BuiltinFunc for ObjectReduce {
+    fn get_parameters(&self) -> &Params {
+        &MAP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "reduce"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let function = function_designator(rcenv_get!(env, "X")?)?;
+        let keywords = sequence_keywords(rcenv_get!(env, "Z")?, &["INITIAL-VALUE", "FROM-END", "KEY"])?;
+        let key = keyword_value(&keywords, "KEY");
+        let from_end = keyword_value(&keywords, "FROM-END").is_some_and(|v| v != Object::Nil);
+        let caller = caller_env(env);
+        let mut elements = vec![];
+        for element in list_items(rcenv_get!(env, "Y")?) {
+            elements.push(key_of(&key, &element, &caller)?);
+        }
+        if from_end {
+            elements.reverse();
+        }
+        let mut elements = elements.into_iter();
+        let mut acc = match keyword_value(&keywords, "INITIAL-VALUE").or_else(|| elements.next()) {
+            Some(v) => v,
+            None => return apply(function, vec![], &caller),
+        };
+        for element in elements {
+            let args = if from_end { vec![element, acc] } else { vec![acc, element] };
+            acc = apply(function.clone(), args, &caller)?;
+        }
+        Ok(acc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_mapping_functions() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(mapcar #'+ '(1 2 3) '(10 20))"), run("'(11 22)"));
+        assert_eq!(run("(mapcar (lambda (x) (* x x)) nil)"), Ok(Object::Nil));
+        assert_eq!(run("(let ((n 0)) (mapc (lambda (x) (setq n (+ n x))) '(1 2 3)) n)"), Ok(Object::Integer(6)));
+        assert_eq!(run("(mapcan (lambda (x) (if (eql x 2) nil (cons x nil))) '(1 2 3))"), run("'(1 3)"));
+        assert_eq!(run("(maplist #'car '(1 2 3))"), run("'(1 2 3)"));
+        assert_eq!(run("(maplist (lambda (l) l) '(1 2))"), run("'((1 2) (2))"));
+        assert_eq!(run("(remove-if #'integerp '(1 a 2 b))"), run("'(a b)"));
+        assert_eq!(run("(remove-if-not #'symbolp '((1) (a) (b)) :key #'car)"), run("'((a) (b))"));
+        assert_eq!(run("(reduce #'+ '(1 2 3 4))"), Ok(Object::Integer(10)));
+        assert_eq!(run("(reduce #'cons '(1 2 3) :initial-value nil)"), run("'(((nil . 1) . 2) . 3)"));
+        assert_eq!(run("(reduce #'cons '(1 2 3) :from-end t)"), run("'(1 2 . 3)"));
+        assert_eq!(run("(reduce (lambda () 0) nil)"), Ok(Object::Integer(0)));
+        assert!(run("(reduce #'+ '(1) :start 0)").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, UNARY_PARAMETERS, COMMON_LISP, rcenv_get, list_items, caller_env};
+use super::file::{check_file_system, file_error, pathname, truename};
+use super::list::{sequence_keywords, keyword_value};
+use super::print::write_object;
+use super::stream::{standard_stream, write_str, STANDARD_OUTPUT};
+
+use crate::env::{DynamicBindings, global_env};
+use crate::eval::Eval;
+use crate::lexer::Lexer;
+use crate::package::designator_name;
+use crate::parse::{parse, ParserError};
+
+pub const LOAD_PATHNAME: &str = "*LOAD-PATHNAME*";
+pub const LOAD_TRUENAME: &str = "*LOAD-TRUENAME*";
+pub const LOAD_VERBOSE: &str = "*LOAD-VERBOSE*";
+pub const LOAD_PRINT: &str = "*LOAD-PRINT*";
+pub const MODULES: &str = "*MODULES*";
+pub const MODULE_PATH: &str = "*MODULE-PATH*";
+
+// Environment variable listing the directories searched by `require`
+const RCL_PATH: &str = "RCL_PATH";
+
+builtin_parameters! {
+    static ref LOAD_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::rest("Y")]);
+    static ref REQUIRE_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil)]);
+}
+
+/// Define the variables of the loader, the module path coming from `RCL_PATH`
+pub(crate) fn define_load_variables(env: &RcEnv) {
+    let module_path: Vec<Object> = match env::var_os(RCL_PATH) {
+        Some(paths) => env::split_paths(&paths)
+            .map(|p| Object::String(p.to_string_lossy().into_owned()))
+            .collect(),
+        None => vec![],
+    };
+    let variables = [
+        (LOAD_PATHNAME, Object::Nil),
+        (LOAD_TRUENAME, Object::Nil),
+        (LOAD_VERBOSE, Object::Nil),
+        (LOAD_PRINT, Object::Nil),
+        (MODULES, Object::Nil),
+        (MODULE_PATH, Object::from(module_path)),
+    ];
+    for (name, value) in variables {
+        env.borrow_mut().insert_global_str(name, value);
+        env.borrow_mut().proclaim_special(name).expect("Loader variables are not constants");
+        env.borrow().get_packages().borrow_mut()
+            .export(COMMON_LISP, name)
+            .expect("COMMON-LISP package is missing");
+    }
+}
+
+/// Directory of the file being loaded, if any
+fn loading_directory(env: &RcEnv) -> Option<PathBuf> {
+    match env.borrow().get_str(LOAD_PATHNAME) {
+        Ok(Object::String(s)) => Path::new(&s).parent().map(Path::to_path_buf),
+        _ => None,
+    }
+}
+
+/// File to load for a name, relative names being resolved against the directory of the file
+/// being loaded and `.lisp` being tried for names without a type
+fn source_path(name: &str, env: &RcEnv) -> PathBuf {
+    let path = match loading_directory(env) {
+        Some(dir) if Path::new(name).is_relative() => dir.join(name),
+        _ => PathBuf::from(name),
+    };
+    if !path.exists() && path.extension().is_none() {
+        let source = path.with_extension("lisp");
+        if source.exists() {
+            return source;
+        }
+    }
+    path
+}
+
+/// Evaluate the forms of a file one after another at top level, `*package*` being restored afterwards
+pub fn load<P: AsRef<Path>>(path: P, env: &RcEnv, verbose: bool, print: bool) -> Result<(), EvalError> {
+    check_file_system(env)?;
+    let name = path.as_ref().to_string_lossy().into_owned();
+    let file = File::open(&path).map_err(|e| file_error(&name, e))?;
+    let global = global_env(env);
+    let output = standard_stream(env, STANDARD_OUTPUT)?;
+    if verbose {
+        output.fresh_line().map_err(super::stream::stream_error)?;
+        write_str(&output, &format!("; Loading {:?}\n", name))?;
+    }
+    let mut bindings = DynamicBindings::new(&global);
+    bindings.bind(&global, LOAD_PATHNAME, Object::String(name.clone()))?;
+    bindings.bind(&global, LOAD_TRUENAME, Object::String(truename(&path).map_err(|e| file_error(&name, e))?))?;
+    let package = global.borrow().get_str("*PACKAGE*")?;
+    bindings.bind(&global, "*PACKAGE*", package)?;
+    let mut lexer = Lexer::new(file);
+    loop {
+        let form = match parse(&mut lexer) {
+            Ok(form) => form,
+            Err(ParserError::EmptyInput) => break,
+            Err(e) => return Err(EvalError::ParseError(name, lexer.position(), e.to_string())),
+        };
+        let value = crate::read(form, &global)?.eval(&global)?;
+        if print {
+            write_object(&global, &value, &output, true)?;
+            write_str(&output, "\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Value of a keyword argument, defaulting to the value of a variable
+fn flag(keywords: &[(String, Object)], name: &str, variable: &str, env: &RcEnv) -> Result<bool, EvalError> {
+    let value = match keyword_value(keywords, name) {
+        Some(v) => v,
+        None => env.borrow().get_str(variable)?,
+    };
+    Ok(value != Object::Nil)
+}
+
+fn modules(env: &RcEnv) -> Result<Vec<String>, EvalError> {
+    let modules = env.borrow().get_str(MODULES)?;
+    Ok(list_items(modules).iter().filter_map(designator_name).collect())
+}
+
+/// Source file of a module, looked for next to the file being loaded and then in `*module-path*`
+fn find_module(name: &str, env: &RcEnv) -> Result<Option<PathBuf>, EvalError> {
+    let module_path = env.borrow().get_str(MODULE_PATH)?;
+    let mut directories: Vec<PathBuf> = loading_directory(env).into_iter().collect();
+    for dir in list_items(module_path) {
+        directories.push(PathBuf::from(pathname(dir)?));
+    }
+    if directories.is_empty() {
+        directories.push(PathBuf::new());
+    }
+    let files = [format!("{}.lisp", name), format!("{}.lisp", name.to_lowercase())];
+    Ok(directories.iter()
+        .flat_map(|dir| files.iter().map(move |f| dir.join(f)))
+        .find(|p| p.is_file()))
+}
+
+pub struct ObjectLoad;
+
+impl BuiltinFunc for ObjectLoad {
+    fn get_parameters(&self) -> &Params {
+        &LOAD_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "load"
+    }
+
+    // Returns NIL instead of failing for a missing file when :if-does-not-exist is NIL
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let keywords = sequence_keywords(rcenv_get!(env, "Y")?, &["VERBOSE", "PRINT", "IF-DOES-NOT-EXIST", "EXTERNAL-FORMAT"])?;
+        let path = source_path(&pathname(rcenv_get!(env, "X")?)?, &caller);
+        if keyword_value(&keywords, "IF-DOES-NOT-EXIST") == Some(Object::Nil) && !path.exists() {
+            return Ok(Object::Nil);
+        }
+        let verbose = flag(&keywords, "VERBOSE", LOAD_VERBOSE, &caller)?;
+        let print = flag(&keywords, "PRINT", LOAD_PRINT, &caller)?;
+        load(path, &caller, verbose, print)?;
+        Ok(Object::T)
+    }
+}
+
+pub struct ObjectProvide;
+
+impl BuiltinFunc for ObjectProvide {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "provide"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = designator_name(&rcenv_get!(env, "X")?).ok_or(EvalError::ParameterTypeMismatched)?;
+        let mut modules = modules(env)?;
+        if !modules.contains(&name) {
+            modules.push(name);
+            let modules = modules.into_iter().map(Object::String).collect::<Vec<_>>();
+            env.borrow_mut().set_str(MODULES, Object::from(modules))?;
+        }
+        Ok(Object::T)
+    }
+}
+
+pub struct ObjectRequire;
+
+impl BuiltinFunc for ObjectRequire {
+    fn get_parameters(&self) -> &Params {
+        &REQUIRE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "require"
+    }
+
+    // Modules already provided are not loaded again, the files given are loaded in order
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let name = designator_name(&rcenv_get!(env, "X")?).ok_or(EvalError::ParameterTypeMismatched)?;
+        if modules(&caller)?.contains(&name) {
+            return Ok(Object::Nil);
+        }
+        let files = match rcenv_get!(env, "Y")? {
+            Object::Nil => match find_module(&name, &caller)? {
+                Some(path) => vec![path],
+                None => return Err(EvalError::FileError(name, "module not found".into())),
+            },
+            Object::List(l) => l.into_iter().map(|p| Ok(source_path(&pathname(p)?, &caller))).collect::<Result<_, EvalError>>()?,
+            p => vec![source_path(&pathname(p)?, &caller)],
+        };
+        let verbose = flag(&[], "VERBOSE", LOAD_VERBOSE, &caller)?;
+        for path in files {
+            load(path, &caller, verbose, false)?;
+        }
+        Ok(Object::T)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env::temp_dir;
+    use std::fs;
+
+    use crate::{Object, Capability, EvalError};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_load() {
+        let (env, run) = interpreter();
+        env.borrow_mut().grant_capability(Capability::FileSystem);
+        let dir = temp_dir().join(format!("rclisp-test-load-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.lisp"), "(require :util) (require \"UTIL\") (defvar *main* (util 1))").unwrap();
+        fs::write(dir.join("util.lisp"), "(defvar *loads* 0) (setq *loads* (+ *loads* 1)) (load \"lib/helper\") (provide :util)").unwrap();
+        fs::write(dir.join("lib/helper.lisp"), "(defvar *where* *load-truename*) (defun util (x) (cons x *load-pathname*))").unwrap();
+        let root = dir.to_string_lossy().into_owned();
+        let run = |source: &str| run(&source.replace("$D", &root));
+        assert_eq!(run("(load \"$D/main\")"), Ok(Object::T));
+        assert_eq!(run("(car *main*)"), Ok(Object::Integer(1)));
+        assert_eq!(run("*loads*"), Ok(Object::Integer(1)));
+        assert_eq!(run("(cdr *main*)"), Ok(Object::String(format!("{}/main.lisp", root))));
+        assert_eq!(run("*load-pathname*"), Ok(Object::Nil));
+        assert_eq!(run("(stringp *where*)"), Ok(Object::T));
+        assert_eq!(run("*modules*"), run("'(\"UTIL\")"));
+        assert_eq!(run("(load \"$D/missing\" :if-does-not-exist nil)"), Ok(Object::Nil));
+        assert!(run("(require :missing)").is_err());
+        assert_eq!(run("(with-output-to-string (*standard-output*) (load \"$D/lib/helper.lisp\" :print t))"), Ok(Object::String("*WHERE*\nNIL\n".into())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_malformed() {
+        let (env, run) = interpreter();
+        env.borrow_mut().grant_capability(Capability::FileSystem);
+        let dir = temp_dir().join(format!("rclisp-test-load-malformed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let unclosed = dir.join("unclosed.lisp").to_string_lossy().into_owned();
+        let stray = dir.join("stray.lisp").to_string_lossy().into_owned();
+        fs::write(&unclosed, "(defvar *before* 1) (defvar *after* (+ 1").unwrap();
+        fs::write(&stray, "(defvar *stray* 1))").unwrap();
+        // Forms before the error are evaluated, the error tells where reading stopped
+        assert_eq!(super::load(&unclosed, &env, false, false), Err(EvalError::ParseError(unclosed.clone(), 40, "No matching parenthesis found".into())));
+        assert_eq!(run("*before*"), Ok(Object::Integer(1)));
+        assert!(run("*after*").is_err());
+        assert!(matches!(super::load(&stray, &env, false, false), Err(EvalError::ParseError(source, 19, _)) if source == stray));
+        match run(&format!("(load {:?})", stray)) {
+            Err(e @ EvalError::ParseError(..)) => assert!(e.to_string().contains(&stray), "{}", e),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(run(&format!("(handler-case (load {:?}) (parse-error () :parse-error))", unclosed)), run(":parse-error"));
+        assert_eq!(run("(handler-case (read-from-string \"(a\") (parse-error () :parse-error))"), run(":parse-error"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
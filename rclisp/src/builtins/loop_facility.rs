@@ -0,0 +1,584 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+
+use super::{BuiltinFunc, RcEnv, Env, Object, EvalError, Params, PLAIN_REST_PARAMETERS, rcenv_get, list_items, caller_env};
+
+use crate::env::DynamicBindings;
+use crate::eval::{Eval, apply, eval_body, new_frame_id};
+use crate::package::designator_name;
+use super::control::{block_key, catch_exit};
+use super::lambda::{body_forms, function_designator};
+use super::arithmetics::{add, negate};
+use super::values::primary_value;
+
+type Tokens = Peekable<std::vec::IntoIter<Object>>;
+
+/// Name of a loop keyword, which may be any symbol with that name
+fn loop_keyword(obj: Option<&Object>) -> Option<String> {
+    match obj {
+        Some(o @ Object::Symbol(_)) => designator_name(o),
+        _ => None,
+    }
+}
+
+fn loop_error<S: AsRef<str>>(message: S) -> EvalError {
+    EvalError::LoopError(message.as_ref().to_string())
+}
+
+fn compare(x: &Object, y: &Object) -> Result<Ordering, EvalError> {
+    let ordering = match (x, y) {
+        (Object::Integer(x), Object::Integer(y)) => Some(x.cmp(y)),
+        (Object::Float(x), Object::Float(y)) => x.partial_cmp(y),
+        (Object::Integer(x), Object::Float(y)) => (*x as f64).partial_cmp(y),
+        (Object::Float(x), Object::Integer(y)) => x.partial_cmp(&(*y as f64)),
+        _ => None,
+    };
+    ordering.ok_or(EvalError::ParameterTypeMismatched)
+}
+
+/// How the variable of a `for` clause goes through its values
+enum Stepping {
+    In(Object, Option<Object>), // (Rest of the list, Step function)
+    On(Object, Option<Object>),
+    // Strings are the only vectors, they are gone through as one character strings
+    Across(Vec<Object>, usize),
+    Range {
+        current: Object,
+        step: Object,
+        limit: Option<(Object, bool)>, // (Limit, Inclusive)
+        down: bool,
+    },
+    Equals(Object, Option<Object>), // (Initial form, Then form)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Accumulation {
+    Collect,
+    Append,
+    Sum,
+    Count,
+    Maximize,
+    Minimize,
+}
+
+struct Accumulator {
+    kind: Accumulation,
+    items: Vec<Object>,
+    number: Option<Object>,
+}
+
+enum Clause {
+    For(Object, Stepping, bool), // (Variable pattern, Stepping, Started)
+    Repeat(isize),
+    While(Object, bool), // (Form, Until)
+    Do(Vec<Object>),
+    Return(Object),
+    Accumulate(Accumulation, Object, Option<String>),
+    Conditional(Object, bool, Vec<Clause>, Vec<Clause>), // (Test, Unless, Then, Else)
+}
+
+// What the loop does after a clause
+enum Flow {
+    Next,
+    Terminate,
+}
+
+/// Variables of a destructuring pattern
+fn pattern_variables(pattern: &Object, variables: &mut Vec<String>) {
+    match pattern {
+        Object::Symbol(s) => variables.push(s.clone()),
+        Object::List(l) => {
+            let (car, cdr) = l.unpack();
+            pattern_variables(&car, variables);
+            pattern_variables(&cdr, variables);
+        },
+        _ => (),
+    }
+}
+
+/// Assign a value to the variables of a pattern, the missing parts of the value are NIL
+fn assign(frame: &RcEnv, pattern: &Object, value: Object) -> Result<(), EvalError> {
+    match pattern {
+        Object::Symbol(s) => frame.borrow_mut().set_str(s, value)?,
+        Object::List(l) => {
+            let (car, cdr) = l.unpack();
+            let (value_car, value_cdr) = match value {
+                Object::List(v) => v.unpack(),
+                _ => (Object::Nil, Object::Nil),
+            };
+            assign(frame, &car, value_car)?;
+            assign(frame, &cdr, value_cdr)?;
+        },
+        _ => (),
+    }
+    Ok(())
+}
+
+const ACCUMULATIONS: &[(&str, Accumulation)] = &[
+    ("COLLECT", Accumulation::Collect), ("COLLECTING", Accumulation::Collect),
+    ("APPEND", Accumulation::Append), ("APPENDING", Accumulation::Append),
+    ("NCONC", Accumulation::Append), ("NCONCING", Accumulation::Append),
+    ("SUM", Accumulation::Sum), ("SUMMING", Accumulation::Sum),
+    ("COUNT", Accumulation::Count), ("COUNTING", Accumulation::Count),
+    ("MAXIMIZE", Accumulation::Maximize), ("MAXIMIZING", Accumulation::Maximize),
+    ("MINIMIZE", Accumulation::Minimize), ("MINIMIZING", Accumulation::Minimize),
+];
+
+/// A loop being run, in a frame holding its variables inside a block
+struct Loop {
+    frame: RcEnv,
+    bindings: DynamicBindings,
+    id: usize,
+    accumulators: Vec<(Option<String>, Accumulator)>,
+    initially: Vec<Object>,
+    finally: Vec<Object>,
+}
+
+impl Loop {
+    fn new(block: &RcEnv, id: usize) -> Self {
+        let frame = Env::inherit(block).wrap();
+        Self {
+            bindings: DynamicBindings::new(&frame),
+            frame,
+            id,
+            accumulators: vec![],
+            initially: vec![],
+            finally: vec![],
+        }
+    }
+
+    fn eval(&self, form: Object) -> Result<Object, EvalError> {
+        form.eval(&self.frame)
+    }
+
+    fn bind_pattern(&mut self, pattern: &Object) -> Result<(), EvalError> {
+        let mut variables = vec![];
+        pattern_variables(pattern, &mut variables);
+        for var in variables {
+            self.bindings.bind(&self.frame, var, Object::Nil)?;
+        }
+        Ok(())
+    }
+
+    /// Parse the clauses of the loop, evaluating the forms of `with` and `for` clauses in order
+    fn parse(&mut self, tokens: &mut Tokens) -> Result<Vec<Clause>, EvalError> {
+        let mut clauses = vec![];
+        while let Some(token) = tokens.next() {
+            let keyword = loop_keyword(Some(&token)).ok_or_else(|| loop_error(format!("{} is not a loop keyword", token)))?;
+            match keyword.as_str() {
+                "NAMED" => {
+                    let name = tokens.next().ok_or_else(|| loop_error("Missing loop name"))?;
+                    self.frame.borrow_mut().insert_str(block_key(&name), Object::Integer(self.id as isize));
+                },
+                "WITH" => loop {
+                    let var = tokens.next().ok_or_else(|| loop_error("Missing variable after WITH"))?;
+                    let value = if loop_keyword(tokens.peek()).as_deref() == Some("=") {
+                        tokens.next();
+                        self.eval(tokens.next().ok_or_else(|| loop_error("Missing form after ="))?)?
+                    } else {
+                        Object::Nil
+                    };
+                    self.bind_pattern(&var)?;
+                    assign(&self.frame, &var, value)?;
+                    if loop_keyword(tokens.peek()).as_deref() != Some("AND") {
+                        break;
+                    }
+                    tokens.next();
+                },
+                "FOR" | "AS" => loop {
+                    clauses.push(self.parse_for(tokens)?);
+                    if loop_keyword(tokens.peek()).as_deref() != Some("AND") {
+                        break;
+                    }
+                    tokens.next();
+                },
+                "REPEAT" => {
+                    let count = self.eval(tokens.next().ok_or_else(|| loop_error("Missing form after REPEAT"))?)?;
+                    match count {
+                        Object::Integer(n) => clauses.push(Clause::Repeat(n)),
+                        _ => return Err(EvalError::ParameterTypeMismatched),
+                    }
+                },
+                "WHILE" | "UNTIL" => {
+                    let form = tokens.next().ok_or_else(|| loop_error(format!("Missing form after {}", keyword)))?;
+                    clauses.push(Clause::While(form, keyword == "UNTIL"));
+                },
+                "INITIALLY" => self.initially.append(&mut compound_forms(tokens)),
+                "FINALLY" => self.finally.append(&mut compound_forms(tokens)),
+                _ => clauses.push(self.parse_selectable(&keyword, tokens)?),
+            }
+        }
+        Ok(clauses)
+    }
+
+    fn parse_for(&mut self, tokens: &mut Tokens) -> Result<Clause, EvalError> {
+        let var = tokens.next().ok_or_else(|| loop_error("Missing variable after FOR"))?;
+        self.bind_pattern(&var)?;
+        let preposition = loop_keyword(tokens.peek()).ok_or_else(|| loop_error(format!("Missing preposition after FOR {}", var)))?;
+        let stepping = match preposition.as_str() {
+            "IN" | "ON" => {
+                tokens.next();
+                let list = self.eval(tokens.next().ok_or_else(|| loop_error(format!("Missing form after {}", preposition)))?)?;
+                let by = if loop_keyword(tokens.peek()).as_deref() == Some("BY") {
+                    tokens.next();
+                    Some(function_designator(self.eval(tokens.next().ok_or_else(|| loop_error("Missing form after BY"))?)?)?)
+                } else {
+                    None
+                };
+                if preposition == "IN" { Stepping::In(list, by) } else { Stepping::On(list, by) }
+            },
+            "ACROSS" => {
+                tokens.next();
+                let items = match self.eval(tokens.next().ok_or_else(|| loop_error("Missing form after ACROSS"))?)? {
+                    Object::String(s) => s.chars().map(|c| Object::String(c.to_string())).collect(),
+                    o @ (Object::List(_) | Object::Nil) => list_items(o),
+                    _ => return Err(EvalError::ParameterTypeMismatched),
+                };
+                Stepping::Across(items, 0)
+            },
+            "=" => {
+                tokens.next();
+                let init = tokens.next().ok_or_else(|| loop_error("Missing form after ="))?;
+                let then = if loop_keyword(tokens.peek()).as_deref() == Some("THEN") {
+                    tokens.next();
+                    Some(tokens.next().ok_or_else(|| loop_error("Missing form after THEN"))?)
+                } else {
+                    None
+                };
+                Stepping::Equals(init, then)
+            },
+            _ => self.parse_range(tokens)?,
+        };
+        Ok(Clause::For(var, stepping, false))
+    }
+
+    /// Arithmetic stepping given by prepositions in any order
+    fn parse_range(&mut self, tokens: &mut Tokens) -> Result<Stepping, EvalError> {
+        let mut current = Object::Integer(0);
+        let mut step = Object::Integer(1);
+        let mut limit = None;
+        let mut down = false;
+        let mut found = false;
+        while let Some(preposition) = loop_keyword(tokens.peek()) {
+            if !["FROM", "UPFROM", "DOWNFROM", "TO", "UPTO", "BELOW", "DOWNTO", "ABOVE", "BY"].contains(&preposition.as_str()) {
+                break;
+            }
+            tokens.next();
+            found = true;
+            let value = self.eval(tokens.next().ok_or_else(|| loop_error(format!("Missing form after {}", preposition)))?)?;
+            match preposition.as_str() {
+                "FROM" | "UPFROM" => current = value,
+                "DOWNFROM" => {
+                    current = value;
+                    down = true;
+                },
+                "TO" | "UPTO" => limit = Some((value, true)),
+                "BELOW" => limit = Some((value, false)),
+                "DOWNTO" => {
+                    limit = Some((value, true));
+                    down = true;
+                },
+                "ABOVE" => {
+                    limit = Some((value, false));
+                    down = true;
+                },
+                _ => step = value,
+            }
+        }
+        if !found {
+            return Err(loop_error("Unknown FOR preposition"));
+        }
+        Ok(Stepping::Range { current, step, limit, down })
+    }
+
+    /// Clauses that can appear in conditionals
+    fn parse_selectable(&mut self, keyword: &str, tokens: &mut Tokens) -> Result<Clause, EvalError> {
+        if let Some((_, kind)) = ACCUMULATIONS.iter().find(|(name, _)| *name == keyword) {
+            let form = tokens.next().ok_or_else(|| loop_error(format!("Missing form after {}", keyword)))?;
+            let into = if loop_keyword(tokens.peek()).as_deref() == Some("INTO") {
+                tokens.next();
+                match tokens.next() {
+                    Some(Object::Symbol(var)) => Some(var),
+                    _ => return Err(loop_error("Missing variable after INTO")),
+                }
+            } else {
+                None
+            };
+            self.add_accumulator(*kind, &into)?;
+            return Ok(Clause::Accumulate(*kind, form, into));
+        }
+        match keyword {
+            "DO" | "DOING" => Ok(Clause::Do(compound_forms(tokens))),
+            "RETURN" => Ok(Clause::Return(tokens.next().ok_or_else(|| loop_error("Missing form after RETURN"))?)),
+            "WHEN" | "IF" | "UNLESS" => {
+                let test = tokens.next().ok_or_else(|| loop_error(format!("Missing form after {}", keyword)))?;
+                let then = self.parse_conjunction(tokens)?;
+                let otherwise = if loop_keyword(tokens.peek()).as_deref() == Some("ELSE") {
+                    tokens.next();
+                    self.parse_conjunction(tokens)?
+                } else {
+                    vec![]
+                };
+                if loop_keyword(tokens.peek()).as_deref() == Some("END") {
+                    tokens.next();
+                }
+                Ok(Clause::Conditional(test, keyword == "UNLESS", then, otherwise))
+            },
+            _ => Err(loop_error(format!("Unknown loop keyword {}", keyword))),
+        }
+    }
+
+    /// Selectable clauses joined by AND
+    fn parse_conjunction(&mut self, tokens: &mut Tokens) -> Result<Vec<Clause>, EvalError> {
+        let mut clauses = vec![];
+        loop {
+            let keyword = loop_keyword(tokens.next().as_ref()).ok_or_else(|| loop_error("Missing clause in conditional"))?;
+            clauses.push(self.parse_selectable(&keyword, tokens)?);
+            if loop_keyword(tokens.peek()).as_deref() != Some("AND") {
+                return Ok(clauses);
+            }
+            tokens.next();
+        }
+    }
+
+    fn add_accumulator(&mut self, kind: Accumulation, into: &Option<String>) -> Result<(), EvalError> {
+        if self.accumulators.iter().any(|(name, _)| name == into) {
+            return Ok(());
+        }
+        let accumulator = Accumulator { kind, items: vec![], number: None };
+        if let Some(var) = into {
+            self.bindings.bind(&self.frame, var, accumulator.value())?;
+        }
+        self.accumulators.push((into.clone(), accumulator));
+        Ok(())
+    }
+
+    fn accumulate(&mut self, kind: Accumulation, value: Object, into: &Option<String>) -> Result<(), EvalError> {
+        let accumulator = match self.accumulators.iter_mut().find(|(name, _)| name == into) {
+            Some((_, a)) => a,
+            None => return Err(loop_error("Unknown accumulation")),
+        };
+        match kind {
+            Accumulation::Collect => accumulator.items.push(value),
+            Accumulation::Append => accumulator.items.append(&mut list_items(value)),
+            Accumulation::Sum => accumulator.number = Some(add(accumulator.number.as_ref().unwrap_or(&Object::Integer(0)), &value)?),
+            Accumulation::Count => if value != Object::Nil {
+                accumulator.number = Some(add(accumulator.number.as_ref().unwrap_or(&Object::Integer(0)), &Object::Integer(1))?);
+            },
+            Accumulation::Maximize | Accumulation::Minimize => {
+                let replace = match &accumulator.number {
+                    Some(n) => compare(&value, n)? == if kind == Accumulation::Maximize { Ordering::Greater } else { Ordering::Less },
+                    None => true,
+                };
+                if replace {
+                    accumulator.number = Some(value);
+                }
+            },
+        }
+        if let Some(var) = into {
+            let value = accumulator.value();
+            self.frame.borrow_mut().set_str(var, value)?;
+        }
+        Ok(())
+    }
+
+    fn run_clause(&mut self, clause: &mut Clause) -> Result<Flow, EvalError> {
+        match clause {
+            Clause::For(var, stepping, started) => {
+                let first = !*started;
+                *started = true;
+                match self.step(stepping, first)? {
+                    Some(value) => assign(&self.frame, var, value)?,
+                    None => return Ok(Flow::Terminate),
+                }
+            },
+            Clause::Repeat(n) => {
+                if *n <= 0 {
+                    return Ok(Flow::Terminate);
+                }
+                *n -= 1;
+            },
+            Clause::While(form, until) => {
+                if (self.eval(form.clone())? == Object::Nil) != *until {
+                    return Ok(Flow::Terminate);
+                }
+            },
+            Clause::Do(forms) => {
+                for form in forms.iter() {
+                    self.eval(form.clone())?;
+                }
+            },
+            Clause::Return(form) => {
+                let values = form.clone().eval_values(&self.frame)?;
+                return Err(EvalError::Exit(self.id, values));
+            },
+            Clause::Accumulate(kind, form, into) => {
+                let value = self.eval(form.clone())?;
+                self.accumulate(*kind, value, into)?;
+            },
+            Clause::Conditional(test, unless, then, otherwise) => {
+                let branch = if (self.eval(test.clone())? != Object::Nil) != *unless { then } else { otherwise };
+                for clause in branch.iter_mut() {
+                    if let Flow::Terminate = self.run_clause(clause)? {
+                        return Ok(Flow::Terminate);
+                    }
+                }
+            },
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Next value of a `for` variable, if the iteration goes on
+    fn step(&self, stepping: &mut Stepping, first: bool) -> Result<Option<Object>, EvalError> {
+        let elements = matches!(stepping, Stepping::In(_, _));
+        match stepping {
+            Stepping::In(rest, by) | Stepping::On(rest, by) => {
+                if !first {
+                    let list = std::mem::replace(rest, Object::Nil);
+                    *rest = match by {
+                        Some(function) => apply(function.clone(), vec![list], &self.frame)?,
+                        None => match list {
+                            Object::List(l) => l.cdr(),
+                            _ => Object::Nil,
+                        },
+                    };
+                }
+                match rest {
+                    Object::List(l) if elements => Ok(Some(l.car())),
+                    Object::List(_) => Ok(Some(rest.clone())),
+                    _ => Ok(None),
+                }
+            },
+            Stepping::Across(items, index) => {
+                if !first {
+                    *index += 1;
+                }
+                Ok(items.get(*index).cloned())
+            },
+            Stepping::Range { current, step, limit, down } => {
+                if !first {
+                    let step = if *down { negate(step)? } else { step.clone() };
+                    *current = add(current, &step)?;
+                }
+                if let Some((limit, inclusive)) = limit {
+                    let ordering = compare(current, limit)?;
+                    let past = if *down { ordering == Ordering::Less } else { ordering == Ordering::Greater };
+                    if past || (!*inclusive && ordering == Ordering::Equal) {
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(current.clone()))
+            },
+            Stepping::Equals(init, then) => {
+                let form = match then {
+                    Some(then) if !first => then.clone(),
+                    _ => init.clone(),
+                };
+                Ok(Some(self.eval(form)?))
+            },
+        }
+    }
+
+    fn run(&mut self, mut clauses: Vec<Clause>) -> Result<Vec<Object>, EvalError> {
+        eval_body(body_forms(std::mem::take(&mut self.initially).into()), &self.frame)?;
+        'iterations: loop {
+            for clause in clauses.iter_mut() {
+                if let Flow::Terminate = self.run_clause(clause)? {
+                    break 'iterations;
+                }
+            }
+        }
+        eval_body(body_forms(std::mem::take(&mut self.finally).into()), &self.frame)?;
+        let result = self.accumulators.iter()
+            .find(|(name, _)| name.is_none())
+            .map(|(_, a)| a.value())
+            .unwrap_or(Object::Nil);
+        Ok(vec![result])
+    }
+}
+
+impl Accumulator {
+    fn value(&self) -> Object {
+        match self.kind {
+            Accumulation::Collect | Accumulation::Append => self.items.clone().into(),
+            Accumulation::Sum | Accumulation::Count => self.number.clone().unwrap_or(Object::Integer(0)),
+            Accumulation::Maximize | Accumulation::Minimize => self.number.clone().unwrap_or(Object::Nil),
+        }
+    }
+}
+
+/// Compound forms up to the next loop keyword
+fn compound_forms(tokens: &mut Tokens) -> Vec<Object> {
+    let mut forms = vec![];
+    while let Some(Object::List(_)) = tokens.peek() {
+        forms.extend(tokens.next());
+    }
+    forms
+}
+
+pub struct ObjectLoop;
+
+impl ObjectLoop {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let forms = list_items(rcenv_get!(env, "X")?);
+        let id = new_frame_id();
+        let block = Env::inherit(&caller_env(env)).wrap();
+        block.borrow_mut().insert_str(block_key(&Object::Nil), Object::Integer(id as isize));
+        // A simple loop only has compound forms and runs until something returns from it
+        if forms.iter().all(|f| matches!(f, Object::List(_))) {
+            let ret = loop {
+                if let Err(e) = forms.iter().try_for_each(|f| f.clone().eval(&block).map(|_| ())) {
+                    break Err(e);
+                }
+            };
+            return catch_exit(ret, id);
+        }
+        let mut state = Loop::new(&block, id);
+        let ret = state.parse(&mut forms.into_iter().peekable())
+            .and_then(|clauses| state.run(clauses));
+        catch_exit(ret, id)
+    }
+}
+
+impl BuiltinFunc for ObjectLoop {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "loop"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_loop() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(loop for x in '(1 2 3) collect (* x x))"), run("'(1 4 9)"));
+        assert_eq!(run("(loop for i from 1 to 10 by 3 collect i)"), run("'(1 4 7 10)"));
+        assert_eq!(run("(loop for i from 5 downto 3 for j below 10 collect (cons i j))"), run("'((5 . 0) (4 . 1) (3 . 2))"));
+        assert_eq!(run("(loop for l on '(1 2 3) append l)"), run("'(1 2 3 2 3 3)"));
+        assert_eq!(run("(loop for (a . b) in '((1 . 2) (3 . 4)) sum (* a b))"), Ok(Object::Integer(14)));
+        assert_eq!(run("(loop for c across \"ab\" collect c)"), run("'(\"a\" \"b\")"));
+        assert_eq!(run("(loop with n = 3 for i from 0 while (eql (floor i n) 0) count t)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(loop for x in '(3 8 1) maximize x into m finally (return m))"), Ok(Object::Integer(8)));
+        assert_eq!(run("(loop for x in '(1 2 3 4) when (eql (floor x 2) 1) collect x else collect 0)"), run("'(0 2 3 0)"));
+        assert_eq!(run("(loop for x in '(1 2 3) unless (eql x 2) do (setq x 0) and collect x)"), run("'(0 0)"));
+        assert_eq!(run("(loop for x = 1 then (* x 2) until (eql x 16) collect x)"), run("'(1 2 4 8)"));
+        assert_eq!(run("(loop for x in '(1 2 3) when (eql x 2) return (* x 10))"), Ok(Object::Integer(20)));
+        assert_eq!(run("(let ((n 0)) (loop (setq n (+ n 1)) (when (eql n 4) (return n))))"), Ok(Object::Integer(4)));
+        assert!(run("(loop for x frobnicate '(1))").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, symbol, guard_obj, rcenv_get, caller_env};
+use super::lambda::block_body;
+use super::types::declared_lambda;
+use super::values::primary_value;
+
+use crate::types::{Lambda, UserLambda};
+use crate::eval::expand_macro;
+
+builtin_parameters! {
+    static ref DEFMACRO_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref MACROEXPAND_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil)]);
+}
+
+/// Macro bound to a symbol, if any
+fn find_macro(name: &Object, env: &RcEnv) -> Option<UserLambda> {
+    let name = match name {
+        Object::Symbol(s) if !s.starts_with(':') => s.clone(),
+        _ => return None,
+    };
+    match env.borrow().get_str(name) {
+        Ok(Object::Lambda(l)) => match *l {
+            Lambda::Macro(_, m) => Some(m),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Expand a form once, returning whether it was a macro call
+pub(crate) fn macroexpand_1(form: Object, env: &RcEnv) -> Result<(Object, bool), EvalError> {
+    let lambda = match &form {
+        Object::List(l) => find_macro(&l.car(), env),
+        _ => None,
+    };
+    match lambda {
+        Some(m) => Ok((expand_macro(m, form, env)?, true)),
+        None => Ok((form, false)),
+    }
+}
+
+pub struct ObjectDefmacro;
+
+impl BuiltinFunc for ObjectDefmacro {
+    fn get_parameters(&self) -> &Params {
+        &DEFMACRO_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defmacro"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let p = Params::destructuring(rcenv_get!(env, "Y")?)?;
+        let body = block_body(&name, rcenv_get!(env, "Z")?);
+
+        let expander = declared_lambda(p, body)?.with_env(&caller_env(env));
+        env.borrow_mut().insert_global(&symbol(&name), Lambda::Macro(name.clone(), expander).into());
+
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectMacroexpand1;
+
+impl ObjectMacroexpand1 {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let form = rcenv_get!(env, "X")?;
+        let (expansion, expanded) = macroexpand_1(form, &caller_env(env))?;
+        Ok(vec![expansion, if expanded { Object::T } else { Object::Nil }])
+    }
+}
+
+impl BuiltinFunc for ObjectMacroexpand1 {
+    fn get_parameters(&self) -> &Params {
+        &MACROEXPAND_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "macroexpand-1"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+pub struct ObjectMacroexpand;
+
+impl ObjectMacroexpand {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let mut form = rcenv_get!(env, "X")?;
+        let mut expanded = false;
+        loop {
+            let (expansion, again) = macroexpand_1(form, &caller)?;
+            form = expansion;
+            if !again {
+                break;
+            }
+            expanded = true;
+        }
+        Ok(vec![form, if expanded { Object::T } else { Object::Nil }])
+    }
+}
+
+impl BuiltinFunc for ObjectMacroexpand {
+    fn get_parameters(&self) -> &Params {
+        &MACROEXPAND_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "macroexpand"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+/// Expansion function of a macro, called with the form and an environment
+struct MacroFunction {
+    name: String,
+    lambda: UserLambda,
+}
+
+impl BuiltinFunc for MacroFunction {
+    fn get_parameters(&self) -> &Params {
+        &MACROEXPAND_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        expand_macro(self.lambda.clone(), rcenv_get!(env, "X")?, &caller_env(env))
+    }
+}
+
+pub struct ObjectMacroFunction;
+
+impl BuiltinFunc for ObjectMacroFunction {
+    fn get_parameters(&self) -> &Params {
+        &MACROEXPAND_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "macro-function"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = rcenv_get!(env, "X")?;
+        match find_macro(&name, &caller_env(env)) {
+            Some(lambda) => Ok(Object::from(MacroFunction { name: name.to_string(), lambda })),
+            None => Ok(Object::Nil),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_defmacro() {
+        let (_, run) = interpreter();
+        run("(defmacro swap-args ((f a b)) (cons f (cons b (cons a nil))))").unwrap();
+        assert_eq!(run("(swap-args (cons 1 2))"), run("(cons 2 1)"));
+        run("(defmacro my-when (test &body body) (cons 'if (cons test (cons (cons 'progn body) nil))))").unwrap();
+        assert_eq!(run("(my-when t 1 2)"), Ok(Object::Integer(2)));
+        assert_eq!(run("(macroexpand-1 '(my-when t 1))"), run("'(if t (progn 1))"));
+        assert_eq!(run("(macroexpand-1 '(car x))"), run("'(car x)"));
+        assert_eq!(run("(defmacro w (&whole form &optional (n 3)) (cons 'progn (cons n (cdr form)))) (w)"), Ok(Object::Integer(3)));
+        assert_eq!(run("((macro-function 'w) '(w 1) nil)"), run("'(progn 1 1)"));
+        assert_eq!(run("(macro-function 'car)"), Ok(Object::Nil));
+        assert!(run("(swap-args (f a))").is_err());
+    }
+
+    #[test]
+    fn test_backquote() {
+        let (_, run) = interpreter();
+        run("(defmacro my-unless (test &body body) `(if ,test nil (progn ,@body)))").unwrap();
+        assert_eq!(run("(my-unless nil 1 2)"), Ok(Object::Integer(2)));
+        assert_eq!(run("(macroexpand-1 '(my-unless x 1 2))"), run("'(if x nil (progn 1 2))"));
+        run("(defmacro with-counter ((var start) &body body) `(let ((,var ,start)) ,@body ,var))").unwrap();
+        assert_eq!(run("(with-counter (n 1) (setq n (+ n 1)) (setq n (* n 10)))"), Ok(Object::Integer(20)));
+        run("(defmacro quoted (x) `',x)").unwrap();
+        assert_eq!(run("(quoted (a b))"), run("'(a b)"));
+        let (x, y) = (run("(let ((x 1) (y '(2 3))) `(x ,x ,@y . ,x))"), run("'(x 1 2 3 . 1)"));
+        assert_eq!(x, y);
+        assert_eq!(run("`(1 ,@nil 2)"), run("(list 1 2)"));
+        assert_eq!(run("(append '(1) nil '(2 3) 4)"), run("'(1 2 3 . 4)"));
+    }
+}
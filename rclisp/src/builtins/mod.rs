@@ -6,33 +6,94 @@ pub mod predicates;
 pub mod setq;
 pub mod list;
 pub mod conditional;
-
-use lazy_static::lazy_static;
+pub mod package;
+pub mod structure;
+pub mod setf;
+pub mod clos;
+pub mod values;
+pub mod hash_table;
+pub mod reader;
+pub mod types;
+pub mod format;
+pub mod condition;
+pub mod variable;
+pub mod control;
+pub mod iteration;
+pub mod loop_facility;
+pub mod macros;
+pub mod stream;
+pub mod file;
+pub mod load;
 
 pub use crate::types::{Object, BuiltinFunc, List, Params, Parameter, cons, nil, symbol, get_list};
-pub use crate::env::{Env, RcEnv};
+pub use crate::env::{Env, RcEnv, FunctionName};
 pub use crate::eval::EvalError;
+pub use crate::package::{COMMON_LISP, COMMON_LISP_USER};
 pub use crate::generate_symbol_list;
 
-pub use arithmetics::{ObjectAdd, ObjectSub, ObjectMul, ObjectDiv};
+pub use arithmetics::{ObjectAdd, ObjectSub, ObjectMul, ObjectDiv, ObjectFloor};
 pub use quote::ObjectQuote;
 pub use print::{ObjectPrint, ObjectPrinc, ObjectTerpri};
-pub use lambda::{ObjectDefun, ObjectLambda, ObjectFuncall};
-pub use predicates::{ObjectSymbolp, ObjectNumberp, ObjectStringp, ObjectAtom, ObjectListp, ObjectNull, ObjectEq, ObjectOr};
-pub use setq::ObjectSetq;
-pub use list::{ObjectCons, ObjectCar, ObjectCdr};
-pub use conditional::ObjectCond;
+pub use lambda::{ObjectDefun, ObjectLambda, ObjectFlet, ObjectLabels, ObjectFunction, ObjectFuncall, ObjectApply, ObjectEval, ObjectCompile, ObjectFunctionLambdaExpression};
+pub use predicates::{ObjectSymbolp, ObjectNumberp, ObjectStringp, ObjectAtom, ObjectListp, ObjectNull, ObjectEq, ObjectEql, ObjectEqual, ObjectEqualp, ObjectOr, ObjectAnd, ObjectNot, ObjectConsp, ObjectIntegerp, ObjectFloatp, ObjectKeywordp, ObjectHashTableP, ObjectStreamp, ObjectFunctionp, ObjectConstantp};
+pub use setq::{ObjectSetq, ObjectPsetq};
+pub use list::{ObjectCons, ObjectList, ObjectAppend, ObjectCar, ObjectCdr, ObjectNth, ObjectMapcar, ObjectMapc, ObjectMapcan, ObjectMaplist, ObjectRemoveIf, ObjectRemoveIfNot, ObjectReduce};
+pub use conditional::{ObjectCond, ObjectIf, ObjectWhen, ObjectUnless};
+pub use control::{ObjectProgn, ObjectLet, ObjectLetStar, ObjectDestructuringBind, ObjectBlock, ObjectReturnFrom, ObjectReturn, ObjectTagbody, ObjectGo, ObjectCatch, ObjectThrow, ObjectUnwindProtect};
+pub use structure::ObjectDefstruct;
+pub use setf::{ObjectSetf, ObjectDefsetf, ObjectDefineSetfExpander, ObjectIncf, ObjectDecf, ObjectPush, ObjectPop, ObjectPushnew, ObjectRotatef, ObjectShiftf};
+pub use clos::{ObjectDefclass, ObjectMakeInstance, ObjectSlotValue, ObjectSetfSlotValue, ObjectDefgeneric, ObjectDefmethod};
+pub use values::{ObjectValues, ObjectValuesList, ObjectMultipleValueBind, ObjectMultipleValueList, ObjectMultipleValueCall, ObjectNthValue};
+pub use hash_table::{ObjectMakeHashTable, ObjectGethash, ObjectSetfGethash, ObjectRemhash, ObjectClrhash, ObjectHashTableCount, ObjectMaphash};
+pub use reader::ObjectReadFromString;
+pub use format::ObjectFormat;
+pub use types::{ObjectTypep, ObjectTypeOf, ObjectSubtypep, ObjectDeftype, ObjectCoerce, ObjectThe, ObjectProclaim, ObjectDeclaim};
+pub use condition::{ObjectDefineCondition, ObjectMakeCondition, ObjectError, ObjectSignal, ObjectWarn, ObjectHandlerBind, ObjectHandlerCase, ObjectIgnoreErrors, ObjectRestartCase, ObjectInvokeRestart, ObjectAssert, ObjectCheckType};
+pub use iteration::{ObjectDolist, ObjectDotimes, ObjectDo, ObjectDoStar};
+pub use loop_facility::ObjectLoop;
+pub use macros::{ObjectDefmacro, ObjectMacroexpand1, ObjectMacroexpand, ObjectMacroFunction};
+pub use stream::{ObjectWithOutputToString, ObjectMakeStringOutputStream, ObjectGetOutputStreamString, ObjectMakeStringInputStream, ObjectWriteString, ObjectWriteChar, ObjectFreshLine, ObjectFinishOutput, ObjectReadChar, ObjectReadLine, ObjectWriteLine, ObjectClose};
+pub use file::{ObjectOpen, ObjectWithOpenFile, ObjectFileLength, ObjectProbeFile, ObjectDirectory, ObjectDeleteFile, ObjectRenameFile, ObjectEnsureDirectoriesExist};
+pub use load::{ObjectLoad, ObjectProvide, ObjectRequire};
+pub use variable::{ObjectDefvar, ObjectDefparameter, ObjectDefconstant, ObjectSymbolValue, ObjectSetfSymbolValue};
+pub use package::{ObjectDefpackage, ObjectInPackage, ObjectFindPackage, ObjectExport, ObjectDoExternalSymbols};
+
+pub use crate::{builtin_parameters, guard_obj, rcenv_get};
 
-pub use crate::{guard_obj, rcenv_get};
 
-lazy_static! {
+builtin_parameters! {
     static ref EMPTY_PARAMETERS: Params = Params::from(vec![]);
     static ref REST_PARAMETERS: Params = Params::from(vec![Parameter::rest("X")]);
+    static ref PLAIN_REST_PARAMETERS: Params = Params::from(vec![Parameter::plain_rest("X")]);
     static ref UNARY_PARAMETERS: Params = Params::from(vec![Parameter::normal("X")]);
     static ref BINARY_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y")]);
     static ref TERNARY_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::normal("Y"), Parameter::normal("Z")]);
 }
 
+/// Parameter tables of builtins, each thread building its own copy on first use, since the
+/// default values of parameters are objects that cannot be shared between threads
+#[macro_export]
+macro_rules! builtin_parameters {
+    ($($vis:vis static ref $name:ident: Params = $params:expr;)*) => {
+        $(
+            #[allow(non_camel_case_types)]
+            $vis struct $name;
+
+            impl std::ops::Deref for $name {
+                type Target = $crate::types::Params;
+
+                fn deref(&self) -> &$crate::types::Params {
+                    // Leaked once per thread so that builtins can hand out plain references
+                    thread_local! {
+                        static PARAMETERS: &'static $crate::types::Params = Box::leak(Box::new($params));
+                    }
+                    PARAMETERS.with(|p| *p)
+                }
+            }
+        )*
+    };
+}
+
 #[macro_export]
 macro_rules! guard_obj {
     ($obj:expr, $variant:ident) => (
@@ -44,12 +105,29 @@ macro_rules! guard_obj {
     );
 }
 
+/// Elements of a list, a non-list object counts as a single element
+pub(crate) fn list_items(obj: Object) -> Vec<Object> {
+    match obj {
+        Object::List(l) => l.into_iter().collect(),
+        Object::Nil => vec![],
+        o => vec![o],
+    }
+}
+
+/// Environment a builtin was called from, skipping the frame holding its parameters
+pub(crate) fn caller_env(env: &RcEnv) -> RcEnv {
+    env.borrow().get_parent().unwrap_or_else(|| env.clone())
+}
+
 macro_rules! insert_builtin {
     ($env:ident, $($x:ident),+) => {
         $({
             let name = symbol($x.get_name());
             let lambda = Object::from($x);
             $env.borrow_mut().insert(&name, lambda);
+            $env.borrow().get_packages().borrow_mut()
+                .export(COMMON_LISP, $x.get_name().to_uppercase())
+                .expect("COMMON-LISP package is missing");
         });+
     };
 }
@@ -67,7 +145,11 @@ pub fn generate_default_env() -> RcEnv {
         ObjectTerpri,
         ObjectDefun,
         ObjectLambda,
+        ObjectFlet,
+        ObjectLabels,
+        ObjectFunction,
         ObjectFuncall,
+        ObjectApply,
         ObjectSymbolp,
         ObjectNumberp,
         ObjectStringp,
@@ -75,12 +157,189 @@ pub fn generate_default_env() -> RcEnv {
         ObjectListp,
         ObjectNull,
         ObjectEq,
+        ObjectEql,
+        ObjectEqual,
+        ObjectEqualp,
         ObjectOr,
         ObjectSetq,
+        ObjectPsetq,
         ObjectCons,
+        ObjectList,
+        ObjectAppend,
         ObjectCar,
         ObjectCdr,
-        ObjectCond
+        ObjectNth,
+        ObjectMapcar,
+        ObjectMapc,
+        ObjectMapcan,
+        ObjectMaplist,
+        ObjectRemoveIf,
+        ObjectRemoveIfNot,
+        ObjectReduce,
+        ObjectCond,
+        ObjectDefpackage,
+        ObjectInPackage,
+        ObjectFindPackage,
+        ObjectExport,
+        ObjectDoExternalSymbols,
+        ObjectDefstruct,
+        ObjectSetf,
+        ObjectDefsetf,
+        ObjectDefineSetfExpander,
+        ObjectIncf,
+        ObjectDecf,
+        ObjectPush,
+        ObjectPop,
+        ObjectPushnew,
+        ObjectRotatef,
+        ObjectShiftf,
+        ObjectDefclass,
+        ObjectMakeInstance,
+        ObjectSlotValue,
+        ObjectDefgeneric,
+        ObjectDefmethod,
+        ObjectFloor,
+        ObjectValues,
+        ObjectValuesList,
+        ObjectMultipleValueBind,
+        ObjectMultipleValueList,
+        ObjectMultipleValueCall,
+        ObjectNthValue,
+        ObjectMakeHashTable,
+        ObjectGethash,
+        ObjectRemhash,
+        ObjectClrhash,
+        ObjectHashTableCount,
+        ObjectMaphash,
+        ObjectReadFromString,
+        ObjectFormat,
+        ObjectDefineCondition,
+        ObjectMakeCondition,
+        ObjectError,
+        ObjectSignal,
+        ObjectWarn,
+        ObjectHandlerBind,
+        ObjectHandlerCase,
+        ObjectIgnoreErrors,
+        ObjectRestartCase,
+        ObjectInvokeRestart,
+        ObjectAssert,
+        ObjectCheckType,
+        ObjectConsp,
+        ObjectIntegerp,
+        ObjectFloatp,
+        ObjectKeywordp,
+        ObjectHashTableP,
+        ObjectStreamp,
+        ObjectTypep,
+        ObjectTypeOf,
+        ObjectSubtypep,
+        ObjectDeftype,
+        ObjectCoerce,
+        ObjectThe,
+        ObjectProclaim,
+        ObjectDeclaim,
+        ObjectDefvar,
+        ObjectDefparameter,
+        ObjectDefconstant,
+        ObjectSymbolValue,
+        ObjectIf,
+        ObjectWhen,
+        ObjectUnless,
+        ObjectAnd,
+        ObjectNot,
+        ObjectProgn,
+        ObjectLet,
+        ObjectLetStar,
+        ObjectDestructuringBind,
+        ObjectBlock,
+        ObjectReturnFrom,
+        ObjectReturn,
+        ObjectTagbody,
+        ObjectGo,
+        ObjectCatch,
+        ObjectThrow,
+        ObjectUnwindProtect,
+        ObjectDolist,
+        ObjectDotimes,
+        ObjectDo,
+        ObjectDoStar,
+        ObjectLoop,
+        ObjectDefmacro,
+        ObjectMacroexpand1,
+        ObjectMacroexpand,
+        ObjectMacroFunction,
+        ObjectWithOutputToString,
+        ObjectMakeStringOutputStream,
+        ObjectGetOutputStreamString,
+        ObjectMakeStringInputStream,
+        ObjectWriteString,
+        ObjectWriteChar,
+        ObjectFreshLine,
+        ObjectFinishOutput,
+        ObjectReadChar,
+        ObjectReadLine,
+        ObjectWriteLine,
+        ObjectClose,
+        ObjectOpen,
+        ObjectWithOpenFile,
+        ObjectFileLength,
+        ObjectProbeFile,
+        ObjectDirectory,
+        ObjectDeleteFile,
+        ObjectRenameFile,
+        ObjectEnsureDirectoriesExist,
+        ObjectLoad,
+        ObjectProvide,
+        ObjectRequire,
+        ObjectEval,
+        ObjectCompile,
+        ObjectFunctionLambdaExpression,
+        ObjectFunctionp,
+        ObjectConstantp
     );
+    env.borrow_mut().insert_function(&FunctionName::Setf("SLOT-VALUE".into()), Object::from(ObjectSetfSlotValue));
+    env.borrow_mut().insert_function(&FunctionName::Setf("GETHASH".into()), Object::from(ObjectSetfGethash));
+    env.borrow_mut().insert_function(&FunctionName::Setf("SYMBOL-VALUE".into()), Object::from(ObjectSetfSymbolValue));
+    env.borrow_mut().insert_str("*PACKAGE*", Object::Package(COMMON_LISP_USER.into()));
+    env.borrow_mut().proclaim_special("*PACKAGE*").expect("*PACKAGE* is a constant");
+    stream::define_standard_streams(&env);
+    load::define_load_variables(&env);
+    let readers = [
+        ("SIMPLE-CONDITION-FORMAT-CONTROL", "SIMPLE-CONDITION", "FORMAT-CONTROL"),
+        ("SIMPLE-CONDITION-FORMAT-ARGUMENTS", "SIMPLE-CONDITION", "FORMAT-ARGUMENTS"),
+        ("TYPE-ERROR-DATUM", "TYPE-ERROR", "DATUM"),
+        ("TYPE-ERROR-EXPECTED-TYPE", "TYPE-ERROR", "EXPECTED-TYPE"),
+        ("CELL-ERROR-NAME", "CELL-ERROR", "NAME"),
+        ("FILE-ERROR-PATHNAME", "FILE-ERROR", "PATHNAME"),
+    ];
+    for (reader, class, slot) in readers {
+        clos::define_reader(&env, reader, class, slot).expect("Condition readers take one argument");
+    }
+    let classes: Vec<String> = env.borrow().get_classes().borrow().names();
+    let restarts = ["MUFFLE-WARNING", "CONTINUE", "STORE-VALUE", "USE-VALUE", "ABORT"];
+    let symbols = ["*PACKAGE*", "CALL-NEXT-METHOD", "NEXT-METHOD-P", "PRINT-OBJECT", "DECLARE"].iter()
+        .chain(readers.iter().map(|(r, _, _)| r))
+        .chain(restarts.iter())
+        .map(|s| s.to_string())
+        .chain(classes);
+    for s in symbols {
+        env.borrow().get_packages().borrow_mut()
+            .export(COMMON_LISP, s)
+            .expect("COMMON-LISP package is missing");
+    }
     env
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{generate_default_env, RcEnv, Object, EvalError};
+    use crate::interpret;
+
+    /// A fresh default environment, and a function evaluating source code in it
+    pub(crate) fn interpreter() -> (RcEnv, impl Fn(&str) -> Result<Object, EvalError>) {
+        let env = generate_default_env();
+        let inner = env.clone();
+        (env, move |source: &str| interpret(source.as_bytes(), &inner))
+    }
+}
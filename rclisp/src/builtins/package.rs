@@ -0,0 +1,203 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, Params, Parameter, UNARY_PARAMETERS, rcenv_get, guard_obj, caller_env};
+
+use crate::eval::Eval;
+use crate::package::designator_name;
+
+builtin_parameters! {
+    static ref DEFPACKAGE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref IN_PACKAGE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X")]);
+    static ref EXPORT_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil)]);
+    static ref DO_SYMBOLS_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+}
+
+fn package_name(obj: &Object) -> Result<String, EvalError> {
+    designator_name(obj).ok_or(EvalError::ParameterTypeMismatched)
+}
+
+fn find_package(env: &RcEnv, designator: &Object) -> Result<String, EvalError> {
+    let name = package_name(designator)?;
+    let packages = env.borrow().get_packages();
+    let packages = packages.borrow();
+    let package = packages.find(&name).ok_or(EvalError::PackageNotFound(name))?;
+    Ok(package.get_name().to_string())
+}
+
+pub struct ObjectDefpackage;
+
+impl BuiltinFunc for ObjectDefpackage {
+    fn get_parameters(&self) -> &Params {
+        &DEFPACKAGE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defpackage"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = package_name(&rcenv_get!(env, "X")?)?;
+        let mut options = rcenv_get!(env, "Y")?;
+        let packages = env.borrow().get_packages();
+        let mut packages = packages.borrow_mut();
+        let name = packages.define(name);
+        while options != Object::Nil {
+            let (option, rest) = guard_obj!(options, List)?.unpack();
+            let (key, args) = guard_obj!(option, List)?.unpack();
+            let args = if let Object::List(l) = args { l.to_list() } else { super::List::EndsWith(Object::Nil) };
+            for arg in args {
+                let arg = package_name(&arg)?;
+                match package_name(&key)?.as_str() {
+                    "USE" => packages.use_package(&name, arg)?,
+                    "EXPORT" => { packages.export(&name, arg)?; },
+                    "SHADOW" => { packages.shadow(&name, arg)?; },
+                    "NICKNAMES" => packages.add_nickname(&name, arg)?,
+                    _ => return Err(EvalError::ParameterTypeMismatched),
+                }
+            }
+            options = rest;
+        }
+        Ok(Object::Package(name))
+    }
+}
+
+pub struct ObjectInPackage;
+
+impl BuiltinFunc for ObjectInPackage {
+    fn get_parameters(&self) -> &Params {
+        &IN_PACKAGE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "in-package"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = find_package(env, &rcenv_get!(env, "X")?)?;
+        env.borrow_mut().insert_global_str("*PACKAGE*", Object::Package(name.clone()));
+        Ok(Object::Package(name))
+    }
+}
+
+pub struct ObjectFindPackage;
+
+impl BuiltinFunc for ObjectFindPackage {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "find-package"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        match find_package(env, &rcenv_get!(env, "X")?) {
+            Ok(name) => Ok(Object::Package(name)),
+            Err(EvalError::PackageNotFound(_)) => Ok(Object::Nil),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub struct ObjectExport;
+
+impl BuiltinFunc for ObjectExport {
+    fn get_parameters(&self) -> &Params {
+        &EXPORT_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "export"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let symbols = rcenv_get!(env, "X")?;
+        let package = match rcenv_get!(env, "Y")? {
+            Object::Nil => env.borrow().current_package(),
+            designator => find_package(env, &designator)?,
+        };
+        let symbols = match symbols {
+            Object::List(l) => l.into_iter().collect(),
+            Object::Nil => vec![],
+            obj => vec![obj],
+        };
+        let packages = env.borrow().get_packages();
+        for symbol in symbols {
+            packages.borrow_mut().export(&package, package_name(&symbol)?)?;
+        }
+        Ok(Object::T)
+    }
+}
+
+pub struct ObjectDoExternalSymbols;
+
+impl BuiltinFunc for ObjectDoExternalSymbols {
+    fn get_parameters(&self) -> &Params {
+        &DO_SYMBOLS_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "do-external-symbols"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let (var, spec) = guard_obj!(rcenv_get!(env, "X")?, List)?.unpack();
+        let var = guard_obj!(var, Symbol)?;
+        let (package, result) = match spec {
+            Object::List(l) => l.unpack(),
+            _ => (Object::Nil, Object::Nil),
+        };
+        let package = match package.eval(&caller)? {
+            Object::Nil => caller.borrow().current_package(),
+            designator => find_package(env, &designator)?,
+        };
+        let symbols = {
+            let packages = env.borrow().get_packages();
+            let packages = packages.borrow();
+            packages.find(&package).map(|p| p.external_symbols()).unwrap_or_default()
+        };
+        let body: Vec<Object> = match rcenv_get!(env, "Y")? {
+            Object::List(l) => l.into_iter().collect(),
+            _ => vec![],
+        };
+        let frame = Env::inherit(&caller).wrap();
+        for symbol in symbols {
+            frame.borrow_mut().insert_str(&var, Object::Symbol(symbol));
+            for form in body.iter() {
+                form.clone().eval(&frame)?;
+            }
+        }
+        frame.borrow_mut().insert_str(&var, Object::Nil);
+        match result {
+            Object::List(l) => l.car().eval(&frame),
+            _ => Ok(Object::Nil),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_packages() {
+        let (_, run) = interpreter();
+        run("(defpackage :geometry (:use :common-lisp) (:nicknames :geo) (:export area))").unwrap();
+        run("(in-package :geometry)").unwrap();
+        run("(defun area (r) (* (scale) (* r r))) (defun scale () 3)").unwrap();
+        assert_eq!(run("(area 2)"), Ok(Object::Integer(12)));
+        run("(export 'perimeter) (defun perimeter (r) (* 6 r))").unwrap();
+        run("(in-package :cl-user)").unwrap();
+        assert_eq!(run("(geometry:area 1)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(geo:perimeter 2)"), Ok(Object::Integer(12)));
+        assert_eq!(run("(geometry::scale)"), Ok(Object::Integer(3)));
+        assert!(run("(geometry:scale)").is_err());
+        assert!(run("(area 1)").is_err());
+        assert_eq!(run("(eq 'geometry::area 'geo:area)"), Ok(Object::T));
+        run("(defpackage :cl-user (:shadow car))").unwrap();
+        run("(defun car (x) (cons :mine x))").unwrap();
+        assert_eq!(run("(car 1)"), run("'(:mine . 1)"));
+        assert_eq!(run("(cl:car '(1 2))"), Ok(Object::Integer(1)));
+        assert_eq!(run("(mapcar #'cdr '((1 . 2)))"), run("'(2)"));
+    }
+}
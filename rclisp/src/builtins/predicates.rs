@@ -1,9 +1,14 @@
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, REST_PARAMETERS, UNARY_PARAMETERS, BINARY_PARAMETERS, rcenv_get, guard_obj};
+use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, PLAIN_REST_PARAMETERS, UNARY_PARAMETERS, BINARY_PARAMETERS, rcenv_get, symbol, list_items, caller_env};
 
 use crate::eval::Eval;
+use crate::types::quote;
+use crate::types::equality::{eq, eql, equal, equalp};
+use crate::env::VariableKind;
+use crate::package::designator_name;
+use super::types::{typep, boolean};
 
 macro_rules! generate_type_predicates {
-    ($struct:ident, $name:expr, $type:ident) => (
+    ($struct:ident, $name:expr, $type:expr) => (
         pub struct $struct;
 
         impl BuiltinFunc for $struct {
@@ -16,7 +21,8 @@ macro_rules! generate_type_predicates {
             }
 
             fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-                if let Ok(_) = guard_obj!(rcenv_get!(env, "X")?, $type) {
+                let x = rcenv_get!(env, "X")?;
+                if typep(env, &x, &symbol($type))? {
                     Ok(Object::T)
                 } else {
                     Ok(Object::Nil)
@@ -26,114 +32,191 @@ macro_rules! generate_type_predicates {
     );
 }
 
-generate_type_predicates!(ObjectSymbolp, "symbolp", Symbol);
-generate_type_predicates!(ObjectStringp, "stringp", String);
-generate_type_predicates!(ObjectListp, "listp", List);
+generate_type_predicates!(ObjectSymbolp, "symbolp", "SYMBOL");
+generate_type_predicates!(ObjectStringp, "stringp", "STRING");
+generate_type_predicates!(ObjectListp, "listp", "LIST");
+generate_type_predicates!(ObjectConsp, "consp", "CONS");
+generate_type_predicates!(ObjectNumberp, "numberp", "NUMBER");
+generate_type_predicates!(ObjectIntegerp, "integerp", "INTEGER");
+generate_type_predicates!(ObjectFloatp, "floatp", "FLOAT");
+generate_type_predicates!(ObjectKeywordp, "keywordp", "KEYWORD");
+generate_type_predicates!(ObjectHashTableP, "hash-table-p", "HASH-TABLE");
+generate_type_predicates!(ObjectStreamp, "streamp", "STREAM");
+generate_type_predicates!(ObjectFunctionp, "functionp", "FUNCTION");
+generate_type_predicates!(ObjectAtom, "atom", "ATOM");
+
+/// Whether a form always evaluates to the same value: self-evaluating objects, constant
+/// variables and quoted forms
+fn constant_form(form: &Object, env: &RcEnv) -> bool {
+    match form {
+        Object::Symbol(s) if s.starts_with(':') => true,
+        Object::Symbol(s) => env.borrow().variable_kind(s) == Some(VariableKind::Constant),
+        Object::Quote(_) => true,
+        Object::List(l) => designator_name(&l.car()).as_deref() == Some("QUOTE"),
+        _ => true,
+    }
+}
 
-pub struct ObjectNumberp;
+pub struct ObjectConstantp;
 
-impl BuiltinFunc for ObjectNumberp {
+impl BuiltinFunc for ObjectConstantp {
     fn get_parameters(&self) -> &Params {
         &UNARY_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
-        "numberp"
+        "constantp"
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let x = rcenv_get!(env, "X")?;
-        match x {
-            Object::Integer(_) | Object::Float(_) => Ok(Object::T),
-            _ => Ok(Object::Nil),
-        }
+        let form = rcenv_get!(env, "X")?;
+        Ok(boolean(constant_form(&form, env)))
     }
 }
 
-pub struct ObjectAtom;
+pub struct ObjectNull;
 
-impl BuiltinFunc for ObjectAtom {
+impl BuiltinFunc for ObjectNull {
     fn get_parameters(&self) -> &Params {
         &UNARY_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
-        "atom"
+        "null"
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
         let x = rcenv_get!(env, "X")?;
         match x {
-            Object::List(_) => Ok(Object::T),
-            _ => Ok(Object::T),
+            Object::Nil => Ok(Object::T),
+            _ => Ok(Object::Nil),
         }
     }
 }
 
-pub struct ObjectNull;
+macro_rules! generate_equality_predicates {
+    ($struct:ident, $name:expr, $test:ident) => (
+        pub struct $struct;
 
-impl BuiltinFunc for ObjectNull {
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &BINARY_PARAMETERS
+            }
+
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                let x = rcenv_get!(env, "X")?;
+                let y = rcenv_get!(env, "Y")?;
+                if $test(&x, &y) {
+                    Ok(Object::T)
+                } else {
+                    Ok(Object::Nil)
+                }
+            }
+        }
+    );
+}
+
+generate_equality_predicates!(ObjectEq, "eq", eq);
+generate_equality_predicates!(ObjectEql, "eql", eql);
+generate_equality_predicates!(ObjectEqual, "equal", equal);
+generate_equality_predicates!(ObjectEqualp, "equalp", equalp);
+
+pub struct ObjectNot;
+
+impl BuiltinFunc for ObjectNot {
     fn get_parameters(&self) -> &Params {
         &UNARY_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
-        "null"
+        "not"
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let x = rcenv_get!(env, "X")?;
-        match x {
+        match rcenv_get!(env, "X")? {
             Object::Nil => Ok(Object::T),
             _ => Ok(Object::Nil),
         }
     }
 }
 
-pub struct ObjectEq;
+pub struct ObjectOr;
 
-impl BuiltinFunc for ObjectEq {
+impl ObjectOr {
+    /// Form giving the result: the first true value quoted, or else the last form
+    fn select_form(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let mut forms = list_items(rcenv_get!(env, "X")?);
+        let Some(last) = forms.pop() else {
+            return Ok(Object::Nil);
+        };
+        for form in forms {
+            let value = form.eval(&caller)?;
+            if value != Object::Nil {
+                return Ok(quote(value));
+            }
+        }
+        Ok(last)
+    }
+}
+
+impl BuiltinFunc for ObjectOr {
     fn get_parameters(&self) -> &Params {
-        &BINARY_PARAMETERS
+        &PLAIN_REST_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
-        "eq"
+        "or"
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let x = rcenv_get!(env, "X")?;
-        let y = rcenv_get!(env, "Y")?;
-        if x == y {
-            Ok(Object::T)
-        } else {
-            Ok(Object::Nil)
-        }
+        self.select_form(env)?.eval(&caller_env(env))
+    }
+
+    // Only the last form can return several values
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.select_form(env)?.eval_values(&caller_env(env))
     }
 }
 
-pub struct ObjectOr;
+pub struct ObjectAnd;
+
+impl ObjectAnd {
+    /// Form giving the result: NIL once a form is false, or else the last form
+    fn select_form(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let mut forms = list_items(rcenv_get!(env, "X")?);
+        let Some(last) = forms.pop() else {
+            return Ok(Object::T);
+        };
+        for form in forms {
+            if form.eval(&caller)? == Object::Nil {
+                return Ok(Object::Nil);
+            }
+        }
+        Ok(last)
+    }
+}
 
-impl BuiltinFunc for ObjectOr {
+impl BuiltinFunc for ObjectAnd {
     fn get_parameters(&self) -> &Params {
-        &REST_PARAMETERS
+        &PLAIN_REST_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
-        "or"
+        "and"
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let mut lst = rcenv_get!(env, "X")?;
-        while lst != Object::Nil {
-            let (clause, cdr) = guard_obj!(lst, List)?.unpack();
-            let ret = clause.eval(env)?;
-            match &ret {
-                Object::Nil => (),
-                _ => return Ok(ret),
-            }
-            lst = cdr;
-        }
-        Ok(Object::Nil)
+        self.select_form(env)?.eval(&caller_env(env))
+    }
+
+    // Only the last form can return several values
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.select_form(env)?.eval_values(&caller_env(env))
     }
 }
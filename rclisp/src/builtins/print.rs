@@ -1,12 +1,69 @@
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, EMPTY_PARAMETERS, UNARY_PARAMETERS, rcenv_get};
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, rcenv_get};
+use super::clos::print_object;
+use super::condition::{condition_p, report};
+use super::stream::{output_stream, write_str};
+
+use crate::types::Stream;
+
+builtin_parameters! {
+    static ref PRINT_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil)]);
+    static ref TERPRI_PARAMETERS: Params = Params::from(vec![Parameter::optional("X", Object::Nil)]);
+}
+
+/// Print an object as `prin1` does when escaping and as `princ` does otherwise, `print-object`
+/// methods being used for the object and for the elements of lists, and conditions being
+/// printed by their report when not escaping
+pub(crate) fn write_object(env: &RcEnv, obj: &Object, stream: &Stream, escape: bool) -> Result<(), EvalError> {
+    if print_object(env, obj, stream)? {
+        return Ok(());
+    }
+    match obj {
+        Object::List(list) => {
+            write_str(stream, "(")?;
+            let (mut item, mut rest) = list.unpack();
+            loop {
+                write_object(env, &item, stream, escape)?;
+                match rest {
+                    Object::List(next) => {
+                        write_str(stream, " ")?;
+                        (item, rest) = next.unpack();
+                    },
+                    Object::Nil => break,
+                    end => {
+                        write_str(stream, " . ")?;
+                        write_object(env, &end, stream, escape)?;
+                        break;
+                    },
+                }
+            }
+            write_str(stream, ")")
+        },
+        Object::Quote(quoted) => {
+            if escape {
+                write_str(stream, "'")?;
+            }
+            write_object(env, quoted, stream, escape)
+        },
+        Object::String(s) if !escape => write_str(stream, s),
+        Object::Instance(_) if !escape && condition_p(env, obj) => write_str(stream, &report(env, obj)?),
+        _ => write_str(stream, &obj.to_string()),
+    }
+}
+
+/// Printed representation of an object, see `write_object`
+pub fn print_string(env: &RcEnv, obj: &Object, escape: bool) -> Result<String, EvalError> {
+    let stream = Stream::string_output();
+    write_object(env, obj, &stream, escape)?;
+    Ok(stream.take_string())
+}
 
 macro_rules! create_print_struct {
-    ($struct:ident, $name:expr, $op:expr) => (
+    ($struct:ident, $name:expr, $escape:expr, $end:expr) => (
         pub struct $struct;
 
         impl BuiltinFunc for $struct {
             fn get_parameters(&self) -> &Params {
-                &UNARY_PARAMETERS
+                &PRINT_PARAMETERS
             }
 
             fn get_name(&self) -> &str {
@@ -15,29 +72,51 @@ macro_rules! create_print_struct {
 
             fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
                 let x = rcenv_get!(env, "X")?;
-                $op(&x);
+                let stream = output_stream(rcenv_get!(env, "Y")?, env)?;
+                write_object(env, &x, &stream, $escape)?;
+                write_str(&stream, $end)?;
                 Ok(x)
             }
         }
     );
 }
 
-create_print_struct!(ObjectPrint, "print", |x: &Object| println!("{}", x));
-create_print_struct!(ObjectPrinc, "princ", |x: &Object| print!("{}", x.print()));
+create_print_struct!(ObjectPrint, "print", true, "\n");
+create_print_struct!(ObjectPrinc, "princ", false, "");
 
 pub struct ObjectTerpri;
 
 impl BuiltinFunc for ObjectTerpri {
     fn get_parameters(&self) -> &Params {
-        &EMPTY_PARAMETERS
+        &TERPRI_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
         "terpri"
     }
 
-    fn eval(&self, _env: &RcEnv) -> Result<Object, EvalError> {
-        println!("");
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let stream = output_stream(rcenv_get!(env, "X")?, env)?;
+        write_str(&stream, "\n")?;
         Ok(Object::Nil)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::print_string;
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_print_string() {
+        let (env, run) = interpreter();
+        let string = Object::String("a \"b\"".into());
+        assert_eq!(print_string(&env, &string, true), Ok("\"a \\\"b\\\"\"".into()));
+        assert_eq!(print_string(&env, &string, false), Ok("a \"b\"".into()));
+        run("(defclass point () ((x :initarg :x)))").unwrap();
+        run("(defmethod print-object ((p point) stream) (format stream \"#<POINT ~a>\" (slot-value p 'x)))").unwrap();
+        let point = run("(list (make-instance 'point :x 1) 'a)").unwrap();
+        assert_eq!(print_string(&env, &point, true), Ok("(#<POINT 1> A)".into()));
+    }
+}
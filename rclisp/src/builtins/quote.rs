@@ -1,8 +1,6 @@
-use lazy_static::lazy_static;
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, symbol};
 
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, symbol};
-
-lazy_static! {
+builtin_parameters! {
     static ref QUOTE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X")]);
 }
 
@@ -18,7 +16,6 @@ impl BuiltinFunc for ObjectQuote {
     }
 
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let x = env.borrow().get(&symbol("X"))?;
-        Ok(Object::Quote(Box::new(x)))
+        Ok(env.borrow().get(&symbol("X"))?)
     }
 }
@@ -0,0 +1,30 @@
+use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, UNARY_PARAMETERS, rcenv_get, guard_obj, caller_env};
+
+use crate::Lexer;
+use crate::parse::parse;
+use super::values::primary_value;
+
+pub struct ObjectReadFromString;
+
+impl BuiltinFunc for ObjectReadFromString {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "read-from-string"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // Returns the object read and the index of the first character not read
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let string = guard_obj!(rcenv_get!(env, "X")?, String)?;
+        let mut lexer = Lexer::new(string.as_bytes());
+        let obj = parse(&mut lexer).map_err(|e| EvalError::ParseError("string".into(), lexer.position(), e.to_string()))?;
+        let obj = crate::read(obj, &caller_env(env))?;
+        Ok(vec![obj, Object::Integer(lexer.position() as isize)])
+    }
+}
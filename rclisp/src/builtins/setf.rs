@@ -0,0 +1,559 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, List, Params, Parameter, PLAIN_REST_PARAMETERS, symbol, cons, rcenv_get, guard_obj, list_items, caller_env};
+use super::lambda::{body_forms, function_designator};
+use super::list::{sequence_keywords, keyword_value, key_of};
+use super::arithmetics::{add, negate};
+use super::macros::macroexpand_1;
+use super::setq::assign;
+
+use crate::env::{FunctionName, SetfExpansion};
+use crate::types::{Cons, UserLambda};
+use crate::types::equality::eql;
+use crate::eval::{Eval, apply, expand_macro, expand_macro_values};
+use crate::package::designator_name;
+
+builtin_parameters! {
+    static ref DEFSETF_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref MODIFY_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref PUSH_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref POP_PARAMETERS: Params = Params::from(vec![Parameter::plain("X")]);
+}
+
+static TEMPORARY_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Fresh variable name for a setf expansion, which cannot clash with a name read from source
+fn temporary(prefix: &str) -> String {
+    format!("#:{}{}", prefix, TEMPORARY_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// How an accessor place stores a new value
+pub(crate) enum Writer {
+    /// Setf function, called with the new value followed by the arguments
+    Function(Object),
+    /// Update function of a short `defsetf`, called with the arguments followed by the new value
+    Update(Object),
+    /// Expander of a long `defsetf`, whose store form is evaluated
+    Expansion(UserLambda),
+}
+
+/// A place whose subforms have been evaluated, so that it can be read and written any
+/// number of times without evaluating them again
+pub(crate) enum Place {
+    Variable(String),
+    // Conses are shared, changing an element changes it for every reference to the list
+    Car(Object),
+    Cdr(Object),
+    Nth(usize, Object),
+    Accessor(Object, Vec<Object>, Writer), // (Accessor, Arguments, Writer)
+    Expander {
+        frame: RcEnv,
+        stores: Vec<String>,
+        store_form: Object,
+        access_form: Object,
+    },
+}
+
+fn evaluate_arguments(args: Object, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+    list_items(args).into_iter().map(|arg| arg.eval(env)).collect()
+}
+
+fn symbol_names(obj: Object) -> Result<Vec<String>, EvalError> {
+    list_items(obj).into_iter().map(|s| guard_obj!(s, Symbol)).collect()
+}
+
+/// Analyze a place form, evaluating its subforms from left to right
+pub(crate) fn place(form: Object, env: &RcEnv) -> Result<Place, EvalError> {
+    let (accessor, args) = match form {
+        Object::Symbol(s) => return Ok(Place::Variable(s.to_uppercase())),
+        Object::List(l) => l.unpack(),
+        _ => return Err(EvalError::ParameterTypeMismatched),
+    };
+    let name = guard_obj!(accessor.clone(), Symbol)?;
+    match (designator_name(&accessor).as_deref(), list_items(args.clone()).as_slice()) {
+        (Some("CAR"), [list]) => return Ok(Place::Car(list.clone().eval(env)?)),
+        (Some("CDR"), [list]) => return Ok(Place::Cdr(list.clone().eval(env)?)),
+        (Some("NTH"), [n, list]) => {
+            let n = guard_obj!(n.clone().eval(env)?, Integer)?;
+            let n = usize::try_from(n).map_err(|_| EvalError::ParameterTypeMismatched)?;
+            return Ok(Place::Nth(n, list.clone().eval(env)?));
+        },
+        _ => (),
+    }
+
+    let expansion = env.borrow().get_setf_expansions().borrow().get(&name).cloned();
+    let writer = match expansion {
+        Some(SetfExpansion::Expander(expander)) => return expander_place(expander, cons(accessor, args), env),
+        Some(SetfExpansion::Update(update)) => Writer::Update(update),
+        Some(SetfExpansion::Defsetf(expander)) => Writer::Expansion(expander),
+        None => {
+            let function = env.borrow().get_function(&FunctionName::Setf(name));
+            match function {
+                Ok(function) => Writer::Function(function),
+                Err(e) => match macroexpand_1(cons(accessor, args), env)? {
+                    (expansion, true) => return place(expansion, env),
+                    _ => return Err(e.into()),
+                },
+            }
+        },
+    };
+    Ok(Place::Accessor(accessor, evaluate_arguments(args, env)?, writer))
+}
+
+/// Place described by the five values of a setf expander, with its temporary variables
+/// bound in a frame of their own
+fn expander_place(expander: UserLambda, form: Object, env: &RcEnv) -> Result<Place, EvalError> {
+    let values = expand_macro_values(expander, form, env)?;
+    let [temps, vals, stores, store_form, access_form] = <[Object; 5]>::try_from(values)
+        .map_err(|_| EvalError::ParameterTypeMismatched)?;
+    let frame = Env::inherit(env).wrap();
+    for (temp, val) in symbol_names(temps)?.into_iter().zip(list_items(vals)) {
+        let value = val.eval(&frame)?;
+        frame.borrow_mut().insert_str(temp, value);
+    }
+    Ok(Place::Expander { frame, stores: symbol_names(stores)?, store_form, access_form })
+}
+
+/// Cons holding the element at `n`
+fn nth_cons(list: &Object, n: usize) -> Result<Cons, EvalError> {
+    let mut tail = guard_obj!(list.clone(), List)?;
+    for _ in 0..n {
+        tail = guard_obj!(tail.cdr(), List)?;
+    }
+    Ok(tail)
+}
+
+impl Place {
+    pub(crate) fn read(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        match self {
+            Self::Variable(name) => Ok(env.borrow().get_str(name)?),
+            Self::Car(list) => match list {
+                Object::List(l) => Ok(l.car()),
+                Object::Nil => Ok(Object::Nil),
+                _ => Err(EvalError::ParameterTypeMismatched),
+            },
+            Self::Cdr(list) => match list {
+                Object::List(l) => Ok(l.cdr()),
+                Object::Nil => Ok(Object::Nil),
+                _ => Err(EvalError::ParameterTypeMismatched),
+            },
+            Self::Nth(n, list) => Ok(list_items(list.clone()).into_iter().nth(*n).unwrap_or(Object::Nil)),
+            Self::Accessor(accessor, args, _) => apply(accessor.clone(), args.clone(), env),
+            Self::Expander { frame, access_form, .. } => access_form.clone().eval(frame),
+        }
+    }
+
+    pub(crate) fn write(&self, value: Object, env: &RcEnv) -> Result<(), EvalError> {
+        match self {
+            Self::Variable(name) => assign(name, value, env)?,
+            Self::Car(list) => guard_obj!(list.clone(), List)?.set_car(value),
+            Self::Cdr(list) => guard_obj!(list.clone(), List)?.set_cdr(value),
+            Self::Nth(n, list) => nth_cons(list, *n)?.set_car(value),
+            Self::Accessor(_, args, Writer::Function(function)) => {
+                let mut values = vec![value];
+                values.extend(args.iter().cloned());
+                apply(function.clone(), values, env)?;
+            },
+            Self::Accessor(_, args, Writer::Update(function)) => {
+                let mut values = args.clone();
+                values.push(value);
+                apply(function.clone(), values, env)?;
+            },
+            // The expander sees temporaries bound to the arguments and to the new value, its
+            // store form runs where they are bound
+            Self::Accessor(accessor, args, Writer::Expansion(expander)) => {
+                let frame = Env::inherit(env).wrap();
+                let mut temps = vec![];
+                for arg in args {
+                    let temp = temporary("ARG");
+                    frame.borrow_mut().insert_str(&temp, arg.clone());
+                    temps.push(symbol(temp));
+                }
+                let store = temporary("NEW");
+                frame.borrow_mut().insert_str(&store, value);
+                let form = cons(accessor.clone(), cons(cons(symbol(store), Object::Nil), Object::from(temps)));
+                expand_macro(expander.clone(), form, env)?.eval(&frame)?;
+            },
+            Self::Expander { frame, stores, store_form, .. } => {
+                let mut values = std::iter::once(value).chain(std::iter::repeat(Object::Nil));
+                for store in stores {
+                    let value = values.next().unwrap_or(Object::Nil);
+                    frame.borrow_mut().insert_str(store, value);
+                }
+                store_form.clone().eval(frame)?;
+            },
+        }
+        Ok(())
+    }
+}
+
+pub struct ObjectSetf;
+
+impl BuiltinFunc for ObjectSetf {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "setf"
+    }
+
+    // Pairs of places and values are assigned in order, returning the last value
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let pairs = list_items(rcenv_get!(env, "X")?);
+        if !pairs.len().is_multiple_of(2) {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        let caller = caller_env(env);
+        let mut ret = Object::Nil;
+        for pair in pairs.chunks(2) {
+            let place = place(pair[0].clone(), &caller)?;
+            ret = pair[1].clone().eval(&caller)?;
+            place.write(ret.clone(), &caller)?;
+        }
+        Ok(ret)
+    }
+}
+
+pub struct ObjectDefsetf;
+
+impl BuiltinFunc for ObjectDefsetf {
+    fn get_parameters(&self) -> &Params {
+        &DEFSETF_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defsetf"
+    }
+
+    // The short form names an update function, the long form has a lambda list, store
+    // variables and a body returning the form that stores the value
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let rest = rcenv_get!(env, "Z")?;
+        let update = match (rcenv_get!(env, "Y")?, rest) {
+            (Object::Symbol(function), Object::Nil) => SetfExpansion::Update(symbol(function)),
+            (Object::Nil, Object::Nil) => return Err(EvalError::ParameterTypeMismatched),
+            (lambda_list, Object::List(l)) => {
+                let (stores, body) = l.unpack();
+                let p = Params::destructuring(cons(stores, lambda_list))?;
+                let body = cons(symbol("BLOCK"), cons(symbol(&name), body_forms(body).into()));
+                SetfExpansion::Defsetf(UserLambda::new(p, List::EndsWith(body)).with_env(&caller_env(env)))
+            },
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        };
+        env.borrow().get_setf_expansions().borrow_mut().insert(name.clone(), update);
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectDefineSetfExpander;
+
+impl BuiltinFunc for ObjectDefineSetfExpander {
+    fn get_parameters(&self) -> &Params {
+        &DEFSETF_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "define-setf-expander"
+    }
+
+    // The body returns the temporary variables, their value forms, the store variables,
+    // the store form and the access form
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let p = Params::destructuring(rcenv_get!(env, "Y")?)?;
+        let body = cons(symbol("BLOCK"), cons(symbol(&name), body_forms(rcenv_get!(env, "Z")?).into()));
+        let expander = UserLambda::new(p, List::EndsWith(body)).with_env(&caller_env(env));
+        env.borrow().get_setf_expansions().borrow_mut().insert(name.clone(), SetfExpansion::Expander(expander));
+        Ok(symbol(name))
+    }
+}
+
+/// Add the delta, 1 by default, to the number in a place
+fn increment(env: &RcEnv, negative: bool) -> Result<Object, EvalError> {
+    let caller = caller_env(env);
+    let place = place(rcenv_get!(env, "X")?, &caller)?;
+    let delta = match list_items(rcenv_get!(env, "Y")?).as_slice() {
+        [] => Object::Integer(1),
+        [delta] => delta.clone().eval(&caller)?,
+        _ => return Err(EvalError::ParameterTypeMismatched),
+    };
+    let delta = if negative { negate(&delta)? } else { delta };
+    let value = add(&place.read(&caller)?, &delta)?;
+    place.write(value.clone(), &caller)?;
+    Ok(value)
+}
+
+macro_rules! generate_increment_forms {
+    ($struct:ident, $name:expr, $negative:expr) => (
+        pub struct $struct;
+
+        impl BuiltinFunc for $struct {
+            fn get_parameters(&self) -> &Params {
+                &MODIFY_PARAMETERS
+            }
+
+            fn get_name(&self) -> &str {
+                $name
+            }
+
+            fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+                increment(env, $negative)
+            }
+        }
+    );
+}
+
+generate_increment_forms!(ObjectIncf, "incf", false);
+generate_increment_forms!(ObjectDecf, "decf", true);
+
+pub struct ObjectPush;
+
+impl BuiltinFunc for ObjectPush {
+    fn get_parameters(&self) -> &Params {
+        &PUSH_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "push"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        if rcenv_get!(env, "Z")? != Object::Nil {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        let item = rcenv_get!(env, "X")?.eval(&caller)?;
+        let place = place(rcenv_get!(env, "Y")?, &caller)?;
+        let value = cons(item, place.read(&caller)?);
+        place.write(value.clone(), &caller)?;
+        Ok(value)
+    }
+}
+
+pub struct ObjectPushnew;
+
+impl BuiltinFunc for ObjectPushnew {
+    fn get_parameters(&self) -> &Params {
+        &PUSH_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "pushnew"
+    }
+
+    // Elements are compared with `eql` unless there is a `:test`
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let item = rcenv_get!(env, "X")?.eval(&caller)?;
+        let place = place(rcenv_get!(env, "Y")?, &caller)?;
+        let keywords = Object::from(evaluate_arguments(rcenv_get!(env, "Z")?, &caller)?);
+        let keywords = sequence_keywords(keywords, &["KEY", "TEST"])?;
+        let key = keyword_value(&keywords, "KEY");
+        let test = keyword_value(&keywords, "TEST").map(function_designator).transpose()?;
+        let list = place.read(&caller)?;
+        let item_key = key_of(&key, &item, &caller)?;
+        for element in list_items(list.clone()) {
+            let element = key_of(&key, &element, &caller)?;
+            let found = match &test {
+                Some(test) => apply(test.clone(), vec![item_key.clone(), element], &caller)? != Object::Nil,
+                None => eql(&item_key, &element),
+            };
+            if found {
+                return Ok(list);
+            }
+        }
+        let value = cons(item, list);
+        place.write(value.clone(), &caller)?;
+        Ok(value)
+    }
+}
+
+pub struct ObjectPop;
+
+impl BuiltinFunc for ObjectPop {
+    fn get_parameters(&self) -> &Params {
+        &POP_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "pop"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let place = place(rcenv_get!(env, "X")?, &caller)?;
+        let (car, cdr) = match place.read(&caller)? {
+            Object::List(l) => l.unpack(),
+            Object::Nil => return Ok(Object::Nil),
+            _ => return Err(EvalError::ParameterTypeMismatched),
+        };
+        place.write(cdr, &caller)?;
+        Ok(car)
+    }
+}
+
+pub struct ObjectRotatef;
+
+impl BuiltinFunc for ObjectRotatef {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "rotatef"
+    }
+
+    // Each place gets the value of the one after it, the last one gets the value of the first
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let places = list_items(rcenv_get!(env, "X")?).into_iter()
+            .map(|p| place(p, &caller))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut values = places.iter().map(|p| p.read(&caller)).collect::<Result<Vec<_>, _>>()?;
+        if !values.is_empty() {
+            values.rotate_left(1);
+        }
+        for (place, value) in places.iter().zip(values) {
+            place.write(value, &caller)?;
+        }
+        Ok(Object::Nil)
+    }
+}
+
+pub struct ObjectShiftf;
+
+impl BuiltinFunc for ObjectShiftf {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "shiftf"
+    }
+
+    // Each place gets the value of the one after it, the last one gets the new value and
+    // the old value of the first place is returned
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let mut forms = list_items(rcenv_get!(env, "X")?);
+        let new_value = forms.pop().ok_or(EvalError::ParameterTypeMismatched)?;
+        if forms.is_empty() {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        let places = forms.into_iter()
+            .map(|p| place(p, &caller))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut values = places.iter().map(|p| p.read(&caller)).collect::<Result<Vec<_>, _>>()?;
+        values.push(new_value.eval(&caller)?);
+        for (place, value) in places.iter().zip(values.iter().skip(1)) {
+            place.write(value.clone(), &caller)?;
+        }
+        Ok(values.swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_places() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(let ((l (cons 1 (cons 2 (cons 3 nil))))) (setf (car l) 0 (nth 2 l) 9) l)"), run("'(0 2 9)"));
+        assert_eq!(run("(let ((l (cons (cons 1 2) nil))) (setf (cdr (car l)) 5) l)"), run("'((1 . 5))"));
+        assert_eq!(run("(let ((h (make-hash-table))) (incf (gethash :a h 10) 5) (gethash :a h))"), Ok(Object::Integer(15)));
+        run("(defvar *counter* 0)").unwrap();
+        assert_eq!(run("(progn (setf (symbol-value '*counter*) 3) (decf *counter*) *counter*)"), Ok(Object::Integer(2)));
+        run("(defstruct point x y)").unwrap();
+        assert_eq!(run("(let ((p (make-point :x 1 :y 2))) (rotatef (point-x p) (point-y p)) (cons (point-x p) (point-y p)))"), run("'(2 . 1)"));
+        assert_eq!(run("(let ((a 1) (b 2) (c 3)) (cons (shiftf a b c 4) (cons a (cons b (cons c nil)))))"), run("'(1 2 3 4)"));
+        assert_eq!(run("(let ((l nil)) (push 1 l) (push 2 l) (pushnew 1 l) (cons (pop l) l))"), run("'(2 1)"));
+    }
+
+    #[test]
+    fn test_place_extensions() {
+        let (_, run) = interpreter();
+        // Subforms of the place are evaluated once
+        run("(defun next-index () (incf *calls*) 0)").unwrap();
+        run("(defvar *calls* 0)").unwrap();
+        assert_eq!(run("(let ((l (cons 1 nil))) (incf (nth (next-index) l) 10) (cons *calls* l))"), run("'(1 11)"));
+        run("(defvar *box* (cons 0 nil))").unwrap();
+        run("(defun box-value () (car *box*))").unwrap();
+        run("(defun (setf box-value) (value) (setf (car *box*) value))").unwrap();
+        assert_eq!(run("(progn (incf (box-value) 2) (box-value))"), Ok(Object::Integer(2)));
+        run("(defun set-box (value) (setf (car *box*) value))").unwrap();
+        run("(defun box () (car *box*))").unwrap();
+        run("(defsetf box set-box)").unwrap();
+        assert_eq!(run("(progn (setf (box) 7) (box))"), Ok(Object::Integer(7)));
+        run("(defun cell (l) (car l))").unwrap();
+        run("(defsetf cell (l) (store) (cons 'setq (cons '*stored* (cons (cons 'cons (cons l (cons store nil))) nil))))").unwrap();
+        run("(defvar *stored* nil)").unwrap();
+        assert_eq!(run("(progn (setf (cell '(a)) 1) *stored*)"), run("'((a) . 1)"));
+        run("(define-setf-expander first-of (l) (values (cons 'tmp nil) (cons l nil) (cons 'new nil) '(setq *stored* new) '(car tmp)))").unwrap();
+        assert_eq!(run("(progn (incf (first-of '(4 5))) *stored*)"), Ok(Object::Integer(5)));
+        run("(defmacro head (l) (cons 'car (cons l nil)))").unwrap();
+        assert_eq!(run("(let ((l (cons 1 nil))) (setf (head l) 3) l)"), run("'(3)"));
+    }
+
+    #[test]
+    fn test_long_defsetf() {
+        let (_, run) = interpreter();
+        run("(defvar *table* (make-hash-table))").unwrap();
+        run("(defun entry (key table) (gethash key table))").unwrap();
+        run("(defsetf entry (key table) (new) `(setf (gethash ,key ,table) ,new))").unwrap();
+        assert_eq!(run("(setf (entry 'a *table*) 1)"), Ok(Object::Integer(1)));
+        assert_eq!(run("(gethash 'a *table*)"), Ok(Object::Integer(1)));
+        // Argument forms are evaluated once, before the new value
+        run("(defvar *order* nil)").unwrap();
+        run("(defun note (x) (push x *order*) x)").unwrap();
+        assert_eq!(run("(incf (entry (note 'a) (progn (note 'table) *table*)) (note 2))"), Ok(Object::Integer(3)));
+        assert_eq!(run("*order*"), run("'(2 table a)"));
+        // The store form cannot see the caller's variables through the parameter names
+        assert_eq!(run("(let ((key 'b) (new 5)) (setf (entry key *table*) (+ new 1)) (gethash 'b *table*))"), Ok(Object::Integer(6)));
+        assert!(run("(setf (entry 'a) 1)").is_err());
+    }
+
+    #[test]
+    fn test_shared_conses() {
+        let (_, run) = interpreter();
+        run("(defun (setf my-first) (new lst) (setf (car lst) new))").unwrap();
+        run("(defvar v (list 1 2 3))").unwrap();
+        assert_eq!(run("(setf (my-first v) :first)"), run(":first"));
+        assert_eq!(run("v"), run("'(:first 2 3)"));
+        run("(defun kdr (c) (cdr c))").unwrap();
+        run("(defun set-kdr (c new) (setf (cdr c) new))").unwrap();
+        run("(defsetf kdr set-kdr)").unwrap();
+        run("(setf (kdr v) '(b))").unwrap();
+        assert_eq!(run("v"), run("'(:first b)"));
+        run("(defun kar (c) (car c))").unwrap();
+        run("(defsetf kar (c) (new) `(setf (car ,c) ,new))").unwrap();
+        run("(setf (kar (kdr v)) 'c)").unwrap();
+        assert_eq!(run("v"), run("'(:first c)"));
+        // Every variable holding the list sees the change
+        assert_eq!(run("(let* ((a (list 1 2)) (b a)) (setf (nth 1 b) 3) (incf (car a)) (list a b))"), run("'((2 3) (2 3))"));
+        assert_eq!(run("(let* ((a (list (list 1) 2)) (b (car a))) (setf (car b) 0) a)"), run("'((0) 2)"));
+        assert!(run("(setf (nth 5 v) 1)").is_err());
+        assert!(run("(setf (car nil) 1)").is_err());
+    }
+
+    #[test]
+    fn test_setf_function_names() {
+        let (_, run) = interpreter();
+        run("(defvar *box* (cons 0 nil))").unwrap();
+        run("(defun (setf box-value) (value) (setf (car *box*) value))").unwrap();
+        assert_eq!(run("(funcall #'(setf box-value) 3)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(car *box*)"), Ok(Object::Integer(3)));
+        // Local setf functions shadow the global one
+        assert_eq!(run("(flet (((setf box-value) (value) value)) (setf (box-value) 4))"), Ok(Object::Integer(4)));
+        assert_eq!(run("(car *box*)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(flet (((setf other) (value) value)) (setf (other) 5))"), Ok(Object::Integer(5)));
+        assert!(run("(setf (other) 5)").is_err());
+        assert_eq!(run("(defgeneric (setf size) (value object))"), run("'(setf size)"));
+        run("(deftype small () '(integer 0 9))").unwrap();
+        assert_eq!(run("(typep 5 'small)"), Ok(Object::T));
+        assert!(run("small").is_err());
+    }
+}
@@ -1,28 +1,92 @@
-use lazy_static::lazy_static;
-
 use crate::rcenv_get;
 
-use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, guard_obj};
+use super::{BuiltinFunc, RcEnv, Object, EvalError, Params, PLAIN_REST_PARAMETERS, guard_obj, list_items, caller_env};
+use super::condition::warn;
+
+use crate::eval::Eval;
+
+/// Assign the nearest binding of a variable, warning when it is neither bound nor special
+/// as it then becomes a new global variable
+pub(crate) fn assign(name: &str, value: Object, env: &RcEnv) -> Result<(), EvalError> {
+    let defined = env.borrow().get_str(name).is_ok() || env.borrow().is_special(name);
+    if !defined {
+        warn(env, "undefined variable: ~a", vec![Object::Symbol(name.into())])?;
+    }
+    env.borrow_mut().set_str(name, value)?;
+    Ok(())
+}
 
-lazy_static! {
-    static ref SETQ_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::normal("Y")]);
+/// Variables and value forms of `var form ...`
+fn variable_pairs(obj: Object) -> Result<Vec<(String, Object)>, EvalError> {
+    let items = list_items(obj);
+    if !items.len().is_multiple_of(2) {
+        return Err(EvalError::ParameterTypeMismatched);
+    }
+    items.chunks(2)
+        .map(|pair| Ok((guard_obj!(pair[0].clone(), Symbol)?.to_uppercase(), pair[1].clone())))
+        .collect()
 }
 
 pub struct ObjectSetq;
 
 impl BuiltinFunc for ObjectSetq {
     fn get_parameters(&self) -> &Params {
-        &SETQ_PARAMETERS
+        &PLAIN_REST_PARAMETERS
     }
 
     fn get_name(&self) -> &str {
         "setq"
     }
 
+    // Each value form sees the variables assigned before it, the last value is returned
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let mut ret = Object::Nil;
+        for (name, form) in variable_pairs(rcenv_get!(env, "X")?)? {
+            ret = form.eval(&caller)?;
+            assign(&name, ret.clone(), &caller)?;
+        }
+        Ok(ret)
+    }
+}
+
+pub struct ObjectPsetq;
+
+impl BuiltinFunc for ObjectPsetq {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "psetq"
+    }
+
+    // Every value form is evaluated before any variable is assigned
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
-        let key = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
-        let value = rcenv_get!(env, "Y")?;
-        env.borrow_mut().insert_global(&Object::Symbol(key), value);
+        let caller = caller_env(env);
+        let mut values = vec![];
+        for (name, form) in variable_pairs(rcenv_get!(env, "X")?)? {
+            values.push((name, form.eval(&caller)?));
+        }
+        for (name, value) in values {
+            assign(&name, value, &caller)?;
+        }
         Ok(Object::Nil)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_setq() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(defun f (x) (setq x 1) x) (f 5)"), Ok(Object::Integer(1)));
+        assert!(run("x").is_err());
+        assert_eq!(run("(let ((a 0) (b 0)) (cons (setq a 1 b (+ a 1)) (cons a b)))"), run("'(2 1 . 2)"));
+        assert_eq!(run("(let ((a 1) (b 2)) (psetq a b b a) (cons a b))"), run("'(2 . 1)"));
+        assert!(run("(setq a)").is_err());
+    }
+}
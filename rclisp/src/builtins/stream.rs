@@ -0,0 +1,404 @@
+use std::io;
+
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, Params, Parameter, EMPTY_PARAMETERS, UNARY_PARAMETERS, COMMON_LISP, rcenv_get, guard_obj, caller_env};
+use super::lambda::body_forms;
+use super::types::boolean;
+use super::values::primary_value;
+use super::list::{sequence_keywords, keyword_value};
+
+use crate::env::DynamicBindings;
+use crate::eval::eval_body;
+use crate::types::{Stream, Sink, Source};
+
+pub const STANDARD_OUTPUT: &str = "*STANDARD-OUTPUT*";
+pub const STANDARD_INPUT: &str = "*STANDARD-INPUT*";
+pub const ERROR_OUTPUT: &str = "*ERROR-OUTPUT*";
+
+builtin_parameters! {
+    static ref OPTIONAL_STREAM_PARAMETERS: Params = Params::from(vec![Parameter::optional("X", Object::Nil)]);
+    static ref WITH_OUTPUT_TO_STRING_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref MAKE_STRING_INPUT_STREAM_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Integer(0)), Parameter::optional("Z", Object::Nil)]);
+    static ref WRITE_STRING_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil), Parameter::rest("Z")]);
+    static ref WRITE_CHAR_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::optional("Y", Object::Nil)]);
+    static ref CLOSE_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::rest("Y")]);
+    static ref READ_PARAMETERS: Params = Params::from(vec![Parameter::optional("X", Object::Nil), Parameter::optional("Y", Object::T), Parameter::optional("Z", Object::Nil)]);
+}
+
+pub(crate) fn stream_error(e: io::Error) -> EvalError {
+    EvalError::StreamError(e.to_string())
+}
+
+fn set_standard_stream(env: &RcEnv, name: &str, stream: Stream) {
+    env.borrow_mut().insert_global_str(name, stream.into());
+}
+
+/// Send what is written to `*standard-output*` to the host
+pub fn set_standard_output(env: &RcEnv, sink: Box<dyn Sink>) {
+    set_standard_stream(env, STANDARD_OUTPUT, Stream::output(sink));
+}
+
+/// Send what is written to `*error-output*` to the host
+pub fn set_error_output(env: &RcEnv, sink: Box<dyn Sink>) {
+    set_standard_stream(env, ERROR_OUTPUT, Stream::output(sink));
+}
+
+/// Read `*standard-input*` from the host
+pub fn set_standard_input(env: &RcEnv, source: Box<dyn Source>) {
+    set_standard_stream(env, STANDARD_INPUT, Stream::input(source));
+}
+
+/// Bind the standard streams to the process' own
+pub(crate) fn define_standard_streams(env: &RcEnv) {
+    set_standard_output(env, Box::new(io::stdout()));
+    set_error_output(env, Box::new(io::stderr()));
+    set_standard_input(env, Box::new(io::BufReader::new(io::stdin())));
+    for name in [STANDARD_OUTPUT, STANDARD_INPUT, ERROR_OUTPUT] {
+        env.borrow_mut().proclaim_special(name).expect("Standard streams are not constants");
+        env.borrow().get_packages().borrow_mut()
+            .export(COMMON_LISP, name)
+            .expect("COMMON-LISP package is missing");
+    }
+}
+
+/// Current value of one of the standard stream variables
+pub(crate) fn standard_stream(env: &RcEnv, name: &str) -> Result<Stream, EvalError> {
+    let stream = env.borrow().get_str(name)?;
+    guard_obj!(stream, Stream)
+}
+
+/// Stream designated by an argument, NIL and T standing for the standard stream
+pub(crate) fn designated_stream(designator: Object, env: &RcEnv, standard: &str) -> Result<Stream, EvalError> {
+    match designator {
+        Object::Nil | Object::T => standard_stream(env, standard),
+        Object::Stream(s) => Ok(s),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+pub(crate) fn output_stream(designator: Object, env: &RcEnv) -> Result<Stream, EvalError> {
+    designated_stream(designator, env, STANDARD_OUTPUT)
+}
+
+pub(crate) fn input_stream(designator: Object, env: &RcEnv) -> Result<Stream, EvalError> {
+    designated_stream(designator, env, STANDARD_INPUT)
+}
+
+pub(crate) fn write_str(stream: &Stream, text: &str) -> Result<(), EvalError> {
+    stream.write_str(text).map_err(stream_error)
+}
+
+/// Characters of a string between two indexes, the end defaulting to its length
+fn substring(s: &str, start: &Object, end: &Object) -> Result<String, EvalError> {
+    let chars: Vec<char> = s.chars().collect();
+    let start = usize::try_from(guard_obj!(start.clone(), Integer)?).map_err(|_| EvalError::ParameterTypeMismatched)?;
+    let end = match end {
+        Object::Nil => chars.len(),
+        e => usize::try_from(guard_obj!(e.clone(), Integer)?).map_err(|_| EvalError::ParameterTypeMismatched)?,
+    };
+    match chars.get(start..end) {
+        Some(chars) => Ok(chars.iter().collect()),
+        None => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+/// Character argument, which is a string of one character
+fn character(obj: Object) -> Result<char, EvalError> {
+    let s = guard_obj!(obj, String)?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(EvalError::ParameterTypeMismatched),
+    }
+}
+
+pub struct ObjectWithOutputToString;
+
+impl BuiltinFunc for ObjectWithOutputToString {
+    fn get_parameters(&self) -> &Params {
+        &WITH_OUTPUT_TO_STRING_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "with-output-to-string"
+    }
+
+    // The variable may be special, such as *standard-output*
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let spec = guard_obj!(rcenv_get!(env, "X")?, List)?;
+        let var = guard_obj!(spec.car(), Symbol)?;
+        let body = body_forms(rcenv_get!(env, "Y")?);
+        let stream = Stream::string_output();
+        let frame = Env::inherit(&caller_env(env)).wrap();
+        let mut bindings = DynamicBindings::new(&frame);
+        bindings.bind(&frame, &var, stream.clone().into())?;
+        eval_body(body, &frame)?;
+        drop(bindings);
+        Ok(Object::String(stream.take_string()))
+    }
+}
+
+pub struct ObjectMakeStringOutputStream;
+
+impl BuiltinFunc for ObjectMakeStringOutputStream {
+    fn get_parameters(&self) -> &Params {
+        &EMPTY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "make-string-output-stream"
+    }
+
+    fn eval(&self, _env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(Stream::string_output().into())
+    }
+}
+
+pub struct ObjectGetOutputStreamString;
+
+impl BuiltinFunc for ObjectGetOutputStreamString {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "get-output-stream-string"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let stream = guard_obj!(rcenv_get!(env, "X")?, Stream)?;
+        if !stream.is_string_stream() {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        Ok(Object::String(stream.take_string()))
+    }
+}
+
+pub struct ObjectMakeStringInputStream;
+
+impl BuiltinFunc for ObjectMakeStringInputStream {
+    fn get_parameters(&self) -> &Params {
+        &MAKE_STRING_INPUT_STREAM_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "make-string-input-stream"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let s = guard_obj!(rcenv_get!(env, "X")?, String)?;
+        let text = substring(&s, &rcenv_get!(env, "Y")?, &rcenv_get!(env, "Z")?)?;
+        Ok(Stream::string_input(text).into())
+    }
+}
+
+/// Write the part of a string between `:start` and `:end`, followed by a newline for `write-line`
+fn write_string(env: &RcEnv, newline: bool) -> Result<Object, EvalError> {
+    let s = rcenv_get!(env, "X")?;
+    let stream = output_stream(rcenv_get!(env, "Y")?, env)?;
+    let keywords = sequence_keywords(rcenv_get!(env, "Z")?, &["START", "END"])?;
+    let start = keyword_value(&keywords, "START").unwrap_or(Object::Integer(0));
+    let end = keyword_value(&keywords, "END").unwrap_or(Object::Nil);
+    let mut text = substring(&guard_obj!(s.clone(), String)?, &start, &end)?;
+    if newline {
+        text.push('\n');
+    }
+    write_str(&stream, &text)?;
+    Ok(s)
+}
+
+pub struct ObjectWriteString;
+
+impl BuiltinFunc for ObjectWriteString {
+    fn get_parameters(&self) -> &Params {
+        &WRITE_STRING_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "write-string"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        write_string(env, false)
+    }
+}
+
+pub struct ObjectWriteLine;
+
+impl BuiltinFunc for ObjectWriteLine {
+    fn get_parameters(&self) -> &Params {
+        &WRITE_STRING_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "write-line"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        write_string(env, true)
+    }
+}
+
+pub struct ObjectWriteChar;
+
+impl BuiltinFunc for ObjectWriteChar {
+    fn get_parameters(&self) -> &Params {
+        &WRITE_CHAR_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "write-char"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let c = rcenv_get!(env, "X")?;
+        let stream = output_stream(rcenv_get!(env, "Y")?, env)?;
+        write_str(&stream, &character(c.clone())?.to_string())?;
+        Ok(c)
+    }
+}
+
+pub struct ObjectFreshLine;
+
+impl BuiltinFunc for ObjectFreshLine {
+    fn get_parameters(&self) -> &Params {
+        &OPTIONAL_STREAM_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "fresh-line"
+    }
+
+    // Returns whether a newline was written
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let stream = output_stream(rcenv_get!(env, "X")?, env)?;
+        match stream.fresh_line().map_err(stream_error)? {
+            true => Ok(Object::T),
+            false => Ok(Object::Nil),
+        }
+    }
+}
+
+pub struct ObjectFinishOutput;
+
+impl BuiltinFunc for ObjectFinishOutput {
+    fn get_parameters(&self) -> &Params {
+        &OPTIONAL_STREAM_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "finish-output"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let stream = output_stream(rcenv_get!(env, "X")?, env)?;
+        stream.flush().map_err(stream_error)?;
+        Ok(Object::Nil)
+    }
+}
+
+pub struct ObjectReadChar;
+
+impl BuiltinFunc for ObjectReadChar {
+    fn get_parameters(&self) -> &Params {
+        &READ_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "read-char"
+    }
+
+    // At the end of the stream, the EOF value is returned unless it is an error
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let stream = input_stream(rcenv_get!(env, "X")?, env)?;
+        match stream.read_char().map_err(stream_error)? {
+            Some(c) => Ok(Object::String(c.to_string())),
+            None if rcenv_get!(env, "Y")? != Object::Nil => Err(EvalError::EndOfFile),
+            None => Ok(rcenv_get!(env, "Z")?),
+        }
+    }
+}
+
+pub struct ObjectReadLine;
+
+impl BuiltinFunc for ObjectReadLine {
+    fn get_parameters(&self) -> &Params {
+        &READ_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "read-line"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // The line and whether it ended without a newline, which is always true at the end
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let stream = input_stream(rcenv_get!(env, "X")?, env)?;
+        match stream.read_line().map_err(stream_error)? {
+            Some((line, missing_newline)) => Ok(vec![Object::String(line), boolean(missing_newline)]),
+            None if rcenv_get!(env, "Y")? != Object::Nil => Err(EvalError::EndOfFile),
+            None => Ok(vec![rcenv_get!(env, "Z")?, Object::T]),
+        }
+    }
+}
+
+pub struct ObjectClose;
+
+impl BuiltinFunc for ObjectClose {
+    fn get_parameters(&self) -> &Params {
+        &CLOSE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "close"
+    }
+
+    // Output written so far is kept even when aborting
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let stream = guard_obj!(rcenv_get!(env, "X")?, Stream)?;
+        sequence_keywords(rcenv_get!(env, "Y")?, &["ABORT"])?;
+        let was_open = stream.is_open();
+        stream.close().map_err(stream_error)?;
+        Ok(boolean(was_open))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+    use super::set_standard_output;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streams() {
+        let (env, run) = interpreter();
+        let string = |s: &str| Ok(Object::String(s.into()));
+        assert_eq!(run("(with-output-to-string (s) (write-string \"ab\" s) (write-char \"c\" s) (fresh-line s) (fresh-line s))"), string("abc\n"));
+        assert_eq!(run("(with-output-to-string (*standard-output*) (princ 1) (terpri) (format t \"~&x~a\" 2))"), string("1\nx2"));
+        assert_eq!(run("(let ((s (make-string-input-stream \"hello\" 1 3))) (cons (read-char s) (cons (read-char s) (read-char s nil :eof))))"), run("'(\"e\" \"l\" . :eof)"));
+        assert!(run("(read-char (make-string-input-stream \"\"))").is_err());
+        let buffer = Buffer::default();
+        set_standard_output(&env, Box::new(buffer.clone()));
+        run("(progn (write-string \"out\") (print 1) (finish-output))").unwrap();
+        assert_eq!(String::from_utf8(buffer.0.lock().unwrap().clone()), Ok("out1\n".into()));
+    }
+}
@@ -0,0 +1,412 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, List, Params, Parameter, REST_PARAMETERS, UNARY_PARAMETERS, BINARY_PARAMETERS, rcenv_get, guard_obj, symbol, list_items, caller_env};
+
+use crate::eval::Eval;
+use crate::package::designator_name;
+use crate::types::{Structure, StructDef, StructSlot, Class};
+use crate::types::class::STRUCTURE_OBJECT;
+use crate::env::FunctionName;
+
+builtin_parameters! {
+    static ref DEFSTRUCT_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+}
+
+fn symbol_name(obj: &Object) -> Result<String, EvalError> {
+    designator_name(obj).ok_or(EvalError::ParameterTypeMismatched)
+}
+
+fn intern<S: AsRef<str>>(env: &RcEnv, name: S) -> Result<String, EvalError> {
+    let current = env.borrow().current_package();
+    let packages = env.borrow().get_packages();
+    let ret = packages.borrow_mut().intern(current, name)?;
+    Ok(ret)
+}
+
+fn find_definition(env: &RcEnv, name: &str) -> Result<StructDef, EvalError> {
+    let structures = env.borrow().get_structures();
+    let structures = structures.borrow();
+    structures.get(name).cloned().ok_or_else(|| EvalError::UnboundVariable(name.into()))
+}
+
+/// Whether structures of type `name` are also of type `target` through `:include`
+pub fn struct_type_p(env: &RcEnv, name: &str, target: &str) -> bool {
+    let structures = env.borrow().get_structures();
+    let structures = structures.borrow();
+    let mut current = Some(name.to_string());
+    while let Some(n) = current {
+        if n == target {
+            return true;
+        }
+        current = structures.get(&n).and_then(|d| d.include.clone());
+    }
+    false
+}
+
+fn guard_struct(env: &RcEnv, obj: Object, structure: &str) -> Result<Structure, EvalError> {
+    let s = guard_obj!(obj, Struct)?;
+    if struct_type_p(env, &s.get_name(), structure) {
+        Ok(s)
+    } else {
+        Err(EvalError::ParameterTypeMismatched)
+    }
+}
+
+/// Build an instance, slots not given in `values` are initialized with their default forms
+fn construct(env: &RcEnv, def: &StructDef, values: Vec<(String, Object)>) -> Result<Object, EvalError> {
+    let mut slots = vec![];
+    for slot in def.slots.iter() {
+        let value = match values.iter().find(|(name, _)| name == &slot.name) {
+            Some((_, v)) => v.clone(),
+            None => slot.default.clone().eval(env)?,
+        };
+        slots.push((slot.name.clone(), value));
+    }
+    Ok(Structure::new(&def.name, slots).into())
+}
+
+fn keyword_values(def: &StructDef, args: Vec<Object>) -> Result<Vec<(String, Object)>, EvalError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(EvalError::ParameterTypeMismatched);
+    }
+    let mut values = vec![];
+    let mut args = args.into_iter();
+    while let (Some(key), Some(value)) = (args.next(), args.next()) {
+        let key = symbol_name(&key)?;
+        if def.find_slot(&key).is_none() {
+            return Err(EvalError::UnknownKeyword(key));
+        }
+        values.push((key, value));
+    }
+    Ok(values)
+}
+
+pub struct StructConstructor {
+    name: String,
+    structure: String,
+    parameters: Params,
+    boa: Option<Vec<String>>,
+}
+
+impl BuiltinFunc for StructConstructor {
+    fn get_parameters(&self) -> &Params {
+        &self.parameters
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let def = find_definition(env, &self.structure)?;
+        if let Some(boa) = &self.boa {
+            // Defaults of the remaining slots may refer to the arguments
+            let mut values = vec![];
+            for name in boa {
+                values.push((name.clone(), env.borrow().get_str(name)?));
+            }
+            construct(env, &def, values)
+        } else {
+            let caller = caller_env(env);
+            let values = keyword_values(&def, list_items(rcenv_get!(env, "X")?))?;
+            construct(&caller, &def, values)
+        }
+    }
+}
+
+pub struct StructAccessor {
+    name: String,
+    structure: String,
+    slot: String,
+}
+
+impl BuiltinFunc for StructAccessor {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let s = guard_struct(env, rcenv_get!(env, "X")?, &self.structure)?;
+        s.get_slot(&self.slot).ok_or(EvalError::ParameterTypeMismatched)
+    }
+}
+
+pub struct StructSetter {
+    name: String,
+    structure: String,
+    slot: String,
+}
+
+impl BuiltinFunc for StructSetter {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let value = rcenv_get!(env, "X")?;
+        let s = guard_struct(env, rcenv_get!(env, "Y")?, &self.structure)?;
+        s.set_slot(&self.slot, value.clone());
+        Ok(value)
+    }
+}
+
+pub struct StructCopier {
+    name: String,
+    structure: String,
+}
+
+impl BuiltinFunc for StructCopier {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let s = guard_struct(env, rcenv_get!(env, "X")?, &self.structure)?;
+        Ok(s.copy().into())
+    }
+}
+
+pub struct StructPredicate {
+    name: String,
+    structure: String,
+}
+
+impl BuiltinFunc for StructPredicate {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        match rcenv_get!(env, "X")? {
+            Object::Struct(s) if struct_type_p(env, &s.get_name(), &self.structure) => Ok(Object::T),
+            _ => Ok(Object::Nil),
+        }
+    }
+}
+
+pub struct ObjectDefstruct;
+
+impl ObjectDefstruct {
+    fn define<F: BuiltinFunc + 'static>(env: &RcEnv, name: &str, function: F) {
+        env.borrow_mut().insert_global_str(name, Object::from(function));
+    }
+}
+
+impl BuiltinFunc for ObjectDefstruct {
+    fn get_parameters(&self) -> &Params {
+        &DEFSTRUCT_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defstruct"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let (name, options) = match rcenv_get!(env, "X")? {
+            Object::List(l) => l.unpack(),
+            obj => (obj, Object::Nil),
+        };
+        let name = guard_obj!(name, Symbol)?;
+        let bare_name = symbol_name(&Object::Symbol(name.clone()))?;
+
+        // Options
+        let mut conc_name = format!("{}-", bare_name);
+        let mut constructors = vec![];
+        let mut copier = Some(format!("COPY-{}", bare_name));
+        let mut predicate = Some(format!("{}-P", bare_name));
+        let mut include = None;
+        for option in list_items(options) {
+            let (key, args) = match option {
+                Object::List(l) => l.unpack(),
+                obj => (obj, Object::Nil),
+            };
+            let mut args = list_items(args).into_iter();
+            let first = args.next();
+            match symbol_name(&key)?.as_str() {
+                "CONC-NAME" => conc_name = match first {
+                    Some(Object::Nil) | None => "".into(),
+                    Some(o) => symbol_name(&o)?,
+                },
+                "CONSTRUCTOR" => constructors.push(match first {
+                    Some(Object::Nil) => None,
+                    None => Some((format!("MAKE-{}", bare_name), None)),
+                    Some(o) => {
+                        let boa = match args.next() {
+                            Some(arglist) => Some(list_items(arglist).iter().map(symbol_name).collect::<Result<Vec<String>, EvalError>>()?),
+                            None => None,
+                        };
+                        Some((symbol_name(&o)?, boa))
+                    },
+                }),
+                "COPIER" => copier = first.filter(|o| o != &Object::Nil).map(|o| symbol_name(&o)).transpose()?,
+                "PREDICATE" => predicate = first.filter(|o| o != &Object::Nil).map(|o| symbol_name(&o)).transpose()?,
+                "INCLUDE" => include = Some(guard_obj!(first.ok_or(EvalError::ParameterTypeMismatched)?, Symbol)?),
+                _ => return Err(EvalError::ParameterTypeMismatched),
+            }
+        }
+        if constructors.is_empty() {
+            constructors.push(Some((format!("MAKE-{}", bare_name), None)));
+        }
+
+        // Slots, inherited ones come first
+        let mut slots: Vec<StructSlot> = match &include {
+            Some(parent) => find_definition(env, parent)?.slots,
+            None => vec![],
+        };
+        for slot in list_items(rcenv_get!(env, "Y")?) {
+            let (slot_name, spec) = match slot {
+                Object::String(_) => continue, // Documentation
+                Object::List(l) => l.unpack(),
+                obj => (obj, Object::Nil),
+            };
+            let mut spec = list_items(spec).into_iter();
+            let default = spec.next().unwrap_or(Object::Nil);
+            let mut read_only = false;
+            while let (Some(key), Some(value)) = (spec.next(), spec.next()) {
+                if symbol_name(&key)? == "READ-ONLY" {
+                    read_only = value != Object::Nil;
+                }
+            }
+            let slot_name = symbol_name(&slot_name)?;
+            slots.retain(|s| s.name != slot_name);
+            slots.push(StructSlot { name: slot_name, default, read_only });
+        }
+
+        let superclass = include.clone().unwrap_or_else(|| STRUCTURE_OBJECT.into());
+        let def = StructDef { name: name.clone(), include, slots };
+        env.borrow().get_structures().borrow_mut().insert(name.clone(), def.clone());
+        env.borrow().get_classes().borrow_mut().define(Class::new(&name, vec![superclass], vec![]))?;
+
+        // Generated functions
+        for (constructor, boa) in constructors.into_iter().flatten() {
+            let constructor_name = intern(env, constructor)?;
+            let parameters = match &boa {
+                Some(args) => Params::from(args.iter().map(Parameter::normal).collect::<Vec<Parameter>>()),
+                None => REST_PARAMETERS.clone(),
+            };
+            Self::define(env, &constructor_name, StructConstructor {
+                name: constructor_name.clone(),
+                structure: name.clone(),
+                parameters,
+                boa,
+            });
+        }
+        for slot in def.slots.iter() {
+            let accessor = intern(env, format!("{}{}", conc_name, slot.name))?;
+            Self::define(env, &accessor, StructAccessor {
+                name: accessor.clone(),
+                structure: name.clone(),
+                slot: slot.name.clone(),
+            });
+            if !slot.read_only {
+                let setter = FunctionName::Setf(accessor.clone());
+                let function = Object::from(StructSetter {
+                    name: setter.to_string(),
+                    structure: name.clone(),
+                    slot: slot.name.clone(),
+                });
+                env.borrow_mut().insert_global_function(&setter, function);
+            }
+        }
+        if let Some(copier) = copier {
+            let copier = intern(env, copier)?;
+            Self::define(env, &copier, StructCopier { name: copier.clone(), structure: name.clone() });
+        }
+        if let Some(predicate) = predicate {
+            let predicate = intern(env, predicate)?;
+            Self::define(env, &predicate, StructPredicate { name: predicate.clone(), structure: name.clone() });
+        }
+
+        Ok(symbol(name))
+    }
+}
+
+/// Replace `#S(name :slot value ...)` literals with structure instances
+pub fn read_structures(obj: Object, env: &RcEnv) -> Result<Object, EvalError> {
+    match obj {
+        Object::Quote(o) => Ok(Object::Quote(Box::new(read_structures(*o, env)?))),
+        Object::List(l) => {
+            if l.car() == symbol("#S") {
+                let (name, args) = guard_obj!(l.cdr(), List)?.unpack();
+                let def = find_definition(env, &guard_obj!(name, Symbol)?)?;
+                let values = keyword_values(&def, list_items(args))?;
+                return construct(env, &def, values);
+            }
+            let mut items = vec![];
+            let mut tail = l.to_list();
+            while let List::Cons(car, cdr) = tail {
+                items.push(read_structures(car, env)?);
+                tail = *cdr;
+            }
+            Ok(items.into_iter().rev().fold(tail, |acc, item| acc.prepend(item)).into())
+        },
+        _ => Ok(obj),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_structures() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(defstruct point x (y 0) (z 9 :read-only t))"), run("'point"));
+        run("(defvar *p* (make-point :x 1))").unwrap();
+        assert_eq!(run("(point-x *p*)"), Ok(Object::Integer(1)));
+        assert_eq!(run("(point-y *p*)"), Ok(Object::Integer(0)));
+        assert_eq!(run("(point-p *p*)"), Ok(Object::T));
+        assert_eq!(run("(point-p 1)"), Ok(Object::Nil));
+        assert_eq!(run("(progn (setf (point-y *p*) 5) (point-y *p*))"), Ok(Object::Integer(5)));
+        assert!(run("(setf (point-z *p*) 1)").is_err());
+        // Copies are shallow but distinct
+        run("(defvar *q* (copy-point *p*))").unwrap();
+        assert_eq!(run("(progn (setf (point-x *q*) 7) (cons (point-x *p*) (point-x *q*)))"), run("'(1 . 7)"));
+        assert_eq!(run("(equalp *p* (copy-point *p*))"), Ok(Object::T));
+    }
+
+    #[test]
+    fn test_structure_options() {
+        let (_, run) = interpreter();
+        run("(defstruct (pair (:constructor pair (a b)) (:conc-name p-) (:predicate is-pair) (:copier nil)) a b)").unwrap();
+        assert_eq!(run("(p-b (pair 1 2))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(is-pair (pair 1 2))"), Ok(Object::T));
+        assert!(run("(copy-pair (pair 1 2))").is_err());
+        // Included slots come first and instances are of the parent type
+        run("(defstruct shape (name \"shape\"))").unwrap();
+        run("(defstruct (circle (:include shape)) (radius 1))").unwrap();
+        run("(defvar *c* (make-circle :name \"c\" :radius 2))").unwrap();
+        assert_eq!(run("(shape-name *c*)"), Ok(Object::String("c".into())));
+        assert_eq!(run("(circle-name *c*)"), Ok(Object::String("c".into())));
+        assert_eq!(run("(circle-radius *c*)"), Ok(Object::Integer(2)));
+        assert_eq!(run("(shape-p *c*)"), Ok(Object::T));
+        assert_eq!(run("(circle-p (make-shape))"), Ok(Object::Nil));
+        assert_eq!(run("(typep *c* 'shape)"), Ok(Object::T));
+    }
+
+    #[test]
+    fn test_structure_syntax() {
+        let (_, run) = interpreter();
+        run("(defstruct point x y)").unwrap();
+        assert_eq!(run("(point-y #S(point :x 1 :y 2))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(point-x (car '(#S(point :x 3))))"), Ok(Object::Integer(3)));
+        assert_eq!(run("(format nil \"~s\" (make-point :x 1 :y \"a\"))"), Ok(Object::String("#S(POINT :X 1 :Y \"a\")".into())));
+        assert_eq!(run("(equalp #S(point :x 1 :y 2) (make-point :x 1 :y 2))"), Ok(Object::T));
+        assert!(run("#S(missing :x 1)").is_err());
+    }
+}
@@ -0,0 +1,725 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, List, Params, Parameter, UNARY_PARAMETERS, BINARY_PARAMETERS, PLAIN_REST_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env, symbol};
+
+use crate::eval::{Eval, apply};
+use crate::package::designator_name;
+use crate::types::{Lambda, UserLambda, TypeDeclaration};
+use crate::types::equality::eql;
+use crate::types::class::{class_of, CLASS_T};
+use super::condition::type_error;
+use super::values::primary_value;
+use super::lambda::{lambda_list, body_forms};
+
+builtin_parameters! {
+    static ref DEFTYPE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref THE_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y")]);
+}
+
+// Type names which are not classes, with the type they are a subtype of
+const ATOMIC_TYPES: &[(&str, &str)] = &[
+    ("ATOM", CLASS_T),
+    ("BOOLEAN", "SYMBOL"),
+    ("KEYWORD", "SYMBOL"),
+    ("FIXNUM", "INTEGER"),
+    ("BIT", "FIXNUM"),
+    ("SHORT-FLOAT", "FLOAT"),
+    ("SINGLE-FLOAT", "FLOAT"),
+    ("DOUBLE-FLOAT", "FLOAT"),
+    ("LONG-FLOAT", "FLOAT"),
+    ("SIMPLE-STRING", "STRING"),
+];
+
+// Declarations which do not concern types
+const IGNORED_DECLARATIONS: &[&str] = &["IGNORE", "IGNORABLE", "OPTIMIZE", "DYNAMIC-EXTENT", "INLINE", "NOTINLINE", "FTYPE", "SPECIAL"];
+
+/// Whether type declarations are checked at runtime
+pub fn safe_mode(env: &RcEnv) -> bool {
+    env.borrow().get_safety() != 0
+}
+
+/// Expand a type defined with `deftype`, `None` if it is not one
+fn expand_type(env: &RcEnv, spec: &Object) -> Result<Option<Object>, EvalError> {
+    let (name, args) = match spec {
+        Object::Symbol(s) => (s.clone(), vec![]),
+        Object::List(l) => match l.car() {
+            Object::Symbol(s) => (s, list_items(l.cdr())),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let expander = env.borrow().get_types().borrow().get(&name).cloned();
+    match expander {
+        Some(expander) => Ok(Some(apply(expander, args, env)?)),
+        None => Ok(None),
+    }
+}
+
+// (Value, Whether the value itself is excluded), `None` when unbounded
+type Bound = Option<(f64, bool)>;
+
+/// Lower and upper bounds of a numeric type specifier, `*` means unbounded and a number
+/// in a list, as in `(integer (0) 10)`, is an exclusive bound
+fn bounds(args: &[Object]) -> Result<(Bound, Bound), EvalError> {
+    let bound = |obj: Option<&Object>| -> Result<Bound, EvalError> {
+        match obj {
+            None => Ok(None),
+            Some(Object::Symbol(s)) if s == "*" => Ok(None),
+            Some(o @ Object::List(_)) => match list_items(o.clone()).as_slice() {
+                [n] => numeric_value(n).map(|v| Some((v, true))).ok_or_else(|| EvalError::UnknownType(o.to_string())),
+                _ => Err(EvalError::UnknownType(o.to_string())),
+            },
+            Some(o) => numeric_value(o).map(|v| Some((v, false))).ok_or_else(|| EvalError::UnknownType(o.to_string())),
+        }
+    };
+    Ok((bound(args.first())?, bound(args.get(1))?))
+}
+
+fn numeric_value(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::Integer(i) => Some(*i as f64),
+        Object::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Whether an object is of the given type specifier
+pub fn typep(env: &RcEnv, obj: &Object, spec: &Object) -> Result<bool, EvalError> {
+    match spec {
+        Object::T => Ok(true),
+        Object::Nil => Ok(false),
+        Object::Symbol(name) => atomic_typep(env, obj, name),
+        Object::List(l) => {
+            let (head, args) = l.unpack();
+            let args = list_items(args);
+            let name = match head {
+                Object::Symbol(s) => s,
+                _ => return Err(EvalError::UnknownType(spec.to_string())),
+            };
+            match name.as_str() {
+                "OR" => {
+                    for arg in args.iter() {
+                        if typep(env, obj, arg)? {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                },
+                "AND" => {
+                    for arg in args.iter() {
+                        if !typep(env, obj, arg)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                },
+                "NOT" if args.len() == 1 => Ok(!typep(env, obj, &args[0])?),
+                "MEMBER" => Ok(args.iter().any(|a| eql(a, obj))),
+                "EQL" if args.len() == 1 => Ok(eql(&args[0], obj)),
+                "SATISFIES" if args.len() == 1 => {
+                    let predicate = args[0].clone().eval(env)?;
+                    Ok(apply(predicate, vec![obj.clone()], env)? != Object::Nil)
+                },
+                "MOD" if args.len() == 1 => {
+                    let n = guard_obj!(args[0].clone(), Integer)?;
+                    Ok(matches!(obj, Object::Integer(i) if *i >= 0 && *i < n))
+                },
+                "CONS" => match obj {
+                    Object::List(l) => {
+                        let (car, cdr) = l.unpack();
+                        let car_type = args.first().cloned().unwrap_or(Object::T);
+                        let cdr_type = args.get(1).cloned().unwrap_or(Object::T);
+                        Ok(typep(env, &car, &star(car_type))? && typep(env, &cdr, &star(cdr_type))?)
+                    },
+                    _ => Ok(false),
+                },
+                "INTEGER" | "FLOAT" | "REAL" | "RATIONAL" | "NUMBER" | "DOUBLE-FLOAT" | "SINGLE-FLOAT" => {
+                    if !atomic_typep(env, obj, &name)? {
+                        return Ok(false);
+                    }
+                    let (low, high) = bounds(&args)?;
+                    let value = numeric_value(obj).unwrap_or(0.0);
+                    let above = low.is_none_or(|(l, exclusive)| value > l || (!exclusive && value == l));
+                    let below = high.is_none_or(|(h, exclusive)| value < h || (!exclusive && value == h));
+                    Ok(above && below)
+                },
+                _ => match expand_type(env, spec)? {
+                    Some(expanded) => typep(env, obj, &expanded),
+                    None => Err(EvalError::UnknownType(spec.to_string())),
+                },
+            }
+        },
+        _ => Err(EvalError::UnknownType(spec.to_string())),
+    }
+}
+
+// `*` in a compound type stands for any type
+fn star(spec: Object) -> Object {
+    match spec {
+        Object::Symbol(s) if s == "*" => Object::T,
+        s => s,
+    }
+}
+
+fn atomic_typep(env: &RcEnv, obj: &Object, name: &str) -> Result<bool, EvalError> {
+    let ret = match name {
+        "T" => true,
+        "NIL" => false,
+        "ATOM" => !matches!(obj, Object::List(_)),
+        "BOOLEAN" => matches!(obj, Object::Nil | Object::T),
+        "KEYWORD" => matches!(obj, Object::Symbol(s) if s.starts_with(':')),
+        "FIXNUM" => matches!(obj, Object::Integer(_)),
+        "BIT" => matches!(obj, Object::Integer(0) | Object::Integer(1)),
+        "SHORT-FLOAT" | "SINGLE-FLOAT" | "DOUBLE-FLOAT" | "LONG-FLOAT" => matches!(obj, Object::Float(_)),
+        "SIMPLE-STRING" => matches!(obj, Object::String(_)),
+        _ => {
+            if let Some(expanded) = expand_type(env, &symbol(name))? {
+                return typep(env, obj, &expanded);
+            }
+            let classes = env.borrow().get_classes();
+            let classes = classes.borrow();
+            if classes.get(name).is_none() {
+                return Err(EvalError::UnknownType(name.into()));
+            }
+            classes.subclass_p(class_of(obj), name)
+        },
+    };
+    Ok(ret)
+}
+
+/// Most specific standard type of an object
+pub fn type_of(obj: &Object) -> Object {
+    match obj {
+        Object::Nil => symbol("NULL"),
+        Object::T => symbol("BOOLEAN"),
+        Object::Symbol(s) if s.starts_with(':') => symbol("KEYWORD"),
+        Object::Integer(0) | Object::Integer(1) => symbol("BIT"),
+        Object::Integer(_) => symbol("FIXNUM"),
+        Object::Float(_) => symbol("DOUBLE-FLOAT"),
+        o => symbol(class_of(o)),
+    }
+}
+
+// (Kind, Lower bound, Upper bound)
+type NumericRange = (String, Bound, Bound);
+
+fn numeric_range(spec: &Object) -> Result<Option<NumericRange>, EvalError> {
+    match spec {
+        Object::Symbol(s) => Ok(match s.as_str() {
+            "FIXNUM" => Some(("INTEGER".into(), Some((isize::MIN as f64, false)), Some((isize::MAX as f64, false)))),
+            "BIT" => Some(("INTEGER".into(), Some((0.0, false)), Some((1.0, false)))),
+            "INTEGER" | "FLOAT" | "REAL" | "RATIONAL" | "NUMBER" => Some((s.clone(), None, None)),
+            _ => None,
+        }),
+        Object::List(l) => {
+            let (head, args) = l.unpack();
+            let args = list_items(args);
+            match head {
+                Object::Symbol(s) if matches!(s.as_str(), "INTEGER" | "FLOAT" | "REAL" | "RATIONAL" | "NUMBER") => {
+                    let (mut low, mut high) = bounds(&args)?;
+                    // Integers between exclusive bounds are also between the next inclusive ones
+                    if s == "INTEGER" {
+                        low = low.map(|(l, exclusive)| (if exclusive { l.floor() + 1.0 } else { l.ceil() }, false));
+                        high = high.map(|(h, exclusive)| (if exclusive { h.ceil() - 1.0 } else { h.floor() }, false));
+                    }
+                    Ok(Some((s, low, high)))
+                },
+                Object::Symbol(s) if s == "MOD" && args.len() == 1 => {
+                    let n = guard_obj!(args[0].clone(), Integer)?;
+                    Ok(Some(("INTEGER".into(), Some((0.0, false)), Some(((n - 1) as f64, false)))))
+                },
+                _ => Ok(None),
+            }
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Whether `t1` is a subtype of `t2`, along with whether the answer is certain
+pub fn subtypep(env: &RcEnv, t1: &Object, t2: &Object) -> Result<(bool, bool), EvalError> {
+    if t1 == t2 || matches!(t2, Object::T) || matches!(t1, Object::Nil) {
+        return Ok((true, true));
+    }
+    if let Some(t1) = expand_type(env, t1)? {
+        return subtypep(env, &t1, t2);
+    }
+    if let Some(t2) = expand_type(env, t2)? {
+        return subtypep(env, t1, &t2);
+    }
+
+    let compound = |spec: &Object| -> Option<(String, Vec<Object>)> {
+        match spec {
+            Object::List(l) => {
+                let (head, args) = l.unpack();
+                designator_name(&head).map(|h| (h, list_items(args)))
+            },
+            _ => None,
+        }
+    };
+    if let Some((head, args)) = compound(t1) {
+        match head.as_str() {
+            "OR" => {
+                let mut certain = true;
+                for arg in args.iter() {
+                    let (sub, sure) = subtypep(env, arg, t2)?;
+                    if !sub {
+                        return Ok((false, sure && certain));
+                    }
+                    certain &= sure;
+                }
+                return Ok((true, true));
+            },
+            "AND" => {
+                for arg in args.iter() {
+                    if subtypep(env, arg, t2)?.0 {
+                        return Ok((true, true));
+                    }
+                }
+                return Ok((false, false));
+            },
+            "MEMBER" | "EQL" => {
+                for arg in args.iter() {
+                    if !typep(env, arg, t2)? {
+                        return Ok((false, true));
+                    }
+                }
+                return Ok((true, true));
+            },
+            _ => (),
+        }
+    }
+    if let Some((head, args)) = compound(t2) {
+        match head.as_str() {
+            "AND" => {
+                let mut certain = true;
+                for arg in args.iter() {
+                    let (sub, sure) = subtypep(env, t1, arg)?;
+                    if !sub {
+                        return Ok((false, sure));
+                    }
+                    certain &= sure;
+                }
+                return Ok((true, certain));
+            },
+            "OR" => {
+                for arg in args.iter() {
+                    if subtypep(env, t1, arg)?.0 {
+                        return Ok((true, true));
+                    }
+                }
+                return Ok((false, false));
+            },
+            _ => (),
+        }
+    }
+
+    if let (Some((k1, l1, h1)), Some((k2, l2, h2))) = (numeric_range(t1)?, numeric_range(t2)?) {
+        let kind = env.borrow().get_classes().borrow().subclass_p(&k1, &k2);
+        // A bound is within another one unless only the other excludes the same value
+        let low = match (l1, l2) {
+            (_, None) => true,
+            (Some((a, a_excl)), Some((b, b_excl))) => a > b || (a == b && (a_excl || !b_excl)),
+            (None, Some(_)) => false,
+        };
+        let high = match (h1, h2) {
+            (_, None) => true,
+            (Some((a, a_excl)), Some((b, b_excl))) => a < b || (a == b && (a_excl || !b_excl)),
+            (None, Some(_)) => false,
+        };
+        return Ok((kind && low && high, true));
+    }
+
+    match (t1, t2) {
+        (Object::Symbol(a), Object::Symbol(b)) => {
+            if b == "ATOM" {
+                let (cons_sub, _) = subtypep(env, &symbol("CONS"), t1)?;
+                let (sub_cons, _) = subtypep(env, t1, &symbol("CONS"))?;
+                return Ok((!cons_sub && !sub_cons, true));
+            }
+            if let Some((_, parent)) = ATOMIC_TYPES.iter().find(|(n, _)| n == a) {
+                return subtypep(env, &symbol(parent), t2);
+            }
+            if ATOMIC_TYPES.iter().any(|(n, _)| n == b) {
+                return Ok((false, true));
+            }
+            let classes = env.borrow().get_classes();
+            let classes = classes.borrow();
+            if classes.get(a).is_none() {
+                return Err(EvalError::UnknownType(a.clone()));
+            }
+            if classes.get(b).is_none() {
+                return Err(EvalError::UnknownType(b.clone()));
+            }
+            Ok((classes.subclass_p(a, b), true))
+        },
+        (_, Object::Symbol(b)) => {
+            // A compound type is a subtype of what its base type is a subtype of
+            match compound(t1) {
+                Some((head, _)) if head != "NOT" && head != "SATISFIES" => {
+                    let (sub, _) = subtypep(env, &symbol(head), &symbol(b))?;
+                    Ok((sub, sub))
+                },
+                _ => Ok((false, false)),
+            }
+        },
+        _ => Ok((false, false)),
+    }
+}
+
+/// Convert an object to the given type
+pub fn coerce(env: &RcEnv, obj: Object, spec: &Object) -> Result<Object, EvalError> {
+    if typep(env, &obj, spec)? {
+        return Ok(obj);
+    }
+    let ret = match (&obj, designator_name(spec).as_deref()) {
+        (Object::Integer(i), Some("FLOAT" | "SHORT-FLOAT" | "SINGLE-FLOAT" | "DOUBLE-FLOAT" | "LONG-FLOAT")) => Some(Object::Float(*i as f64)),
+        (Object::Symbol(_), Some("FUNCTION")) => Some(obj.clone().eval(env)?),
+        (Object::List(l), Some("FUNCTION")) if designator_name(&l.car()).as_deref() == Some("LAMBDA") => Some(obj.clone().eval(env)?),
+        _ => None,
+    };
+    match ret {
+        Some(r) if typep(env, &r, spec)? => Ok(r),
+        _ => Err(type_error(env, obj, spec.clone(), None)),
+    }
+}
+
+pub(crate) fn boolean(b: bool) -> Object {
+    if b { Object::T } else { Object::Nil }
+}
+
+pub struct ObjectTypep;
+
+impl BuiltinFunc for ObjectTypep {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "typep"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let obj = rcenv_get!(env, "X")?;
+        let spec = rcenv_get!(env, "Y")?;
+        Ok(boolean(typep(&caller_env(env), &obj, &spec)?))
+    }
+}
+
+pub struct ObjectTypeOf;
+
+impl BuiltinFunc for ObjectTypeOf {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "type-of"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(type_of(&rcenv_get!(env, "X")?))
+    }
+}
+
+pub struct ObjectSubtypep;
+
+impl BuiltinFunc for ObjectSubtypep {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "subtypep"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    // Returns whether it is a subtype and whether the answer is certain
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let t1 = rcenv_get!(env, "X")?;
+        let t2 = rcenv_get!(env, "Y")?;
+        let (sub, certain) = subtypep(&caller_env(env), &t1, &t2)?;
+        Ok(vec![boolean(sub), boolean(certain)])
+    }
+}
+
+pub struct ObjectDeftype;
+
+impl BuiltinFunc for ObjectDeftype {
+    fn get_parameters(&self) -> &Params {
+        &DEFTYPE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "deftype"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let params = lambda_list(rcenv_get!(env, "Y")?)?;
+        let body = body_forms(rcenv_get!(env, "Z")?);
+        let expander = Lambda::Named(name.clone(), declared_lambda(params, body)?.with_env(&caller_env(env)));
+        env.borrow().get_types().borrow_mut().insert(name.clone(), expander.into());
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectCoerce;
+
+impl BuiltinFunc for ObjectCoerce {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "coerce"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let obj = rcenv_get!(env, "X")?;
+        let spec = rcenv_get!(env, "Y")?;
+        coerce(&caller_env(env), obj, &spec)
+    }
+}
+
+pub struct ObjectThe;
+
+impl ObjectThe {
+    fn run(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let caller = caller_env(env);
+        let spec = rcenv_get!(env, "X")?;
+        let form = rcenv_get!(env, "Y")?;
+        let values = form.eval_values(&caller)?;
+        if !safe_mode(&caller) {
+            return Ok(values);
+        }
+        // (values type*) checks each value, a plain type only the primary one
+        let specs = match &spec {
+            Object::List(l) if designator_name(&l.car()).as_deref() == Some("VALUES") => list_items(l.cdr()),
+            _ => vec![spec.clone()],
+        };
+        for (spec, value) in specs.iter().zip(values.iter().chain(std::iter::repeat(&Object::Nil))) {
+            if !typep(&caller, value, spec)? {
+                return Err(type_error(&caller, value.clone(), spec.clone(), None));
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl BuiltinFunc for ObjectThe {
+    fn get_parameters(&self) -> &Params {
+        &THE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "the"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.run(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.run(env)
+    }
+}
+
+/// Apply global declarations, only `optimize` has an effect for now
+fn proclaim(env: &RcEnv, spec: Object) -> Result<(), EvalError> {
+    let (head, args) = guard_obj!(spec, List)?.unpack();
+    if designator_name(&head).as_deref() != Some("OPTIMIZE") {
+        return Ok(());
+    }
+    for quality in list_items(args) {
+        let (name, level) = match quality {
+            Object::List(l) => {
+                let (name, rest) = l.unpack();
+                (name, guard_obj!(rest, List)?.car())
+            },
+            name => (name, Object::Integer(3)),
+        };
+        if designator_name(&name).as_deref() == Some("SAFETY") {
+            let level = guard_obj!(level, Integer)?;
+            env.borrow_mut().set_safety(level);
+        }
+    }
+    Ok(())
+}
+
+pub struct ObjectProclaim;
+
+impl BuiltinFunc for ObjectProclaim {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "proclaim"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        proclaim(&caller_env(env), rcenv_get!(env, "X")?)?;
+        Ok(Object::Nil)
+    }
+}
+
+pub struct ObjectDeclaim;
+
+impl BuiltinFunc for ObjectDeclaim {
+    fn get_parameters(&self) -> &Params {
+        &PLAIN_REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "declaim"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        for spec in list_items(rcenv_get!(env, "X")?) {
+            proclaim(&caller, spec)?;
+        }
+        Ok(Object::Nil)
+    }
+}
+
+/// Whether a form is a `(declare ...)` expression
+pub(crate) fn is_declaration(form: &Object) -> bool {
+    match form {
+        Object::List(l) => designator_name(&l.car()).as_deref() == Some("DECLARE"),
+        _ => false,
+    }
+}
+
+/// Type declarations at the start of a body, along with the forms after them.
+/// The other declarations do not change how the body runs and are dropped.
+pub(crate) fn parse_declarations(body: List) -> Result<(Vec<TypeDeclaration>, List), EvalError> {
+    let mut declarations = vec![];
+    let mut body = body;
+    while matches!(&body, List::Cons(form, _) if is_declaration(form)) {
+        let (form, rest) = body.unpack();
+        for spec in list_items(guard_obj!(form, List)?.cdr()) {
+            let (head, args) = guard_obj!(spec, List)?.unpack();
+            let mut variables = list_items(args);
+            let name = designator_name(&head).unwrap_or_default();
+            let type_spec = if IGNORED_DECLARATIONS.contains(&name.as_str()) {
+                continue;
+            } else if name == "TYPE" {
+                if variables.is_empty() {
+                    return Err(EvalError::ParameterTypeMismatched);
+                }
+                variables.remove(0)
+            } else {
+                head
+            };
+            declarations.push(TypeDeclaration { type_spec, variables });
+        }
+        body = body_forms(rest);
+    }
+    Ok((declarations, body))
+}
+
+/// Lambda whose declarations are parsed once, rather than each time it is called
+pub(crate) fn declared_lambda(params: Params, body: List) -> Result<UserLambda, EvalError> {
+    let (declarations, body) = parse_declarations(body)?;
+    Ok(UserLambda::new(params, body).with_declarations(declarations))
+}
+
+/// Check the declared variables against their types, in the frame holding their bindings
+pub(crate) fn check_declarations(declarations: &[TypeDeclaration], env: &RcEnv) -> Result<(), EvalError> {
+    if !safe_mode(env) {
+        return Ok(());
+    }
+    for declaration in declarations {
+        for var in declaration.variables.iter() {
+            let value = var.clone().eval(env)?;
+            if !typep(env, &value, &declaration.type_spec)? {
+                return Err(type_error(env, value, declaration.type_spec.clone(), Some(var.clone())));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply the declarations at the start of a body in the frame holding its bindings, returning
+/// the forms after them
+pub(crate) fn declare(body: List, env: &RcEnv) -> Result<List, EvalError> {
+    let (declarations, body) = parse_declarations(body)?;
+    check_declarations(&declarations, env)?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{typep, subtypep, declared_lambda, body_forms, Object, Params, TypeDeclaration};
+    use crate::builtins::{generate_default_env, symbol};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_compound_types() {
+        let env = generate_default_env();
+        let range = Object::from(vec![symbol("INTEGER"), Object::Integer(0), Object::Integer(10)]);
+        assert_eq!(typep(&env, &Object::Integer(3), &range), Ok(true));
+        assert_eq!(typep(&env, &Object::Integer(11), &range), Ok(false));
+        assert_eq!(typep(&env, &Object::Nil, &symbol("LIST")), Ok(true));
+        assert_eq!(subtypep(&env, &symbol("BIT"), &range), Ok((true, true)));
+        assert_eq!(subtypep(&env, &symbol("FIXNUM"), &symbol("STRING")), Ok((false, true)));
+        assert!(typep(&env, &Object::Integer(3), &symbol("NO-SUCH-TYPE")).is_err());
+    }
+
+    #[test]
+    fn test_exclusive_bounds() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(list (typep 7 '(integer (5) 10)) (typep 5 '(integer (5) 10)) (typep 10 '(integer 5 (10))))"), run("'(t nil nil)"));
+        assert_eq!(run("(list (typep 0.5 '(float (0.0) (1.0))) (typep 1.0 '(float (0.0) (1.0))) (typep 1.0 '(real * (1)))) "), run("'(t nil nil)"));
+        assert_eq!(run("(multiple-value-list (subtypep '(integer (0) (10)) '(integer 0 10)))"), run("'(t t)"));
+        assert_eq!(run("(multiple-value-list (subtypep '(integer 0 10) '(integer (0) 10)))"), run("'(nil t)"));
+        assert_eq!(run("(multiple-value-list (subtypep '(mod 4) '(integer 0 (4))))"), run("'(t t)"));
+        assert_eq!(run("(multiple-value-list (subtypep '(integer (0) (4)) '(integer 1 3)))"), run("'(t t)"));
+        assert_eq!(run("(multiple-value-list (subtypep '(real (0) 1) '(real 0 1)))"), run("'(t t)"));
+        assert_eq!(run("(multiple-value-list (subtypep '(real 0 1) '(real (0) 1)))"), run("'(nil t)"));
+        assert!(run("(typep 1 '(integer (1 2)))").is_err());
+        assert!(run("(typep 1 '(integer (a)))").is_err());
+    }
+
+    #[test]
+    fn test_declarations() {
+        let (env, run) = interpreter();
+        assert_eq!(run("(let ((x 5)) (declare (type integer x) (ignorable x)) (declare (fixnum x)) x)"), Ok(Object::Integer(5)));
+        assert!(run("(let* ((x \"a\")) (declare (integer x)) x)").is_err());
+        run("(defun f (x &optional y) (declare (string x)) (cons x y))").unwrap();
+        assert_eq!(run("(f \"a\")"), run("'(\"a\")"));
+        assert_eq!(run("(handler-case (f 1) (type-error (e) (type-error-datum e)))"), Ok(Object::Integer(1)));
+        assert!(run("(multiple-value-bind (a b) (floor 7 2) (declare (string b)) a)").is_err());
+        assert_eq!(run("(destructuring-bind (a . b) '(1 2) (declare (list b)) b)"), run("'(2)"));
+        assert!(run("(progn (declare (integer x)) 1)").is_err());
+        assert_eq!(env.borrow().get_safety(), 1);
+        run("(declaim (optimize (safety 0)))").unwrap();
+        assert_eq!(env.borrow().get_safety(), 0);
+        assert_eq!(run("(f 1)"), run("'(1)"));
+    }
+
+    #[test]
+    fn test_parsed_declarations() {
+        let (env, run) = interpreter();
+        let body = body_forms(run("'((declare (ignore y) (type integer x y)) (declare (string z)) x)").unwrap());
+        let lambda = declared_lambda(Params::from(vec![]), body).unwrap();
+        assert_eq!(lambda.get_declarations(), &[
+            TypeDeclaration { type_spec: symbol("INTEGER"), variables: vec![symbol("X"), symbol("Y")] },
+            TypeDeclaration { type_spec: symbol("STRING"), variables: vec![symbol("Z")] },
+        ]);
+        assert_eq!(lambda.get_body().into_iter().collect::<Vec<_>>(), vec![symbol("X")]);
+        // Malformed declarations are found when the function is defined, not when it is called
+        assert!(run("(defun g (x) (declare (type)) x)").is_err());
+        assert!(run("(lambda (x) (declare 1) x)").is_err());
+        run("(defmacro m (x) (declare (integer x)) `(quote ,x))").unwrap();
+        assert_eq!(run("(m 1)"), Ok(Object::Integer(1)));
+        assert!(run("(m a)").is_err());
+        run("(defmethod size ((x integer)) (declare (fixnum x)) x)").unwrap();
+        assert_eq!(run("(size 3)"), Ok(Object::Integer(3)));
+        assert_eq!(run("(function-lambda-expression (lambda (x) (declare (integer x)) x))"), run("'(lambda (x) (declare (type integer x)) x)"));
+        env.borrow_mut().set_safety(0);
+        assert_eq!(run("(m a)"), run("'a"));
+    }
+}
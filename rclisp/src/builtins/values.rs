@@ -0,0 +1,229 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Env, Object, EvalError, List, Params, Parameter, REST_PARAMETERS, UNARY_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env};
+
+use crate::eval::{Eval, apply, apply_values, eval_body, eval_body_values};
+use crate::env::DynamicBindings;
+use super::types::declare;
+
+builtin_parameters! {
+    static ref MULTIPLE_VALUE_BIND_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain("Y"), Parameter::plain_rest("Z")]);
+    static ref MULTIPLE_VALUE_LIST_PARAMETERS: Params = Params::from(vec![Parameter::plain("X")]);
+    static ref MULTIPLE_VALUE_CALL_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::plain_rest("Y")]);
+    static ref NTH_VALUE_PARAMETERS: Params = Params::from(vec![Parameter::normal("X"), Parameter::plain("Y")]);
+}
+
+/// Primary value of a set of values, `NIL` if there are none
+pub fn primary_value(values: Vec<Object>) -> Object {
+    values.into_iter().next().unwrap_or(Object::Nil)
+}
+
+pub struct ObjectValues;
+
+impl BuiltinFunc for ObjectValues {
+    fn get_parameters(&self) -> &Params {
+        &REST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "values"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        Ok(list_items(rcenv_get!(env, "X")?))
+    }
+}
+
+pub struct ObjectValuesList;
+
+impl BuiltinFunc for ObjectValuesList {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "values-list"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        Ok(primary_value(self.eval_values(env)?))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        match rcenv_get!(env, "X")? {
+            Object::Nil => Ok(vec![]),
+            Object::List(l) => Ok(l.into_iter().collect()),
+            _ => Err(EvalError::ParameterTypeMismatched),
+        }
+    }
+}
+
+pub struct ObjectMultipleValueBind;
+
+impl ObjectMultipleValueBind {
+    /// Bind the variables in a new frame, returning the body to run in it while the bindings are alive
+    fn bind(&self, env: &RcEnv) -> Result<(List, RcEnv, DynamicBindings), EvalError> {
+        let caller = caller_env(env);
+        let vars = list_items(rcenv_get!(env, "X")?);
+        let form = rcenv_get!(env, "Y")?;
+        let mut values = form.eval_values(&caller)?.into_iter();
+        let frame = Env::inherit(&caller).wrap();
+        let mut bindings = DynamicBindings::new(&frame);
+        for var in vars {
+            let name = guard_obj!(var, Symbol)?;
+            bindings.bind(&frame, name, values.next().unwrap_or(Object::Nil))?;
+        }
+        let body = match rcenv_get!(env, "Z")? {
+            Object::List(l) => l.to_list(),
+            _ => List::EndsWith(Object::Nil),
+        };
+        let body = declare(body, &frame)?;
+        Ok((body, frame, bindings))
+    }
+}
+
+impl BuiltinFunc for ObjectMultipleValueBind {
+    fn get_parameters(&self) -> &Params {
+        &MULTIPLE_VALUE_BIND_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "multiple-value-bind"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let (body, frame, _bindings) = self.bind(env)?;
+        eval_body(body, &frame)
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let (body, frame, _bindings) = self.bind(env)?;
+        eval_body_values(body, &frame)
+    }
+}
+
+pub struct ObjectMultipleValueList;
+
+impl BuiltinFunc for ObjectMultipleValueList {
+    fn get_parameters(&self) -> &Params {
+        &MULTIPLE_VALUE_LIST_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "multiple-value-list"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let form = rcenv_get!(env, "X")?;
+        let values = form.eval_values(&caller_env(env))?;
+        Ok(Object::from(values))
+    }
+}
+
+pub struct ObjectMultipleValueCall;
+
+impl ObjectMultipleValueCall {
+    fn arguments(&self, env: &RcEnv) -> Result<(Object, Vec<Object>), EvalError> {
+        let caller = caller_env(env);
+        let function = rcenv_get!(env, "X")?;
+        let mut args = vec![];
+        for form in list_items(rcenv_get!(env, "Y")?) {
+            args.append(&mut form.eval_values(&caller)?);
+        }
+        Ok((function, args))
+    }
+}
+
+impl BuiltinFunc for ObjectMultipleValueCall {
+    fn get_parameters(&self) -> &Params {
+        &MULTIPLE_VALUE_CALL_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "multiple-value-call"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let (function, args) = self.arguments(env)?;
+        apply(function, args, &caller_env(env))
+    }
+
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        let (function, args) = self.arguments(env)?;
+        apply_values(function, args, &caller_env(env))
+    }
+}
+
+pub struct ObjectNthValue;
+
+impl BuiltinFunc for ObjectNthValue {
+    fn get_parameters(&self) -> &Params {
+        &NTH_VALUE_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "nth-value"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let n = guard_obj!(rcenv_get!(env, "X")?, Integer)?;
+        let form = rcenv_get!(env, "Y")?;
+        let values = form.eval_values(&caller_env(env))?;
+        if n < 0 {
+            return Err(EvalError::ParameterTypeMismatched);
+        }
+        Ok(values.into_iter().nth(n as usize).unwrap_or(Object::Nil))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Object;
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_values() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(multiple-value-list (values 1 2 3))"), run("'(1 2 3)"));
+        assert_eq!(run("(multiple-value-list (values))"), Ok(Object::Nil));
+        assert_eq!(run("(values 1 2)"), Ok(Object::Integer(1)));
+        assert_eq!(run("(values)"), Ok(Object::Nil));
+        assert_eq!(run("(multiple-value-bind (a b c) (values 1 2) (list a b c))"), run("'(1 2 nil)"));
+        assert_eq!(run("(multiple-value-bind (a) (values 1 2) a)"), Ok(Object::Integer(1)));
+        assert_eq!(run("(nth-value 1 (values 1 2 3))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(nth-value 5 (values 1 2 3))"), Ok(Object::Nil));
+        assert_eq!(run("(multiple-value-list (values-list '(a b)))"), run("'(a b)"));
+    }
+
+    #[test]
+    fn test_secondary_values() {
+        let (_, run) = interpreter();
+        run("(defvar *table* (make-hash-table))").unwrap();
+        run("(setf (gethash 'a *table*) nil)").unwrap();
+        assert_eq!(run("(multiple-value-list (gethash 'a *table*))"), run("'(nil t)"));
+        assert_eq!(run("(multiple-value-list (gethash 'b *table*))"), run("'(nil nil)"));
+        assert_eq!(run("(multiple-value-list (floor 7 2))"), run("'(3 1)"));
+        assert_eq!(run("(multiple-value-list (floor -7 2))"), run("'(-4 1)"));
+    }
+
+    #[test]
+    fn test_values_propagation() {
+        let (_, run) = interpreter();
+        // Values of the last form pass through
+        assert_eq!(run("(multiple-value-list (let ((x 1)) (values x 2)))"), run("'(1 2)"));
+        assert_eq!(run("(multiple-value-list (progn 0 (values 1 2)))"), run("'(1 2)"));
+        run("(defun two () (values 1 2))").unwrap();
+        assert_eq!(run("(multiple-value-list (two))"), run("'(1 2)"));
+        assert_eq!(run("(multiple-value-list (or nil (values 1 2)))"), run("'(1 2)"));
+        assert_eq!(run("(multiple-value-list (and t (two)))"), run("'(1 2)"));
+        assert_eq!(run("(multiple-value-list (or (two) nil))"), run("'(1)"));
+        assert_eq!(run("(multiple-value-list (and (two) nil (two)))"), run("'(nil)"));
+        assert_eq!(run("(list (or) (and))"), run("'(nil t)"));
+        // Forms which are not in tail position only give their primary value
+        assert_eq!(run("(multiple-value-list (progn (values 1 2) 3))"), run("'(3)"));
+        assert_eq!(run("(multiple-value-list (let ((x (two))) x))"), run("'(1)"));
+        assert_eq!(run("(list (two) (two))"), run("'(1 1)"));
+    }
+}
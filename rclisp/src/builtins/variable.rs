@@ -0,0 +1,152 @@
+use super::{builtin_parameters, BuiltinFunc, RcEnv, Object, EvalError, Params, Parameter, UNARY_PARAMETERS, BINARY_PARAMETERS, rcenv_get, guard_obj, list_items, caller_env, symbol};
+
+use crate::env::VariableKind;
+use crate::eval::Eval;
+
+builtin_parameters! {
+    static ref DEFVAR_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::plain_rest("Y")]);
+    static ref DEFPARAMETER_PARAMETERS: Params = Params::from(vec![Parameter::plain("X"), Parameter::normal("Y"), Parameter::optional("Z", Object::Nil)]);
+}
+
+pub struct ObjectDefvar;
+
+impl BuiltinFunc for ObjectDefvar {
+    fn get_parameters(&self) -> &Params {
+        &DEFVAR_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defvar"
+    }
+
+    // The initial value is only evaluated if the variable is unbound
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let caller = caller_env(env);
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let rest = list_items(rcenv_get!(env, "Y")?);
+        caller.borrow_mut().proclaim_special(&name)?;
+        let unbound = caller.borrow().get_global_str(&name).is_none();
+        if let (Some(form), true) = (rest.first(), unbound) {
+            let value = form.clone().eval(&caller)?;
+            caller.borrow_mut().insert_global_str(&name, value);
+        }
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectDefparameter;
+
+impl BuiltinFunc for ObjectDefparameter {
+    fn get_parameters(&self) -> &Params {
+        &DEFPARAMETER_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defparameter"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let value = rcenv_get!(env, "Y")?;
+        env.borrow_mut().proclaim_special(&name)?;
+        env.borrow_mut().insert_global_str(&name, value);
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectDefconstant;
+
+impl BuiltinFunc for ObjectDefconstant {
+    fn get_parameters(&self) -> &Params {
+        &DEFPARAMETER_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "defconstant"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let value = rcenv_get!(env, "Y")?;
+        env.borrow_mut().define_constant(&name, value)?;
+        Ok(symbol(name))
+    }
+}
+
+pub struct ObjectSymbolValue;
+
+impl BuiltinFunc for ObjectSymbolValue {
+    fn get_parameters(&self) -> &Params {
+        &UNARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "symbol-value"
+    }
+
+    // Lexical bindings are not visible, only the global or dynamic value
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let name = guard_obj!(rcenv_get!(env, "X")?, Symbol)?;
+        let value = env.borrow().get_global_str(&name);
+        value.ok_or(EvalError::UnboundVariable(name))
+    }
+}
+
+pub struct ObjectSetfSymbolValue;
+
+impl BuiltinFunc for ObjectSetfSymbolValue {
+    fn get_parameters(&self) -> &Params {
+        &BINARY_PARAMETERS
+    }
+
+    fn get_name(&self) -> &str {
+        "(setf symbol-value)"
+    }
+
+    fn eval(&self, env: &RcEnv) -> Result<Object, EvalError> {
+        let value = rcenv_get!(env, "X")?;
+        let name = guard_obj!(rcenv_get!(env, "Y")?, Symbol)?;
+        if env.borrow().variable_kind(&name) == Some(VariableKind::Constant) {
+            return Err(EvalError::ConstantVariable(name));
+        }
+        env.borrow_mut().insert_global_str(&name, value.clone());
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EvalError, Object};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_special_variables_exits() {
+        let (env, run) = interpreter();
+        run("(defvar *depth* 0)").unwrap();
+        run("(defun depth () *depth*)").unwrap();
+        // The global value comes back however the binding form is left
+        assert_eq!(run("(catch 'out (let ((*depth* 1)) (throw 'out (depth))))"), Ok(Object::Integer(1)));
+        assert_eq!(run("(block b (let ((*depth* 2)) (return-from b (depth))))"), Ok(Object::Integer(2)));
+        assert_eq!(run("(handler-case (let ((*depth* 3)) (error \"e\")) (error () (depth)))"), Ok(Object::Integer(0)));
+        run("(defun f (*depth*) (car *depth*))").unwrap();
+        assert_eq!(run("(f 4)"), Err(EvalError::ParameterTypeMismatched));
+        assert_eq!(env.borrow().get_str("*DEPTH*"), Ok(Object::Integer(0)));
+        // A special variable bound without a global value is unbound again afterwards
+        run("(defvar *unset*)").unwrap();
+        assert_eq!(run("(let ((*unset* 1)) *unset*)"), Ok(Object::Integer(1)));
+        assert!(matches!(run("*unset*"), Err(EvalError::UnboundVariable(v)) if v == "*UNSET*"));
+    }
+
+    #[test]
+    fn test_constant_variables() {
+        let (_, run) = interpreter();
+        run("(defconstant +limit+ 10)").unwrap();
+        assert!(matches!(run("(setq +limit+ 1)"), Err(EvalError::ConstantVariable(c)) if c == "+LIMIT+"));
+        assert!(run("(let ((+limit+ 1)) +limit+)").is_err());
+        assert!(run("(defun g (+limit+) +limit+)").is_ok() && run("(g 1)").is_err());
+        assert!(run("(defvar +limit+ 1)").is_err());
+        // Redefining it with the same value is allowed
+        assert_eq!(run("(defconstant +limit+ 10)"), run("'+limit+"));
+        assert_eq!(run("+limit+"), Ok(Object::Integer(10)));
+    }
+}
@@ -1,10 +1,14 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::rc::Rc;
 
-use crate::types::Object;
+use crate::types::{Object, UserLambda, symbol, cons, StructDef, Classes, Generic, Conditions};
+use crate::types::equality::eql;
+use crate::package::{Packages, COMMON_LISP_USER};
+
+const DEFAULT_SAFETY: isize = 1;
 
 #[macro_export]
 macro_rules! rcenv_get {
@@ -17,12 +21,59 @@ macro_rules! rcenv_get {
 pub enum EnvError {
     SymbolNotFound(String),
     NotASymbol(String),
+    ConstantVariable(String),
+}
+
+// Variables proclaimed globally, they are never bound lexically
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VariableKind {
+    Special,
+    Constant,
+}
+
+// Name of a function, a symbol or a list `(setf accessor)`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FunctionName {
+    Symbol(String),
+    Setf(String),
+}
+
+// How setf stores into a place whose accessor has been given a setf expansion
+#[derive(Clone, Debug, PartialEq)]
+pub enum SetfExpansion {
+    Update(Object),       // Update function of a short `defsetf`
+    Defsetf(UserLambda),  // Store form expander of a long `defsetf`
+    Expander(UserLambda), // Expander of `define-setf-expander`, returning five values
+}
+
+// Access to the host which embedders may withhold from Lisp code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    FileSystem,
+}
+
+/// Registries shared by every frame of an environment, held by its root frame
+#[derive(Debug, Default, PartialEq)]
+pub struct Globals {
+    packages: Rc<RefCell<Packages>>,
+    structures: Rc<RefCell<HashMap<String, StructDef>>>,
+    classes: Rc<RefCell<Classes>>,
+    generics: Rc<RefCell<HashMap<FunctionName, Generic>>>,
+    conditions: Rc<RefCell<Conditions>>,
+    catchers: Rc<RefCell<Vec<(Object, usize)>>>, // Active catch tags with their frame ids, innermost last
+    variables: RefCell<HashMap<String, VariableKind>>,
+    capabilities: RefCell<HashSet<Capability>>,
+    types: Rc<RefCell<HashMap<String, Object>>>, // Expanders of the types defined with deftype
+    setf_expansions: Rc<RefCell<HashMap<String, SetfExpansion>>>,
+    safety: Cell<isize>, // Global SAFETY optimization quality, type declarations are not checked at 0
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Env {
     parent: Option<Rc<RefCell<Env>>>,
     inner: HashMap<String, Object>,
+    setf_functions: HashMap<String, Object>, // Functions named (setf accessor), by accessor
+    globals: Option<Rc<Globals>>, // Only in the root frame, the others reach it through their parent
 }
 
 pub type RcEnv = Rc<RefCell<Env>>;
@@ -32,15 +83,39 @@ impl fmt::Display for EnvError {
         match self {
             Self::SymbolNotFound(s) => write!(f, "Symbol \"{}\" not found", s),
             Self::NotASymbol(s) => write!(f, "{} is not a symbol", s),
+            Self::ConstantVariable(s) => write!(f, "{} is a constant and cannot be changed", s),
         }
     }
 }
 
 impl Error for EnvError {}
 
+impl From<FunctionName> for Object {
+    fn from(name: FunctionName) -> Self {
+        match name {
+            FunctionName::Symbol(s) => symbol(s),
+            FunctionName::Setf(s) => cons(symbol("SETF"), cons(symbol(s), Object::Nil)),
+        }
+    }
+}
+
+impl fmt::Display for FunctionName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::Symbol(s) => write!(f, "{}", s),
+            Self::Setf(s) => write!(f, "(SETF {})", s),
+        }
+    }
+}
+
 impl Env {
     pub fn new() -> Self {
+        let globals = Globals {
+            safety: Cell::new(DEFAULT_SAFETY),
+            ..Default::default()
+        };
         Self {
+            globals: Some(Rc::new(globals)),
             ..Default::default()
         }
     }
@@ -56,7 +131,80 @@ impl Env {
     pub fn inherit(parent: &Rc<RefCell<Self>>) -> Self {
         Self {
             parent: Some(parent.clone()),
-            inner: HashMap::new(),
+            ..Default::default()
+        }
+    }
+
+    pub fn get_parent(&self) -> Option<Rc<RefCell<Self>>> {
+        self.parent.clone()
+    }
+
+    /// Registries of the environment, from its root frame
+    pub fn get_globals(&self) -> Rc<Globals> {
+        match (&self.globals, &self.parent) {
+            (Some(globals), _) => globals.clone(),
+            (None, Some(parent)) => parent.borrow().get_globals(),
+            (None, None) => unreachable!("Root frames are created with their globals"),
+        }
+    }
+
+    pub fn get_packages(&self) -> Rc<RefCell<Packages>> {
+        self.get_globals().packages.clone()
+    }
+
+    pub fn get_structures(&self) -> Rc<RefCell<HashMap<String, StructDef>>> {
+        self.get_globals().structures.clone()
+    }
+
+    pub fn get_classes(&self) -> Rc<RefCell<Classes>> {
+        self.get_globals().classes.clone()
+    }
+
+    pub fn get_generics(&self) -> Rc<RefCell<HashMap<FunctionName, Generic>>> {
+        self.get_globals().generics.clone()
+    }
+
+    pub fn get_conditions(&self) -> Rc<RefCell<Conditions>> {
+        self.get_globals().conditions.clone()
+    }
+
+    pub fn get_catchers(&self) -> Rc<RefCell<Vec<(Object, usize)>>> {
+        self.get_globals().catchers.clone()
+    }
+
+    pub fn get_types(&self) -> Rc<RefCell<HashMap<String, Object>>> {
+        self.get_globals().types.clone()
+    }
+
+    pub fn get_setf_expansions(&self) -> Rc<RefCell<HashMap<String, SetfExpansion>>> {
+        self.get_globals().setf_expansions.clone()
+    }
+
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.get_globals().capabilities.borrow().contains(&capability)
+    }
+
+    pub fn grant_capability(&mut self, capability: Capability) {
+        self.get_globals().capabilities.borrow_mut().insert(capability);
+    }
+
+    pub fn revoke_capability(&mut self, capability: Capability) {
+        self.get_globals().capabilities.borrow_mut().remove(&capability);
+    }
+
+    pub fn get_safety(&self) -> isize {
+        self.get_globals().safety.get()
+    }
+
+    pub fn set_safety(&mut self, level: isize) {
+        self.get_globals().safety.set(level);
+    }
+
+    /// Name of the package bound to `*PACKAGE*`
+    pub fn current_package(&self) -> String {
+        match self.get_str("*PACKAGE*") {
+            Ok(Object::Package(p)) => p,
+            _ => COMMON_LISP_USER.into(),
         }
     }
 
@@ -95,29 +243,192 @@ impl Env {
         }
     }
 
-    pub fn insert_global_str<S: AsRef<str>>(&mut self, key: S, value: Object) {
-        if self.parent.is_none() {
+    /// Assign the innermost existing binding, or create a global one if there is none.
+    /// Special variables only have global values, which are rebound dynamically.
+    pub fn set_str<S: AsRef<str>>(&mut self, key: S, value: Object) -> Result<(), EnvError> {
+        match self.variable_kind(&key) {
+            Some(VariableKind::Constant) => return Err(EnvError::ConstantVariable(key.as_ref().into())),
+            Some(VariableKind::Special) => {
+                self.insert_global_str(key, value);
+                return Ok(());
+            },
+            None => (),
+        }
+        if self.inner.contains_key(key.as_ref()) || self.parent.is_none() {
             self.insert_str(key, value);
-            return;
+            Ok(())
+        } else if let Some(parent) = &self.parent {
+            parent.borrow_mut().set_str(key, value)
+        } else {
+            unreachable!()
         }
-        if self.inner.get(key.as_ref()).is_some() {
-            self.inner.remove(key.as_ref());
+    }
+
+    pub fn set(&mut self, key: &Object, value: Object) -> Result<(), EnvError> {
+        if let Object::Symbol(k) = key {
+            self.set_str(k.to_uppercase(), value)
+        } else {
+            Err(EnvError::NotASymbol(key.to_string()))
         }
+    }
+
+    /// Bind a name in the global environment, lexical bindings are left alone
+    pub fn insert_global_str<S: AsRef<str>>(&mut self, key: S, value: Object) {
         if let Some(parent) = &self.parent {
             parent.borrow_mut().insert_global_str(key, value);
+        } else {
+            self.insert_str(key, value);
+        }
+    }
+
+    /// Global value of a name, skipping lexical bindings
+    pub fn get_global_str<S: AsRef<str>>(&self, key: S) -> Option<Object> {
+        match &self.parent {
+            Some(parent) => parent.borrow().get_global_str(key),
+            None => self.inner.get(key.as_ref()).cloned(),
         }
     }
 
+    fn remove_global_str<S: AsRef<str>>(&mut self, key: S) {
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().remove_global_str(key),
+            None => {
+                self.inner.remove(key.as_ref());
+            },
+        }
+    }
+
+    pub fn variable_kind<S: AsRef<str>>(&self, key: S) -> Option<VariableKind> {
+        self.get_globals().variables.borrow().get(key.as_ref()).copied()
+    }
+
+    pub fn is_special<S: AsRef<str>>(&self, key: S) -> bool {
+        self.variable_kind(key) == Some(VariableKind::Special)
+    }
+
+    /// Proclaim a variable special, it is rebound dynamically from then on
+    pub fn proclaim_special<S: AsRef<str>>(&mut self, key: S) -> Result<(), EnvError> {
+        if self.variable_kind(&key) == Some(VariableKind::Constant) {
+            return Err(EnvError::ConstantVariable(key.as_ref().into()));
+        }
+        self.get_globals().variables.borrow_mut().insert(key.as_ref().into(), VariableKind::Special);
+        Ok(())
+    }
+
+    /// Define a constant, which may only be redefined with the same value
+    pub fn define_constant<S: AsRef<str>>(&mut self, key: S, value: Object) -> Result<(), EnvError> {
+        match self.variable_kind(&key) {
+            Some(VariableKind::Constant) if self.get_global_str(&key).is_some_and(|v| eql(&v, &value)) => return Ok(()),
+            Some(_) => return Err(EnvError::ConstantVariable(key.as_ref().into())),
+            None => (),
+        }
+        self.insert_global_str(&key, value);
+        self.get_globals().variables.borrow_mut().insert(key.as_ref().into(), VariableKind::Constant);
+        Ok(())
+    }
+
     pub fn insert_global(&mut self, key: &Object, value: Object) {
         if let Object::Symbol(k) = key {
             self.insert_global_str(k.to_uppercase(), value);
         }
     }
+
+    fn get_setf_function(&self, accessor: &str) -> Option<Object> {
+        match (self.setf_functions.get(accessor), &self.parent) {
+            (Some(function), _) => Some(function.clone()),
+            (None, Some(parent)) => parent.borrow().get_setf_function(accessor),
+            (None, None) => None,
+        }
+    }
+
+    /// Innermost function bound to a name
+    pub fn get_function(&self, name: &FunctionName) -> Result<Object, EnvError> {
+        match name {
+            FunctionName::Symbol(s) => self.get_str(s),
+            FunctionName::Setf(accessor) => self.get_setf_function(accessor)
+                .ok_or_else(|| EnvError::SymbolNotFound(name.to_string())),
+        }
+    }
+
+    pub fn insert_function(&mut self, name: &FunctionName, function: Object) {
+        match name {
+            FunctionName::Symbol(s) => self.insert_str(s, function),
+            FunctionName::Setf(accessor) => {
+                self.setf_functions.insert(accessor.clone(), function);
+            },
+        }
+    }
+
+    /// Bind a function name in the global environment
+    pub fn insert_global_function(&mut self, name: &FunctionName, function: Object) {
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().insert_global_function(name, function),
+            None => self.insert_function(name, function),
+        }
+    }
+}
+
+/// Outermost frame of an environment, where global definitions live
+pub fn global_env(env: &RcEnv) -> RcEnv {
+    let mut ret = env.clone();
+    loop {
+        let parent = ret.borrow().get_parent();
+        match parent {
+            Some(p) => ret = p,
+            None => return ret,
+        }
+    }
+}
+
+/// Dynamic bindings made by one binding form, the previous global values are restored
+/// when it is dropped, so that they are undone on every kind of exit
+pub struct DynamicBindings {
+    env: RcEnv,
+    saved: Vec<(String, Option<Object>)>,
+}
+
+impl DynamicBindings {
+    pub fn new(env: &RcEnv) -> Self {
+        Self {
+            env: env.clone(),
+            saved: vec![],
+        }
+    }
+
+    /// Bind a variable in a new frame, or dynamically if it is special
+    pub fn bind<S: AsRef<str>>(&mut self, frame: &RcEnv, key: S, value: Object) -> Result<(), EnvError> {
+        let kind = self.env.borrow().variable_kind(&key);
+        match kind {
+            Some(VariableKind::Constant) => Err(EnvError::ConstantVariable(key.as_ref().into())),
+            Some(VariableKind::Special) => {
+                let old = self.env.borrow().get_global_str(&key);
+                self.saved.push((key.as_ref().into(), old));
+                self.env.borrow_mut().insert_global_str(key, value);
+                Ok(())
+            },
+            None => {
+                frame.borrow_mut().insert_str(key, value);
+                Ok(())
+            },
+        }
+    }
+}
+
+impl Drop for DynamicBindings {
+    fn drop(&mut self) {
+        let mut env = self.env.borrow_mut();
+        for (key, old) in self.saved.drain(..).rev() {
+            match old {
+                Some(value) => env.insert_global_str(key, value),
+                None => env.remove_global_str(key),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Env, Object};
+    use super::{Env, Object, DynamicBindings, Capability};
 
     #[test]
     fn test_env_simple() {
@@ -181,4 +492,51 @@ mod test {
             Ok(Object::Symbol("AValue2".into()))
         );
     }
+
+    #[test]
+    fn test_env_set() {
+        let p = Env::new().wrap();
+        let c = Env::inherit(&p).wrap();
+        c.borrow_mut().insert(&Object::Symbol("A".into()), Object::Integer(1));
+        c.borrow_mut().set(&Object::Symbol("A".into()), Object::Integer(2)).unwrap();
+        c.borrow_mut().set(&Object::Symbol("B".into()), Object::Integer(3)).unwrap();
+        assert_eq!(c.borrow().get(&Object::Symbol("A".into())), Ok(Object::Integer(2)));
+        assert!(p.borrow().get(&Object::Symbol("A".into())).is_err());
+        assert_eq!(p.borrow().get(&Object::Symbol("B".into())), Ok(Object::Integer(3)));
+    }
+
+    #[test]
+    fn test_env_dynamic_bindings() {
+        let p = Env::new().wrap();
+        p.borrow_mut().insert_str("*X*", Object::Integer(1));
+        p.borrow_mut().proclaim_special("*X*").unwrap();
+        let frame = Env::inherit(&p).wrap();
+        {
+            let mut bindings = DynamicBindings::new(&frame);
+            bindings.bind(&frame, "*X*", Object::Integer(2)).unwrap();
+            bindings.bind(&frame, "Y", Object::Integer(3)).unwrap();
+            assert_eq!(p.borrow().get_str("*X*"), Ok(Object::Integer(2)));
+            assert!(p.borrow().get_str("Y").is_err());
+        }
+        assert_eq!(frame.borrow().get_str("*X*"), Ok(Object::Integer(1)));
+        p.borrow_mut().define_constant("C", Object::Integer(1)).unwrap();
+        assert!(frame.borrow_mut().set_str("C", Object::Integer(2)).is_err());
+        assert!(p.borrow_mut().define_constant("C", Object::Integer(2)).is_err());
+    }
+
+    #[test]
+    fn test_env_globals() {
+        let p = Env::new().wrap();
+        let c = Env::inherit(&Env::inherit(&p).wrap()).wrap();
+        c.borrow_mut().grant_capability(Capability::FileSystem);
+        c.borrow_mut().set_safety(0);
+        c.borrow_mut().proclaim_special("*X*").unwrap();
+        assert!(p.borrow().has_capability(Capability::FileSystem));
+        assert_eq!(p.borrow().get_safety(), 0);
+        assert!(p.borrow().is_special("*X*"));
+        assert!(std::rc::Rc::ptr_eq(&c.borrow().get_globals(), &p.borrow().get_globals()));
+        // Separate environments do not share their registries
+        assert!(!Env::new().has_capability(Capability::FileSystem));
+        assert_eq!(Env::new().get_safety(), 1);
+    }
 }
@@ -2,7 +2,8 @@ use std::fmt;
 use std::error::Error;
 
 use crate::env::EnvError;
-use crate::types::ParamError;
+use crate::package::PackageError;
+use crate::types::{Object, ParamError};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum EvalError {
@@ -10,7 +11,33 @@ pub enum EvalError {
     UnmatchedNumberOfParameters(usize, usize),
     IllegalFunctionCall,
     ParameterTypeMismatched,
-    ParseError,
+    ParseError(String, usize, String), // (Source, Position, Reason)
+    PackageNotFound(String),
+    SymbolNotExternal(String, String),
+    UnknownKeyword(String),
+    PatternMismatch(String, String), // (Lambda list, Datum)
+    NoApplicableMethod(String),
+    NoNextMethod(String),
+    UnboundSlot(String),
+    MissingSlot(String, String), // (Slot, Class)
+    IncongruentLambdaList(String),
+    InconsistentPrecedence(String),
+    ClassNotFound(String),
+    UnknownType(String),
+    ConstantVariable(String),
+    UnknownBlock(String),
+    UnknownTag(String),
+    NoCatchTag(String),
+    DivisionByZero,
+    RestartNotFound(String),
+    FormatError(String),
+    LoopError(String),
+    StreamError(String),
+    EndOfFile,
+    FileError(String, String), // (Pathname, Reason)
+    CapabilityDenied(String),
+    Condition(Object, String), // Signaled and unhandled condition with its report
+    Exit(usize, Vec<Object>),  // Transfer of control to the dynamic frame with the id
 }
 
 impl fmt::Display for EvalError {
@@ -20,7 +47,33 @@ impl fmt::Display for EvalError {
             Self::UnmatchedNumberOfParameters(exp, act) => write!(f, "Unmatched number of parameters, expecting {} but got {}", exp, act),
             Self::IllegalFunctionCall => write!(f, "Illegal function call"),
             Self::ParameterTypeMismatched => write!(f, "Parameter type mismatched"),
-            Self::ParseError => write!(f, "Failed to parse input"),
+            Self::ParseError(s, p, r) => write!(f, "Failed to parse {} at position {}: {}", s, p, r),
+            Self::PackageNotFound(p) => write!(f, "Package {} does not exist", p),
+            Self::SymbolNotExternal(p, s) => write!(f, "Symbol {} is not external in package {}", s, p),
+            Self::UnknownKeyword(k) => write!(f, "Unknown keyword argument :{}", k),
+            Self::PatternMismatch(p, d) => write!(f, "{} does not match the lambda list {}", d, p),
+            Self::NoApplicableMethod(g) => write!(f, "No applicable method for generic function {}", g),
+            Self::NoNextMethod(g) => write!(f, "No next method for generic function {}", g),
+            Self::UnboundSlot(s) => write!(f, "Slot {} is unbound", s),
+            Self::MissingSlot(s, c) => write!(f, "There is no slot {} in {}", s, c),
+            Self::IncongruentLambdaList(g) => write!(f, "Lambda list is not congruent with the generic function {}", g),
+            Self::InconsistentPrecedence(c) => write!(f, "Inconsistent precedence list for class {}", c),
+            Self::ClassNotFound(c) => write!(f, "There is no class named {}", c),
+            Self::UnknownType(t) => write!(f, "Unknown type specifier {}", t),
+            Self::UnknownBlock(b) => write!(f, "Return from unknown block {}", b),
+            Self::UnknownTag(t) => write!(f, "Attempt to go to nonexistent tag {}", t),
+            Self::NoCatchTag(t) => write!(f, "Attempt to throw to a tag that does not exist: {}", t),
+            Self::ConstantVariable(v) => write!(f, "{} is a constant and cannot be changed", v),
+            Self::DivisionByZero => write!(f, "Division by zero"),
+            Self::RestartNotFound(r) => write!(f, "No restart named {} is active", r),
+            Self::FormatError(e) => write!(f, "Format error: {}", e),
+            Self::LoopError(e) => write!(f, "Loop error: {}", e),
+            Self::StreamError(e) => write!(f, "Stream error: {}", e),
+            Self::EndOfFile => write!(f, "End of file"),
+            Self::FileError(p, e) => write!(f, "File error on {}: {}", p, e),
+            Self::CapabilityDenied(c) => write!(f, "{} access is not permitted", c),
+            Self::Condition(_, report) => write!(f, "{}", report),
+            Self::Exit(_, _) => write!(f, "Attempt to transfer control to a frame that has been exited"),
         }
     }
 }
@@ -31,7 +84,8 @@ impl From<EnvError> for EvalError {
     fn from(e: EnvError) -> Self {
         match e {
             EnvError::SymbolNotFound(s) => Self::UnboundVariable(s),
-            _ => unreachable!(),
+            EnvError::ConstantVariable(s) => Self::ConstantVariable(s),
+            EnvError::NotASymbol(_) => Self::ParameterTypeMismatched,
         }
     }
 }
@@ -43,3 +97,12 @@ impl From<ParamError> for EvalError {
         }
     }
 }
+
+impl From<PackageError> for EvalError {
+    fn from(p: PackageError) -> Self {
+        match p {
+            PackageError::PackageNotFound(s) => Self::PackageNotFound(s),
+            PackageError::SymbolNotExternal(p, s) => Self::SymbolNotExternal(p, s),
+        }
+    }
+}
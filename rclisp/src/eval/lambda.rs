@@ -1,10 +1,17 @@
-use super::{Eval, EvalError};
+use super::{Eval, EvalError, eval_body, eval_body_values};
 use crate::types::{Object, Lambda, UserLambda, Builtin};
 use crate::env::RcEnv;
+use crate::builtins::types::check_declarations;
 
 impl Eval for UserLambda {
     fn eval(self, env: &RcEnv) -> Result<Object, EvalError> {
-        self.get_body().eval(env)
+        check_declarations(self.get_declarations(), env)?;
+        eval_body(self.get_body(), env)
+    }
+
+    fn eval_values(self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        check_declarations(self.get_declarations(), env)?;
+        eval_body_values(self.get_body(), env)
     }
 }
 
@@ -12,13 +19,24 @@ impl Eval for Builtin {
     fn eval(self, env: &RcEnv) -> Result<Object, EvalError> {
         self.inner.eval(env)
     }
+
+    fn eval_values(self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        self.inner.eval_values(env)
+    }
 }
 
 impl Eval for Lambda {
     fn eval(self, env: &RcEnv) -> Result<Object, EvalError> {
         match self {
-            Self::Named(_, l) | Self::Unnamed(l) => l.eval(env),
+            Self::Named(_, l) | Self::Unnamed(l) | Self::Macro(_, l) => l.eval(env),
             Self::Builtin(b) => b.eval(env),
         }
     }
+
+    fn eval_values(self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        match self {
+            Self::Named(_, l) | Self::Unnamed(l) | Self::Macro(_, l) => l.eval_values(env),
+            Self::Builtin(b) => b.eval_values(env),
+        }
+    }
 }
@@ -1,7 +1,9 @@
 use log::trace;
 
-use crate::types::{List, Parameter, nil, cons};
-use super::{Eval, EvalError, RcEnv, Object, Env};
+use crate::types::{List, Lambda, UserLambda, Params, Parameter, nil, cons};
+use super::{Eval, EvalError, RcEnv, Object, Env, eval_body_values};
+use crate::env::DynamicBindings;
+use crate::builtins::types::check_declarations;
 
 fn collect_parameters(rest: Vec<Object>) -> Object {
     let mut v = rest;
@@ -16,22 +18,198 @@ fn collect_parameters(rest: Vec<Object>) -> Object {
     }
 }
 
+/// Bind a lambda list to values that are not evaluated any further, `whole` is the form
+/// `&whole` refers to. Initial forms are evaluated in the frame, after the parameters before them
+pub(crate) fn bind_parameters(params: &Params, whole: Object, args: Object, frame: &RcEnv, bindings: &mut DynamicBindings) -> Result<(), EvalError> {
+    let mismatch = || EvalError::UnmatchedNumberOfParameters(params.len(), list_length(&args));
+    let mut rest = args.clone();
+    let mut has_rest = false;
+    let mut keys = vec![];
+    let mut allow_other_keys = false;
+    for param in params.iter() {
+        match param {
+            Parameter::Whole(name) => bindings.bind(frame, name, whole.clone())?,
+            // Macros are only ever expanded in the global environment
+            Parameter::Environment(name) => bindings.bind(frame, name, Object::Nil)?,
+            // Keyword arguments are taken from the same rest of the list
+            Parameter::Rest(name) | Parameter::PlainRest(name) => {
+                has_rest = true;
+                bindings.bind(frame, name, rest.clone())?;
+            },
+            Parameter::Normal(name) | Parameter::Plain(name) => {
+                let value = next_argument(&mut rest).ok_or_else(mismatch)?;
+                bindings.bind(frame, name, value)?;
+            },
+            Parameter::Nested(pattern) => {
+                let value = next_argument(&mut rest).ok_or_else(mismatch)?;
+                destructure(pattern, value, frame, bindings)?;
+            },
+            Parameter::Optional(name, default, supplied) => {
+                let (value, supplied_p) = match next_argument(&mut rest) {
+                    Some(v) => (v, Object::T),
+                    None => (default.clone().eval(frame)?, Object::Nil),
+                };
+                bindings.bind(frame, name, value)?;
+                if let Some(supplied) = supplied {
+                    bindings.bind(frame, supplied, supplied_p)?;
+                }
+            },
+            Parameter::Key(name, key, default, supplied) => {
+                keys.push(key.as_str());
+                let (value, supplied_p) = match keyword_argument(&rest, key)? {
+                    Some(v) => (v, Object::T),
+                    None => (default.clone().eval(frame)?, Object::Nil),
+                };
+                bindings.bind(frame, name, value)?;
+                if let Some(supplied) = supplied {
+                    bindings.bind(frame, supplied, supplied_p)?;
+                }
+            },
+            Parameter::AllowOtherKeys => allow_other_keys = true,
+            Parameter::Aux(name, init) => {
+                let value = init.clone().eval(frame)?;
+                bindings.bind(frame, name, value)?;
+            },
+        }
+    }
+    if !keys.is_empty() || allow_other_keys {
+        allow_other_keys = allow_other_keys || keyword_argument(&rest, ":ALLOW-OTHER-KEYS")?.is_some_and(|v| v != Object::Nil);
+        let mut plist = rest;
+        while let Some(key) = next_argument(&mut plist) {
+            next_argument(&mut plist);
+            match key {
+                Object::Symbol(k) if allow_other_keys || k == ":ALLOW-OTHER-KEYS" || keys.contains(&k.as_str()) => (),
+                k => return Err(EvalError::UnknownKeyword(k.to_string().trim_start_matches(':').into())),
+            }
+        }
+    } else if !has_rest && rest != Object::Nil {
+        return Err(mismatch());
+    }
+    Ok(())
+}
+
+/// Bind a destructuring pattern to a value, an arity mismatch names the pattern
+pub(crate) fn destructure(pattern: &Params, value: Object, frame: &RcEnv, bindings: &mut DynamicBindings) -> Result<(), EvalError> {
+    bind_parameters(pattern, value.clone(), value.clone(), frame, bindings).map_err(|e| match e {
+        EvalError::UnmatchedNumberOfParameters(_, _) => EvalError::PatternMismatch(pattern.to_lambda_list().to_string(), value.to_string()),
+        e => e,
+    })
+}
+
+/// Value following the first occurrence of a keyword in a property list of arguments
+fn keyword_argument(plist: &Object, key: &str) -> Result<Option<Object>, EvalError> {
+    let mut rest = plist.clone();
+    while let Some(k) = next_argument(&mut rest) {
+        let value = next_argument(&mut rest).ok_or(EvalError::ParameterTypeMismatched)?;
+        if matches!(&k, Object::Symbol(s) if s == key) {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+fn next_argument(rest: &mut Object) -> Option<Object> {
+    match std::mem::replace(rest, Object::Nil) {
+        Object::List(l) => {
+            let (car, cdr) = l.unpack();
+            *rest = cdr;
+            Some(car)
+        },
+        _ => None,
+    }
+}
+
+fn list_length(list: &Object) -> usize {
+    match list {
+        Object::List(l) => l.len(),
+        _ => 0,
+    }
+}
+
+/// Expansion of a macro call, the lambda list destructures the unevaluated form
+pub(crate) fn expand_macro(lambda: UserLambda, form: Object, env: &RcEnv) -> Result<Object, EvalError> {
+    Ok(expand_macro_values(lambda, form, env)?.into_iter().next().unwrap_or(Object::Nil))
+}
+
+/// Every value returned by a macro's body, as setf expanders return several forms
+pub(crate) fn expand_macro_values(lambda: UserLambda, form: Object, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+    let frame = Env::inherit(&lambda.get_env().unwrap_or_else(|| env.clone())).wrap();
+    let mut bindings = DynamicBindings::new(&frame);
+    let params = lambda.get_parameters();
+    let args = match &form {
+        Object::List(l) => l.cdr(),
+        _ => Object::Nil,
+    };
+    bind_parameters(params, form.clone(), args, &frame, &mut bindings).map_err(|e| match e {
+        EvalError::UnmatchedNumberOfParameters(_, _) => EvalError::PatternMismatch(params.to_lambda_list().to_string(), form.to_string()),
+        e => e,
+    })?;
+    let ret = check_declarations(lambda.get_declarations(), &frame).and_then(|_| eval_body_values(lambda.get_body(), &frame));
+    drop(bindings);
+    ret
+}
+
+// A list either evaluates directly, expands into another form or becomes a function call
+// in a new frame, with the special variables among the parameters bound until the call returns
+enum Call {
+    Done(Object),
+    Expand(Object),
+    Apply(Lambda, RcEnv, DynamicBindings),
+}
+
 impl Eval for List {
     fn eval(self, env: &RcEnv) -> Result<Object, EvalError> {
+        match self.prepare_call(env) {
+            Ok(Call::Done(obj)) => Ok(obj),
+            Ok(Call::Expand(form)) => form.eval(env),
+            Ok(Call::Apply(lambda, env, bindings)) => {
+                let ret = lambda.eval(&env);
+                drop(bindings);
+                ret
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn eval_values(self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        match self.prepare_call(env) {
+            Ok(Call::Done(obj)) => Ok(vec![obj]),
+            Ok(Call::Expand(form)) => form.eval_values(env),
+            Ok(Call::Apply(lambda, env, bindings)) => {
+                let ret = lambda.eval_values(&env);
+                drop(bindings);
+                ret
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl List {
+    fn prepare_call(self, env: &RcEnv) -> Result<Call, EvalError> {
         trace!("eval list: {}", self);
 
         // Empty list == nil
         let len = self.len();
         if len == 0 {
-            return Ok(Object::Nil);
+            return Ok(Call::Done(Object::Nil));
         }
 
         // Check function call format
         let (car, cdr) = self.unpack();
         trace!("car: {}, cdr: {}", car, cdr);
-        let first_result = car.eval(env)?;
+        let first_result = car.clone().eval(env)?;
+        if let Object::Lambda(l) = &first_result {
+            if let Lambda::Macro(_, m) = l.as_ref() {
+                // Only a macro name in function position is expanded
+                if !matches!(car, Object::Symbol(_)) {
+                    return Err(EvalError::IllegalFunctionCall);
+                }
+                return Ok(Call::Expand(expand_macro(m.clone(), cons(car, cdr), env)?));
+            }
+        }
         if (cdr == Object::Nil) && !matches!(first_result, Object::Lambda(_)) {
-            return Ok(first_result);
+            return Ok(Call::Done(first_result));
         }
         let lambda = if let Object::Lambda(l) = first_result {
             *l
@@ -40,17 +218,31 @@ impl Eval for List {
             for obj in l.into_iter() {
                 ret = obj.eval(env)?;
             }
-            return Ok(ret);
+            return Ok(Call::Done(ret));
         } else {
-            return cdr.eval(env);
+            return Ok(Call::Done(cdr.eval(env)?));
         };
         trace!("Calling {}", lambda);
         let cdr = if let Object::List(l) = cdr {
-            *l
+            l.to_list()
         } else {
             List::EndsWith(Object::Nil)
         };
 
+        // User lambdas bind their lambda list to the evaluated arguments in a frame of their
+        // defining environment, special variables among the parameters are bound dynamically
+        if let Lambda::Named(_, l) | Lambda::Unnamed(l) = &lambda {
+            let mut arguments = vec![];
+            for v in cdr.into_iter() {
+                arguments.push(v.eval(env)?);
+            }
+            let arguments = collect_parameters(arguments);
+            let frame = Env::inherit(&l.get_env().unwrap_or_else(|| env.clone())).wrap();
+            let mut bindings = DynamicBindings::new(&frame);
+            bind_parameters(lambda.get_parameters(), arguments.clone(), arguments, &frame, &mut bindings)?;
+            return Ok(Call::Apply(lambda, frame, bindings));
+        }
+
         // Check parameter count
         let params = lambda.get_parameters();
         let len = len.saturating_sub(1); // Avoid overflow
@@ -58,23 +250,31 @@ impl Eval for List {
             return Err(EvalError::UnmatchedNumberOfParameters(params.len(), len));
         }
 
-        // If the lambda requires no parameters
+        // Builtins run in a frame of the caller's environment
         if params.is_empty() {
-            return lambda.eval(env);
+            return Ok(Call::Apply(lambda, env.clone(), DynamicBindings::new(env)));
         }
 
-        // Bind parameters
-        let new_env = Env::inherit(env).wrap();
+        // Evaluate every argument before binding the parameters
+        let mut arguments = vec![];
         let mut values = cdr.into_iter();
         for param in params.iter() {
             let value = values.next();
             let (name, val) = match param {
                 Parameter::Rest(name) => {
-                    let mut rest = vec![value.unwrap()];
+                    let mut rest = vec![];
+                    for v in value.into_iter().chain(values) {
+                        rest.push(v.eval(env)?);
+                    }
+                    arguments.push((name, collect_parameters(rest)));
+                    break;
+                },
+                Parameter::PlainRest(name) => {
+                    let mut rest: Vec<Object> = value.into_iter().collect();
                     let mut iter_rest: Vec<Object> = values.collect();
                     rest.append(&mut iter_rest);
-                    new_env.borrow_mut().insert_str(name, collect_parameters(rest));
-                    return lambda.eval(&new_env);
+                    arguments.push((name, collect_parameters(rest)));
+                    break;
                 },
                 Parameter::Normal(name) => {
                     let val = value.expect("Failed to find matching normal value").eval(env)?;
@@ -84,18 +284,97 @@ impl Eval for List {
                     let val = value.expect("Failed to find matching plain value");
                     (name, val)
                 },
-                Parameter::Optional(name, default) => {
+                Parameter::Optional(name, default, _) => {
                     let val = if let Some(v) = value {
-                        v
+                        v.eval(env)?
                     } else {
                         default.clone()
                     };
                     trace!("Binding {} to {}", name, val);
                     (name, val)
                 },
+                _ => return Err(EvalError::IllegalFunctionCall),
             };
+            arguments.push((name, val));
+        }
+
+        let new_env = Env::inherit(env).wrap();
+        for (name, val) in arguments {
             new_env.borrow_mut().insert_str(name, val);
         }
-        lambda.eval(&new_env)
+        Ok(Call::Apply(lambda, new_env.clone(), DynamicBindings::new(&new_env)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bind_parameters, destructure};
+    use crate::env::{Env, DynamicBindings, RcEnv};
+    use crate::builtins::generate_default_env;
+    use crate::lexer::Lexer;
+    use crate::parse::parse;
+    use crate::types::{Object, Params};
+    use crate::EvalError;
+
+    fn read(s: &str) -> Object {
+        parse(&mut Lexer::new(s.as_bytes())).unwrap()
+    }
+
+    fn params(s: &str) -> Params {
+        Params::destructuring(read(s)).unwrap()
+    }
+
+    /// Bind a lambda list to arguments in a new frame, returns the frame
+    fn bind(lambda_list: &str, args: &str) -> Result<RcEnv, EvalError> {
+        let frame = Env::inherit(&generate_default_env()).wrap();
+        let mut bindings = DynamicBindings::new(&frame);
+        let args = read(args);
+        bind_parameters(&params(lambda_list), args.clone(), args, &frame, &mut bindings)?;
+        Ok(frame)
+    }
+
+    #[test]
+    fn test_bind_parameters() {
+        let frame = bind("(a &optional (b (list a)) c)", "(1)").unwrap();
+        assert_eq!(frame.borrow().get_str("B").map(|b| b.to_string()), Ok("(1)".into()));
+        assert_eq!(frame.borrow().get_str("C"), Ok(Object::Nil));
+        let frame = bind("(a &rest r &key k)", "(1 :k 2)").unwrap();
+        assert_eq!(frame.borrow().get_str("R").map(|r| r.to_string()), Ok("(:K 2)".into()));
+        assert_eq!(frame.borrow().get_str("K"), Ok(Object::Integer(2)));
+        assert!(bind("(&key a)", "(:b 1 :allow-other-keys t)").is_ok());
+        assert!(bind("(&key a &allow-other-keys)", "(:b 1)").is_ok());
+    }
+
+    #[test]
+    fn test_bind_parameters_errors() {
+        assert!(matches!(bind("(a b)", "(1)"), Err(EvalError::UnmatchedNumberOfParameters(2, 1))));
+        assert!(matches!(bind("(a &optional b)", "(1 2 3)"), Err(EvalError::UnmatchedNumberOfParameters(2, 3))));
+        assert!(matches!(bind("(&key a)", "(:b 1)"), Err(EvalError::UnknownKeyword(k)) if k == "B"));
+        assert!(matches!(bind("(&key a)", "(:a)"), Err(EvalError::ParameterTypeMismatched)));
+        assert!(matches!(bind("(&optional (a (car 1)))", "()"), Err(EvalError::ParameterTypeMismatched)));
+        // A nested pattern reports itself and the value it did not match
+        assert!(matches!(bind("(a (b c))", "(1 (2))"), Err(EvalError::PatternMismatch(p, v)) if p == "(B C)" && v == "(2)"));
+        let frame = Env::new().wrap();
+        let mut bindings = DynamicBindings::new(&frame);
+        assert!(matches!(destructure(&params("(a . b)"), Object::Integer(1), &frame, &mut bindings), Err(EvalError::PatternMismatch(_, v)) if v == "1"));
+    }
+
+    #[test]
+    fn test_bind_special_parameters() {
+        let env = generate_default_env();
+        env.borrow_mut().insert_global_str("*X*", Object::Integer(1));
+        env.borrow_mut().proclaim_special("*X*").unwrap();
+        env.borrow_mut().define_constant("C", Object::Integer(0)).unwrap();
+        let frame = Env::inherit(&env).wrap();
+        {
+            let mut bindings = DynamicBindings::new(&frame);
+            bind_parameters(&params("(*x* y)"), Object::Nil, read("(2 3)"), &frame, &mut bindings).unwrap();
+            assert_eq!(env.borrow().get_str("*X*"), Ok(Object::Integer(2)));
+            assert!(env.borrow().get_str("Y").is_err());
+            // Constants cannot be bound, the bindings made before are still undone
+            assert!(matches!(bind_parameters(&params("(*x* c)"), Object::Nil, read("(4 5)"), &frame, &mut bindings), Err(EvalError::ConstantVariable(c)) if c == "C"));
+            assert_eq!(env.borrow().get_str("*X*"), Ok(Object::Integer(4)));
+        }
+        assert_eq!(env.borrow().get_str("*X*"), Ok(Object::Integer(1)));
     }
 }
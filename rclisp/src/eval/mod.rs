@@ -2,23 +2,79 @@ mod error;
 mod list;
 mod lambda;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::env::{Env, RcEnv};
-use crate::types::Object;
+use crate::types::{Object, List, cons, quote};
 
 pub use error::EvalError;
+pub(crate) use list::{expand_macro, expand_macro_values, destructure};
 
-pub trait Eval {
+pub trait Eval: Sized {
     fn eval(self, env: &RcEnv) -> Result<Object, EvalError>;
+
+    /// Evaluate keeping every returned value, only used where multiple values are expected
+    fn eval_values(self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        Ok(vec![self.eval(env)?])
+    }
 }
 
 impl Eval for Object {
     fn eval(self, env: &RcEnv) -> Result<Object, EvalError> {
         match self {
+            Self::Symbol(s) if s.starts_with(':') => Ok(Self::Symbol(s)),
             Self::Symbol(s) => Ok(env.borrow().get_str(s)?),
             Self::Quote(o) => Ok(*o),
-            Self::Nil | Self::T | Self::Integer(_) | Self::Float(_) | Self::String(_) | Self::Lambda(_) => Ok(self),
+            Self::Nil | Self::T | Self::Integer(_) | Self::Float(_) | Self::String(_) | Self::Lambda(_) | Self::Package(_) | Self::Struct(_) | Self::Instance(_) | Self::HashTable(_) | Self::Stream(_) => Ok(self),
             // Self::Lambda(_) => Ok(Object::Nil),
-            Self::List(l) => l.eval(env),
+            Self::List(l) => l.to_list().eval(env),
         }
     }
+
+    fn eval_values(self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        match self {
+            Self::List(l) => l.to_list().eval_values(env),
+            _ => Ok(vec![self.eval(env)?]),
+        }
+    }
+}
+
+/// Evaluate forms in order, returning the value of the last one
+pub fn eval_body(body: List, env: &RcEnv) -> Result<Object, EvalError> {
+    let mut ret = Object::Nil;
+    for form in body.into_iter() {
+        ret = form.eval(env)?;
+    }
+    Ok(ret)
+}
+
+/// Same as `eval_body`, but keeps all values of the last form
+pub fn eval_body_values(body: List, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+    let mut forms = body.into_iter().peekable();
+    while let Some(form) = forms.next() {
+        if forms.peek().is_none() {
+            return form.eval_values(env);
+        }
+        form.eval(env)?;
+    }
+    Ok(vec![Object::Nil])
+}
+
+static FRAME_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Unique id for a frame that can be the target of `EvalError::Exit`
+pub fn new_frame_id() -> usize {
+    FRAME_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Call a function object with arguments that are already evaluated
+pub fn apply(function: Object, args: Vec<Object>, env: &RcEnv) -> Result<Object, EvalError> {
+    let args = args.into_iter().rev().fold(Object::Nil, |acc, arg| cons(quote(arg), acc));
+    cons(function, args).eval(env)
+}
+
+/// Same as `apply`, but keeps all returned values
+pub fn apply_values(function: Object, args: Vec<Object>, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+    let args = args.into_iter().rev().fold(Object::Nil, |acc, arg| cons(quote(arg), acc));
+    cons(function, args).eval_values(env)
 }
@@ -10,6 +10,9 @@ const DELIM: u8 = b' ';
 const PAREN_LEFT: u8 = b'(';
 const PAREN_RIGHT: u8 = b')';
 const QUOTE: u8 = b'\'';
+const BACKQUOTE: u8 = b'`';
+const COMMA: u8 = b',';
+const SPLICE: u8 = b'@';
 const STRING: u8 = b'"';
 const ESCAPE: u8 = b'\\';
 const DOT: u8 = b'.';
@@ -98,6 +101,9 @@ pub enum Token {
     Integer(isize),
     Float(f64),
     Quote,
+    Backquote,
+    Comma,
+    CommaAt,
     Dot,
     Symbol(String),
     String(String),
@@ -112,6 +118,7 @@ pub struct Lexer<R> {
     pos: usize,
     filled: usize,
     initialized: usize,
+    consumed: usize,
 }
 
 impl<R> Lexer<R> {
@@ -129,9 +136,15 @@ impl<R: Read + std::fmt::Debug> Lexer<R> {
             pos: 0,
             filled: 0,
             initialized: 0,
+            consumed: 0,
         }
     }
 
+    /// Number of bytes consumed from the source so far
+    pub fn position(&self) -> usize {
+        self.consumed
+    }
+
     #[inline]
     fn fill_buf(&mut self) -> IOResult<&[u8]> {
         if self.pos >= self.filled {
@@ -150,7 +163,9 @@ impl<R: Read + std::fmt::Debug> Lexer<R> {
 
     #[inline]
     fn consume(&mut self, amt: usize) {
-        self.pos = cmp::min(self.pos + amt, self.filled);
+        let amt = cmp::min(amt, self.filled - self.pos);
+        self.pos += amt;
+        self.consumed += amt;
     }
 }
 
@@ -160,6 +175,10 @@ impl<R: Read + std::fmt::Debug> Iterator for Lexer<R> {
     fn next(&mut self) -> Option<Self::Item> {
         let mut token_buf = vec![];
 
+        // Kept across refills, a string or a comment may span several of them
+        let mut in_string = false;
+        let mut escape = false;
+        let mut in_comment = false;
         'read_loop: loop {
             let available = match self.fill_buf() {
                 Ok(n) => n,
@@ -170,26 +189,19 @@ impl<R: Read + std::fmt::Debug> Iterator for Lexer<R> {
                 break;
             }
             let mut i = 0usize;
-            let mut in_string = false;
-            let mut escape = false;
-            let mut in_comment = false;
             while i < available.len() {
                 let c = available[i];
                 trace!("c: {}, i: {}, in_string: {}, escape: {}, token_buf: {:?}", c as char, i, in_string, escape, token_buf);
-                if c == COMMENT {
-                    in_comment = true;
-                } else if (c == COMMEND_END) && in_comment {
-                    in_comment = false;
-                } else if in_comment {
-                    // Do nothing
-                } else if c == ESCAPE {
-                    escape = !escape;
+                if in_comment {
+                    in_comment = c != COMMEND_END;
+                } else if in_string && escape {
+                    // The escaped character is taken as it is
                     token_buf.push(c);
+                    escape = false;
+                } else if in_string && c == ESCAPE {
+                    escape = true;
                 } else if c == STRING {
                     token_buf.push(c);
-                    if escape {
-                        escape = false;
-                    }
                     if in_string {
                         self.consume(i + 1);
                         break 'read_loop;
@@ -197,7 +209,12 @@ impl<R: Read + std::fmt::Debug> Iterator for Lexer<R> {
                     in_string = true;
                 } else if in_string {
                     token_buf.push(c);
-                } else if (PARENS.contains(&c) || c == QUOTE) && token_buf.is_empty() {
+                } else if c == COMMENT && !token_buf.is_empty() {
+                    self.consume(i);
+                    break 'read_loop;
+                } else if c == COMMENT {
+                    in_comment = true;
+                } else if (PARENS.contains(&c) || [QUOTE, BACKQUOTE, COMMA].contains(&c)) && token_buf.is_empty() {
                     token_buf.push(c);
                     self.consume(i + 1);
                     break 'read_loop;
@@ -215,16 +232,26 @@ impl<R: Read + std::fmt::Debug> Iterator for Lexer<R> {
             self.consume(i);
         }
 
+        // A comma directly followed by an at sign splices
+        if token_buf == [COMMA] && matches!(self.fill_buf(), Ok([SPLICE, ..])) {
+            token_buf.push(SPLICE);
+            self.consume(1);
+        }
+
         let ret = if token_buf.is_empty() {
             None
         } else if token_buf.len() == 1 {
             match token_buf[0] {
                 QUOTE => Some(Token::Quote),
+                BACKQUOTE => Some(Token::Backquote),
+                COMMA => Some(Token::Comma),
                 PAREN_LEFT => Some(Token::ParenLeft),
                 PAREN_RIGHT => Some(Token::ParenRight),
                 DOT => Some(Token::Dot),
                 _ => Some(token_from_buf!(&token_buf)),
             }
+        } else if token_buf == [COMMA, SPLICE] {
+            Some(Token::CommaAt)
         } else {
             Some(token_from_buf!(&token_buf))
         };
@@ -359,4 +386,61 @@ mod test {
         let result = lexer.collect::<Vec<Token>>();
         assert_eq!(result, vec![Token::Symbol("test".to_string())]);
     }
+
+    #[test]
+    fn test_lexer_string() {
+        let lexer = Lexer::new(r#"("a;b" "say \"hi\"" "back\\slash") ; done"#.as_bytes());
+        let result = lexer.collect::<Vec<Token>>();
+        assert_eq!(
+            result,
+            vec![
+                Token::ParenLeft,
+                Token::String("a;b".to_string()),
+                Token::String("say \"hi\"".to_string()),
+                Token::String("back\\slash".to_string()),
+                Token::ParenRight,
+            ]
+        );
+        // A comment ends the token before it
+        let lexer = Lexer::new("abc; comment\ndef".as_bytes());
+        let result = lexer.collect::<Vec<Token>>();
+        assert_eq!(result, vec![Token::Symbol("abc".to_string()), Token::Symbol("def".to_string())]);
+        // Strings and comments longer than the buffer
+        let long = "x;".repeat(super::DEFAULT_BUF_SIZE);
+        let source = format!("\"{}\" ;{}\nend", long, long);
+        let lexer = Lexer::new(source.as_bytes());
+        let result = lexer.collect::<Vec<Token>>();
+        assert_eq!(result, vec![Token::String(long), Token::Symbol("end".to_string())]);
+    }
+
+    #[test]
+    fn test_lexer_backquote() {
+        let lexer = Lexer::new("`(a ,b ,@c \",@\")".as_bytes());
+        let result = lexer.collect::<Vec<Token>>();
+        assert_eq!(
+            result,
+            vec![
+                Token::Backquote,
+                Token::ParenLeft,
+                Token::Symbol("a".to_string()),
+                Token::Comma,
+                Token::Symbol("b".to_string()),
+                Token::CommaAt,
+                Token::Symbol("c".to_string()),
+                Token::String(",@".to_string()),
+                Token::ParenRight,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_position() {
+        let mut lexer = Lexer::new("abc (d) e".as_bytes());
+        assert_eq!(lexer.next(), Some(Token::Symbol("abc".to_string())));
+        assert_eq!(lexer.position(), 4);
+        lexer.next();
+        lexer.next();
+        lexer.next();
+        assert_eq!(lexer.position(), 7);
+    }
 }
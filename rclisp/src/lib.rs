@@ -3,6 +3,7 @@
 #![feature(maybe_uninit_slice)]
 
 pub mod env;
+pub mod package;
 pub mod lexer;
 pub mod parse;
 pub mod types;
@@ -17,8 +18,12 @@ use std::io::Read;
 pub use lexer::Lexer;
 pub use eval::{Eval, EvalError};
 pub use types::Object;
-pub use env::RcEnv;
+pub use env::{RcEnv, Capability};
 pub use builtins::generate_default_env;
+pub use builtins::stream::{set_standard_output, set_error_output, set_standard_input};
+pub use builtins::load::load;
+pub use builtins::print::print_string;
+pub use types::{Sink, Source};
 
 // use eval::Eval;
 
@@ -42,11 +47,24 @@ pub use builtins::generate_default_env;
 //     // println!("Env: {:?}", env);
 // }
 
+/// Turn a parsed form into the object seen by the evaluator
+pub fn read(obj: Object, env: &RcEnv) -> Result<Object, EvalError> {
+    let current = env.borrow().current_package();
+    let packages = env.borrow().get_packages();
+    let obj = packages.borrow_mut().intern_object(&current, obj)?;
+    builtins::structure::read_structures(obj, env)
+}
+
+/// Evaluate every form read from `source`, returning the result of the last one.
+/// Errors raised by the interpreter come back as their own `EvalError` variant unless a Lisp
+/// handler was established around them, in which case they were signaled as a condition and an
+/// unhandled one is returned as `EvalError::Condition`, like those signaled by `error`.
 pub fn interpret<R: Read + fmt::Debug>(source: R, env: &RcEnv) -> Result<Object, EvalError> {
     let mut lexer = Lexer::new(source);
     let mut ret = Ok(Object::Nil);
     while let Ok(obj) = parse::parse(&mut lexer) {
         debug!("parse result: {} {:?}", obj, obj);
+        let obj = read(obj, env)?;
         ret = obj.eval(&env);
         debug!("evaluation result: {:?}", ret);
     }
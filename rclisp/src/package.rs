@@ -0,0 +1,339 @@
+//! Packages
+//!
+//! Symbols are interned by the reader into the current package before
+//! evaluation. A symbol is represented by its canonical name: symbols whose
+//! home is `COMMON-LISP` keep their bare name, keywords are written `:NAME`,
+//! and every other symbol is written `PACKAGE::NAME`. `COMMON-LISP-USER` is a
+//! package of its own using `COMMON-LISP`, whose symbols also keep their bare
+//! name unless `COMMON-LISP` has a symbol of the same name, as when shadowing.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::types::{List, Object};
+
+pub const COMMON_LISP: &str = "COMMON-LISP";
+pub const COMMON_LISP_USER: &str = "COMMON-LISP-USER";
+pub const KEYWORD: &str = "KEYWORD";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PackageError {
+    PackageNotFound(String),
+    SymbolNotExternal(String, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    name: String,
+    nicknames: Vec<String>,
+    uses: Vec<String>,
+    symbols: HashMap<String, String>, // (Name, Canonical name)
+    external: HashSet<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packages {
+    inner: HashMap<String, Package>,
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::PackageNotFound(p) => write!(f, "Package {} does not exist", p),
+            Self::SymbolNotExternal(p, s) => write!(f, "Symbol {} is not external in package {}", s, p),
+        }
+    }
+}
+
+impl Error for PackageError {}
+
+impl Package {
+    pub fn new<S: AsRef<str>>(name: S) -> Self {
+        Self {
+            name: name.as_ref().to_string(),
+            nicknames: vec![],
+            uses: vec![],
+            symbols: HashMap::new(),
+            external: HashSet::new(),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_nicknames(&self) -> &[String] {
+        &self.nicknames
+    }
+
+    pub fn get_uses(&self) -> &[String] {
+        &self.uses
+    }
+
+    pub fn add_nickname<S: AsRef<str>>(&mut self, nickname: S) {
+        let nickname = nickname.as_ref().to_string();
+        if !self.nicknames.contains(&nickname) {
+            self.nicknames.push(nickname);
+        }
+    }
+
+    /// Canonical name of a symbol whose home is this package
+    pub fn canonical_name<S: AsRef<str>>(&self, name: S) -> String {
+        let name = name.as_ref();
+        match self.name.as_str() {
+            COMMON_LISP | COMMON_LISP_USER => name.to_string(),
+            KEYWORD => format!(":{}", name),
+            _ => format!("{}::{}", self.name, name),
+        }
+    }
+
+    pub fn is_external<S: AsRef<str>>(&self, name: S) -> bool {
+        self.external.contains(name.as_ref())
+    }
+
+    /// Canonical names of all external symbols, sorted for stable iteration
+    pub fn external_symbols(&self) -> Vec<String> {
+        let mut ret: Vec<String> = self.external.iter()
+            .filter_map(|name| self.symbols.get(name).cloned())
+            .collect();
+        ret.sort();
+        ret
+    }
+}
+
+impl Default for Packages {
+    fn default() -> Self {
+        let mut common_lisp = Package::new(COMMON_LISP);
+        common_lisp.add_nickname("CL");
+        let mut common_lisp_user = Package::new(COMMON_LISP_USER);
+        common_lisp_user.add_nickname("CL-USER");
+        common_lisp_user.uses.push(COMMON_LISP.into());
+        let keyword = Package::new(KEYWORD);
+        let mut ret = Self {
+            inner: HashMap::new(),
+        };
+        for package in [common_lisp, common_lisp_user, keyword] {
+            ret.inner.insert(package.name.clone(), package);
+        }
+        ret
+    }
+}
+
+impl Packages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn resolve_name<S: AsRef<str>>(&self, designator: S) -> Option<&str> {
+        let designator = designator.as_ref();
+        self.inner.values()
+            .find(|p| p.name == designator || p.nicknames.iter().any(|n| n == designator))
+            .map(|p| p.name.as_str())
+    }
+
+    pub fn find<S: AsRef<str>>(&self, designator: S) -> Option<&Package> {
+        let name = self.resolve_name(designator)?;
+        self.inner.get(name)
+    }
+
+    fn find_mut<S: AsRef<str>>(&mut self, designator: S) -> Result<&mut Package, PackageError> {
+        let name = self.resolve_name(designator.as_ref())
+            .ok_or_else(|| PackageError::PackageNotFound(designator.as_ref().into()))?
+            .to_string();
+        Ok(self.inner.get_mut(&name).unwrap())
+    }
+
+    /// Create the package if it does not exist yet, returns its real name
+    pub fn define<S: AsRef<str>>(&mut self, name: S) -> String {
+        if let Some(existing) = self.resolve_name(name.as_ref()) {
+            return existing.to_string();
+        }
+        let package = Package::new(name.as_ref());
+        self.inner.insert(package.name.clone(), package);
+        name.as_ref().to_string()
+    }
+
+    pub fn add_nickname<S1: AsRef<str>, S2: AsRef<str>>(&mut self, package: S1, nickname: S2) -> Result<(), PackageError> {
+        self.find_mut(package)?.add_nickname(nickname);
+        Ok(())
+    }
+
+    pub fn use_package<S1: AsRef<str>, S2: AsRef<str>>(&mut self, package: S1, used: S2) -> Result<(), PackageError> {
+        let used = self.resolve_name(used.as_ref())
+            .ok_or_else(|| PackageError::PackageNotFound(used.as_ref().into()))?
+            .to_string();
+        let package = self.find_mut(package)?;
+        if !package.uses.contains(&used) {
+            package.uses.push(used);
+        }
+        Ok(())
+    }
+
+    /// Look up a symbol accessible in the package, either present or inherited
+    pub fn find_symbol<S1: AsRef<str>, S2: AsRef<str>>(&self, package: S1, name: S2) -> Option<String> {
+        let package = self.find(package)?;
+        let name = name.as_ref();
+        if let Some(canonical) = package.symbols.get(name) {
+            return Some(canonical.clone());
+        }
+        package.uses.iter()
+            .filter_map(|used| self.inner.get(used))
+            .find(|used| used.is_external(name))
+            .and_then(|used| used.symbols.get(name).cloned())
+    }
+
+    /// Canonical name of a new symbol whose home is the package
+    fn new_canonical_name(&self, package: &str, name: &str) -> Result<String, PackageError> {
+        let package = self.find(package).ok_or_else(|| PackageError::PackageNotFound(package.into()))?;
+        let common_lisp = &self.inner[COMMON_LISP];
+        Ok(match package.name.as_str() {
+            COMMON_LISP_USER if common_lisp.symbols.contains_key(name) => format!("{}::{}", COMMON_LISP_USER, name),
+            _ => package.canonical_name(name),
+        })
+    }
+
+    pub fn intern<S1: AsRef<str>, S2: AsRef<str>>(&mut self, package: S1, name: S2) -> Result<String, PackageError> {
+        if let Some(canonical) = self.find_symbol(package.as_ref(), name.as_ref()) {
+            return Ok(canonical);
+        }
+        let canonical = self.new_canonical_name(package.as_ref(), name.as_ref())?;
+        let package = self.find_mut(package)?;
+        package.symbols.insert(name.as_ref().into(), canonical.clone());
+        if package.name == KEYWORD {
+            package.external.insert(name.as_ref().into());
+        }
+        Ok(canonical)
+    }
+
+    /// Make a fresh symbol present in the package, hiding the inherited one
+    pub fn shadow<S1: AsRef<str>, S2: AsRef<str>>(&mut self, package: S1, name: S2) -> Result<String, PackageError> {
+        let canonical = self.new_canonical_name(package.as_ref(), name.as_ref())?;
+        let package = self.find_mut(package)?;
+        package.symbols.entry(name.as_ref().into()).or_insert_with(|| canonical.clone());
+        Ok(package.symbols[name.as_ref()].clone())
+    }
+
+    pub fn export<S1: AsRef<str>, S2: AsRef<str>>(&mut self, package: S1, name: S2) -> Result<String, PackageError> {
+        let canonical = self.intern(package.as_ref(), name.as_ref())?;
+        let package = self.find_mut(package)?;
+        package.symbols.insert(name.as_ref().into(), canonical.clone());
+        package.external.insert(name.as_ref().into());
+        Ok(canonical)
+    }
+
+    /// Resolve a symbol token as the reader sees it into its canonical name
+    pub fn read_symbol<S1: AsRef<str>, S2: AsRef<str>>(&mut self, current: S1, token: S2) -> Result<String, PackageError> {
+        let token = token.as_ref();
+        // Uninterned symbols and reader macros
+        if token.starts_with('#') {
+            return Ok(token.to_string());
+        }
+        if let Some(name) = token.strip_prefix(':') {
+            return self.intern(KEYWORD, name);
+        }
+        if let Some((package, name)) = token.split_once("::") {
+            return self.intern(package, name);
+        }
+        if let Some((package, name)) = token.split_once(':') {
+            let found = self.find(package).ok_or_else(|| PackageError::PackageNotFound(package.into()))?;
+            return if found.is_external(name) {
+                Ok(found.symbols[name].clone())
+            } else {
+                Err(PackageError::SymbolNotExternal(found.name.clone(), name.into()))
+            };
+        }
+        self.intern(current, token)
+    }
+
+    /// Intern every symbol inside a freshly read form
+    pub fn intern_object(&mut self, current: &str, obj: Object) -> Result<Object, PackageError> {
+        match obj {
+            Object::Symbol(s) => Ok(Object::Symbol(self.read_symbol(current, s)?)),
+            Object::Quote(o) => Ok(Object::Quote(Box::new(self.intern_object(current, *o)?))),
+            Object::List(l) => {
+                let mut items = vec![];
+                let mut tail = l.to_list();
+                loop {
+                    match tail {
+                        List::Cons(car, cdr) => {
+                            items.push(self.intern_object(current, car)?);
+                            tail = *cdr;
+                        },
+                        List::EndsWith(o) => {
+                            tail = List::EndsWith(self.intern_object(current, o)?);
+                            break;
+                        },
+                    }
+                }
+                Ok(items.into_iter().rev().fold(tail, |acc, item| acc.prepend(item)).into())
+            },
+            _ => Ok(obj),
+        }
+    }
+}
+
+/// Name designated by a string or a symbol, stripping any package prefix
+pub fn designator_name(obj: &Object) -> Option<String> {
+    match obj {
+        Object::String(s) => Some(s.to_string()),
+        Object::Symbol(s) => Some(s.rsplit(':').next().unwrap_or(s).to_string()),
+        Object::Package(p) => Some(p.to_string()),
+        Object::Quote(o) => designator_name(o),
+        Object::Nil => Some("NIL".into()),
+        Object::T => Some("T".into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Packages, PackageError, COMMON_LISP, COMMON_LISP_USER};
+
+    #[test]
+    fn test_package_standard() {
+        let mut packages = Packages::new();
+        packages.export(COMMON_LISP, "CAR").unwrap();
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CAR"), Ok("CAR".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "FOO"), Ok("FOO".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, ":FOO"), Ok(":FOO".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CL:CAR"), Ok("CAR".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CL-USER::FOO"), Ok("FOO".into()));
+    }
+
+    #[test]
+    fn test_package_isolation() {
+        let mut packages = Packages::new();
+        packages.define("LIB");
+        packages.use_package("LIB", COMMON_LISP).unwrap();
+        packages.export("LIB", "API").unwrap();
+        assert_eq!(packages.read_symbol("LIB", "HELPER"), Ok("LIB::HELPER".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "HELPER"), Ok("HELPER".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "LIB:API"), Ok("LIB::API".into()));
+        assert_eq!(
+            packages.read_symbol(COMMON_LISP_USER, "LIB:HELPER"),
+            Err(PackageError::SymbolNotExternal("LIB".into(), "HELPER".into()))
+        );
+        packages.use_package(COMMON_LISP_USER, "LIB").unwrap();
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "API"), Ok("LIB::API".into()));
+    }
+
+    #[test]
+    fn test_package_shadow() {
+        let mut packages = Packages::new();
+        packages.export(COMMON_LISP, "CAR").unwrap();
+        packages.define("LIB");
+        packages.use_package("LIB", COMMON_LISP).unwrap();
+        packages.shadow("LIB", "CAR").unwrap();
+        assert_eq!(packages.read_symbol("LIB", "CAR"), Ok("LIB::CAR".into()));
+        assert_eq!(
+            packages.read_symbol("NOWHERE", "CAR"),
+            Err(PackageError::PackageNotFound("NOWHERE".into()))
+        );
+        packages.shadow(COMMON_LISP_USER, "CAR").unwrap();
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CAR"), Ok("COMMON-LISP-USER::CAR".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CL:CAR"), Ok("CAR".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CL-USER::CAR"), Ok("COMMON-LISP-USER::CAR".into()));
+        assert_eq!(packages.read_symbol(COMMON_LISP_USER, "CDR"), Ok("CDR".into()));
+    }
+}
@@ -6,6 +6,9 @@ use std::fmt;
 use crate::lexer::Token;
 use crate::types::{cons, nil, quote, Object};
 
+const UNQUOTE: &str = "UNQUOTE";
+const UNQUOTE_SPLICING: &str = "UNQUOTE-SPLICING";
+
 #[derive(Clone, Debug)]
 pub enum ParserError {
     UnexpectedToken(Token),
@@ -48,14 +51,101 @@ impl From<Token> for Object {
     }
 }
 
+fn parse_atom<I: Iterator<Item = Token>>(token: Token, tokens: &mut I) -> Result<Object, ParserError> {
+    match token {
+        // Structure literal #S(name :slot value ...)
+        Token::Symbol(s) if s.eq_ignore_ascii_case("#S") => match tokens.next() {
+            Some(Token::ParenLeft) => Ok(cons(Object::Symbol("#S".into()), parse_list(tokens)?)),
+            Some(t) => Err(ParserError::UnexpectedToken(t)),
+            None => Err(ParserError::UnexpectedEOF),
+        },
+        // Function shorthand #'name or #'(lambda ...)
+        Token::Symbol(s) if s == "#'" => match tokens.next() {
+            Some(Token::ParenLeft) => Ok(cons(Object::Symbol("FUNCTION".into()), cons(parse_list(tokens)?, nil()))),
+            Some(t) => Err(ParserError::UnexpectedToken(t)),
+            None => Err(ParserError::UnexpectedEOF),
+        },
+        Token::Symbol(s) if s.starts_with("#'") => {
+            let name = Object::from(Token::Symbol(s[2..].into()));
+            Ok(cons(Object::Symbol("FUNCTION".into()), cons(name, nil())))
+        },
+        _ => Ok(token.into()),
+    }
+}
+
 fn parse_quote<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Object, ParserError> {
-    let next_token = tokens.next();
-    if next_token == Some(Token::ParenLeft) {
-        Ok(quote(parse_list(tokens)?))
-    } else if let Some(n) = next_token {
-        Ok(quote(n.into()))
-    } else {
-        Err(ParserError::UnexpectedEOF)
+    Ok(quote(parse_next(tokens)?))
+}
+
+/// Form following a prefix like a quote or a comma
+fn parse_next<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Object, ParserError> {
+    match tokens.next() {
+        Some(Token::ParenLeft) => parse_list(tokens),
+        Some(Token::Quote) => parse_quote(tokens),
+        Some(t @ (Token::Backquote | Token::Comma | Token::CommaAt)) => parse_backquote(t, tokens),
+        Some(t @ (Token::ParenRight | Token::Dot)) => Err(ParserError::UnexpectedToken(t)),
+        Some(t) => parse_atom(t, tokens),
+        None => Err(ParserError::UnexpectedEOF),
+    }
+}
+
+/// Backquoted form expanded into the code building it, or a comma marking a form to evaluate in it
+fn parse_backquote<I: Iterator<Item = Token>>(token: Token, tokens: &mut I) -> Result<Object, ParserError> {
+    let form = parse_next(tokens)?;
+    Ok(match token {
+        Token::Comma => Object::from(vec![Object::Symbol(UNQUOTE.into()), form]),
+        Token::CommaAt => Object::from(vec![Object::Symbol(UNQUOTE_SPLICING.into()), form]),
+        _ => quasiquote(form),
+    })
+}
+
+/// Form marked with a comma, as `(UNQUOTE form)` or `(UNQUOTE-SPLICING form)`
+fn unquoted(obj: &Object, marker: &str) -> Option<Object> {
+    let Object::List(l) = obj else {
+        return None;
+    };
+    match l.unpack() {
+        (Object::Symbol(s), Object::List(rest)) if s == marker => match rest.unpack() {
+            (form, Object::Nil) => Some(form),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn has_unquote(obj: &Object) -> bool {
+    match obj {
+        Object::Symbol(s) => s == UNQUOTE || s == UNQUOTE_SPLICING,
+        Object::List(l) => l.clone().into_iter().any(|o| has_unquote(&o)),
+        Object::Quote(o) => has_unquote(o),
+        _ => false,
+    }
+}
+
+fn call(name: &str, args: Vec<Object>) -> Object {
+    cons(Object::Symbol(name.into()), Object::from(args))
+}
+
+/// Code building a backquoted form, with `CONS` for elements and `APPEND` for spliced lists.
+/// A dotted `. ,form` reads as the elements `UNQUOTE form`, so it is found when looking at the tail
+fn quasiquote(obj: Object) -> Object {
+    if !has_unquote(&obj) {
+        return quote(obj);
+    }
+    if let Some(form) = unquoted(&obj, UNQUOTE).or_else(|| unquoted(&obj, UNQUOTE_SPLICING)) {
+        return form;
+    }
+    match obj {
+        Object::List(l) => {
+            let (car, cdr) = l.unpack();
+            let rest = quasiquote(cdr);
+            match unquoted(&car, UNQUOTE_SPLICING) {
+                Some(spliced) => call("APPEND", vec![spliced, rest]),
+                None => call("CONS", vec![quasiquote(car), rest]),
+            }
+        },
+        Object::Quote(o) => call("CONS", vec![quote(Object::Symbol("QUOTE".into())), call("CONS", vec![quasiquote(*o), nil()])]),
+        o => quote(o),
     }
 }
 
@@ -75,7 +165,8 @@ fn parse_dotted_list<I: Iterator<Item = Token>>(
         Token::ParenRight | Token::Dot => Err(ParserError::UnexpectedToken(next_token)),
         Token::ParenLeft => Ok(cons(last, parse_list(tokens)?)),
         Token::Quote => Ok(cons(last, parse_quote(tokens)?)),
-        _ => Ok(cons(last, next_token.into())),
+        Token::Backquote | Token::Comma | Token::CommaAt => Ok(cons(last, parse_backquote(next_token, tokens)?)),
+        _ => Ok(cons(last, parse_atom(next_token, tokens)?)),
     }
 }
 
@@ -89,7 +180,16 @@ fn parse_list<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Object, Parse
                     return Err(ParserError::UnexpectedToken(t));
                 }
                 let last = stack.pop().unwrap();
-                stack.push(parse_dotted_list(last, tokens)?);
+                let mut ret = parse_dotted_list(last, tokens)?;
+                match tokens.next() {
+                    Some(Token::ParenRight) => (),
+                    Some(t) => return Err(ParserError::UnexpectedToken(t)),
+                    None => return Err(ParserError::UnmatchedParens),
+                }
+                for obj in stack.into_iter().rev() {
+                    ret = cons(obj, ret);
+                }
+                return Ok(ret);
             }
             Token::ParenLeft => {
                 stack.push(parse_list(tokens)?);
@@ -97,11 +197,14 @@ fn parse_list<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Object, Parse
             Token::Quote => {
                 stack.push(parse_quote(tokens)?);
             }
+            Token::Backquote | Token::Comma | Token::CommaAt => {
+                stack.push(parse_backquote(t, tokens)?);
+            }
             Token::ParenRight => {
                 return Ok(Object::from(stack));
             }
             _ => {
-                stack.push(t.into());
+                stack.push(parse_atom(t, tokens)?);
             }
         }
         token = tokens.next();
@@ -119,15 +222,17 @@ pub fn parse<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Object, Parser
         Token::Dot | Token::ParenRight => Err(ParserError::UnexpectedToken(first_token)),
         Token::ParenLeft => parse_list(tokens),
         Token::Quote => parse_quote(tokens),
+        Token::Backquote | Token::Comma | Token::CommaAt => parse_backquote(first_token, tokens),
         // _ => Ok(cons(Object::from(first_token), parse(tokens)?)),
-        _ => Ok(first_token.into()),
+        _ => parse_atom(first_token, tokens),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::super::lexer::Lexer;
-    use super::parse;
+    use super::super::lexer::{Lexer, Token};
+    use super::super::types::Object;
+    use super::{parse, ParserError};
 
     fn parse_and_compare<S1: AsRef<str>, S2: AsRef<str>>(orig: S1, res: S2) {
         let mut lexer = Lexer::new(orig.as_ref().as_bytes());
@@ -141,5 +246,37 @@ mod test {
         parse_and_compare("(test1 '(test2 . \"test3\")) ", "(TEST1 '(TEST2 . \"test3\"))");
         parse_and_compare("((A . B) . (C . D))", "((A . B) C . D)");
         parse_and_compare("(A)", "(A)");
+        parse_and_compare("((A B))", "((A B))");
+        parse_and_compare("(A B . C)", "(A B . C)");
+        parse_and_compare("'#S(point :x 1)", "'(#S POINT :X 1)");
+        parse_and_compare("#'car", "(FUNCTION CAR)");
+        parse_and_compare("#'(lambda (x) x)", "(FUNCTION (LAMBDA (X) X))");
+        parse_and_compare("`(a b)", "'(A B)");
+        parse_and_compare("`(a ,b ,@c)", "(CONS 'A (CONS B (APPEND C 'NIL)))");
+        parse_and_compare("`(a . ,b)", "(CONS 'A B)");
+        parse_and_compare("`(f ',x)", "(CONS 'F (CONS (CONS 'QUOTE (CONS X NIL)) 'NIL))");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        fn parse_str(s: &str) -> Result<Object, ParserError> {
+            parse(&mut Lexer::new(s.as_bytes()))
+        }
+        assert!(matches!(parse_str(""), Err(ParserError::EmptyInput)));
+        assert!(matches!(parse_str("  \n "), Err(ParserError::EmptyInput)));
+        assert!(matches!(parse_str("(a (b)"), Err(ParserError::UnmatchedParens)));
+        assert!(matches!(parse_str(")"), Err(ParserError::UnexpectedToken(Token::ParenRight))));
+        assert!(matches!(parse_str("'"), Err(ParserError::UnexpectedEOF)));
+        assert!(matches!(parse_str("#'"), Err(ParserError::UnexpectedEOF)));
+        // A dot needs exactly one object on each side
+        assert!(matches!(parse_str("(. a)"), Err(ParserError::UnexpectedToken(Token::Dot))));
+        assert!(matches!(parse_str("(a . )"), Err(ParserError::UnexpectedToken(Token::ParenRight))));
+        assert!(matches!(parse_str("(a . b c)"), Err(ParserError::UnexpectedToken(Token::Symbol(s))) if s == "c"));
+        // Forms after the first one are left to the next call
+        let mut lexer = Lexer::new("a (b) )".as_bytes());
+        assert_eq!(parse(&mut lexer).ok().map(|o| o.to_string()), Some("A".into()));
+        assert_eq!(parse(&mut lexer).ok().map(|o| o.to_string()), Some("(B)".into()));
+        assert!(matches!(parse(&mut lexer), Err(ParserError::UnexpectedToken(Token::ParenRight))));
+        assert!(matches!(parse(&mut lexer), Err(ParserError::EmptyInput)));
     }
 }
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use super::Object;
+use super::equality::eql;
+use crate::eval::EvalError;
+
+pub const CLASS_T: &str = "T";
+pub const STANDARD_OBJECT: &str = "STANDARD-OBJECT";
+pub const STRUCTURE_OBJECT: &str = "STRUCTURE-OBJECT";
+pub const CONDITION: &str = "CONDITION";
+
+// (Name, Direct superclasses)
+const BUILTIN_CLASSES: &[(&str, &[&str])] = &[
+    (CLASS_T, &[]),
+    (STANDARD_OBJECT, &[CLASS_T]),
+    (STRUCTURE_OBJECT, &[CLASS_T]),
+    ("NUMBER", &[CLASS_T]),
+    ("REAL", &["NUMBER"]),
+    ("RATIONAL", &["REAL"]),
+    ("INTEGER", &["RATIONAL"]),
+    ("FLOAT", &["REAL"]),
+    ("SEQUENCE", &[CLASS_T]),
+    ("LIST", &["SEQUENCE"]),
+    ("CONS", &["LIST"]),
+    ("SYMBOL", &[CLASS_T]),
+    ("NULL", &["SYMBOL", "LIST"]),
+    ("ARRAY", &[CLASS_T]),
+    ("VECTOR", &["ARRAY", "SEQUENCE"]),
+    ("STRING", &["VECTOR"]),
+    ("FUNCTION", &[CLASS_T]),
+    ("PACKAGE", &[CLASS_T]),
+    ("HASH-TABLE", &[CLASS_T]),
+    ("STREAM", &[CLASS_T]),
+    ("STRING-STREAM", &["STREAM"]),
+    ("FILE-STREAM", &["STREAM"]),
+];
+
+// (Name, Direct superclasses, Slots initialized by the keyword of the same name)
+const CONDITION_CLASSES: &[(&str, &[&str], &[&str])] = &[
+    (CONDITION, &[CLASS_T], &[]),
+    ("SERIOUS-CONDITION", &[CONDITION], &[]),
+    ("ERROR", &["SERIOUS-CONDITION"], &[]),
+    ("WARNING", &[CONDITION], &[]),
+    ("STYLE-WARNING", &["WARNING"], &[]),
+    ("SIMPLE-CONDITION", &[CONDITION], &["FORMAT-CONTROL", "FORMAT-ARGUMENTS"]),
+    ("SIMPLE-ERROR", &["SIMPLE-CONDITION", "ERROR"], &[]),
+    ("SIMPLE-WARNING", &["SIMPLE-CONDITION", "WARNING"], &[]),
+    ("TYPE-ERROR", &["ERROR"], &["DATUM", "EXPECTED-TYPE"]),
+    ("SIMPLE-TYPE-ERROR", &["SIMPLE-CONDITION", "TYPE-ERROR"], &[]),
+    ("PROGRAM-ERROR", &["ERROR"], &[]),
+    ("CONTROL-ERROR", &["ERROR"], &[]),
+    ("PARSE-ERROR", &["ERROR"], &[]),
+    ("CELL-ERROR", &["ERROR"], &["NAME"]),
+    ("UNBOUND-VARIABLE", &["CELL-ERROR"], &[]),
+    ("UNDEFINED-FUNCTION", &["CELL-ERROR"], &[]),
+    ("UNBOUND-SLOT", &["CELL-ERROR"], &["INSTANCE"]),
+    ("ARITHMETIC-ERROR", &["ERROR"], &["OPERATION", "OPERANDS"]),
+    ("DIVISION-BY-ZERO", &["ARITHMETIC-ERROR"], &[]),
+    ("PACKAGE-ERROR", &["ERROR"], &["PACKAGE"]),
+    ("STREAM-ERROR", &["ERROR"], &["STREAM"]),
+    ("END-OF-FILE", &["STREAM-ERROR"], &[]),
+    ("FILE-ERROR", &["ERROR"], &["PATHNAME"]),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotDef {
+    pub name: String,
+    pub initargs: Vec<String>,
+    pub initform: Option<Object>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Class {
+    pub name: String,
+    pub direct_superclasses: Vec<String>,
+    pub direct_slots: Vec<SlotDef>,
+    pub precedence_list: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Qualifier {
+    Primary,
+    Before,
+    After,
+    Around,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Specializer {
+    Class(String),
+    Eql(Object),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Method {
+    pub qualifier: Qualifier,
+    pub specializers: Vec<Specializer>,
+    pub function: Object,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Generic {
+    pub methods: Vec<Method>,
+    pub required: Option<usize>, // Number of required parameters every method takes, once known
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Classes {
+    inner: HashMap<String, Class>,
+}
+
+impl Class {
+    pub fn new<S: AsRef<str>>(name: S, direct_superclasses: Vec<String>, direct_slots: Vec<SlotDef>) -> Self {
+        Self {
+            name: name.as_ref().to_string(),
+            direct_superclasses,
+            direct_slots,
+            precedence_list: vec![],
+        }
+    }
+}
+
+impl Default for Classes {
+    fn default() -> Self {
+        let mut ret = Self {
+            inner: HashMap::new(),
+        };
+        for (name, supers) in BUILTIN_CLASSES {
+            let class = Class::new(name, supers.iter().map(|s| s.to_string()).collect(), vec![]);
+            ret.define(class).expect("Inconsistent builtin classes");
+        }
+        for (name, supers, slots) in CONDITION_CLASSES {
+            let slots = slots.iter()
+                .map(|s| SlotDef { name: s.to_string(), initargs: vec![format!(":{}", s)], initform: None })
+                .collect();
+            let class = Class::new(name, supers.iter().map(|s| s.to_string()).collect(), slots);
+            ret.define(class).expect("Inconsistent condition classes");
+        }
+        ret
+    }
+}
+
+impl Classes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.inner.keys().cloned().collect()
+    }
+
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<&Class> {
+        self.inner.get(name.as_ref())
+    }
+
+    /// Register a class, computing its precedence list
+    pub fn define(&mut self, class: Class) -> Result<(), EvalError> {
+        let mut class = class;
+        class.precedence_list = self.linearize(&class.name, &class.direct_superclasses)?;
+        self.inner.insert(class.name.clone(), class);
+        Ok(())
+    }
+
+    /// C3 linearization
+    fn linearize(&self, name: &str, supers: &[String]) -> Result<Vec<String>, EvalError> {
+        let mut sequences: Vec<Vec<String>> = vec![];
+        for s in supers {
+            let class = self.inner.get(s).ok_or_else(|| EvalError::ClassNotFound(s.clone()))?;
+            sequences.push(class.precedence_list.clone());
+        }
+        sequences.push(supers.to_vec());
+        let mut ret = vec![name.to_string()];
+        loop {
+            sequences.retain(|s| !s.is_empty());
+            if sequences.is_empty() {
+                return Ok(ret);
+            }
+            let candidate = sequences.iter()
+                .map(|s| &s[0])
+                .find(|c| sequences.iter().all(|s| !s[1..].contains(c)))
+                .cloned()
+                .ok_or_else(|| EvalError::InconsistentPrecedence(name.to_string()))?;
+            for s in sequences.iter_mut() {
+                if s[0] == candidate {
+                    s.remove(0);
+                }
+            }
+            ret.push(candidate);
+        }
+    }
+
+    pub fn precedence_list<S: AsRef<str>>(&self, name: S) -> Vec<String> {
+        self.inner.get(name.as_ref())
+            .map(|c| c.precedence_list.clone())
+            .unwrap_or_else(|| vec![name.as_ref().to_string(), CLASS_T.into()])
+    }
+
+    pub fn subclass_p<S1: AsRef<str>, S2: AsRef<str>>(&self, class: S1, superclass: S2) -> bool {
+        self.precedence_list(class).iter().any(|c| c == superclass.as_ref())
+    }
+
+    /// Slots of the class and all its superclasses, more specific definitions first
+    pub fn effective_slots<S: AsRef<str>>(&self, name: S) -> Vec<SlotDef> {
+        let mut ret: Vec<SlotDef> = vec![];
+        for class in self.precedence_list(name).iter().filter_map(|c| self.inner.get(c)) {
+            for slot in class.direct_slots.iter() {
+                if let Some(existing) = ret.iter_mut().find(|s| s.name == slot.name) {
+                    existing.initargs.extend(slot.initargs.iter().cloned());
+                    if existing.initform.is_none() {
+                        existing.initform = slot.initform.clone();
+                    }
+                } else {
+                    ret.push(slot.clone());
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// Name of the class an object is a direct instance of
+pub fn class_of(obj: &Object) -> String {
+    match obj {
+        Object::Nil => "NULL",
+        Object::T | Object::Symbol(_) => "SYMBOL",
+        Object::Integer(_) => "INTEGER",
+        Object::Float(_) => "FLOAT",
+        Object::String(_) => "STRING",
+        Object::List(_) => "CONS",
+        Object::Lambda(_) => "FUNCTION",
+        Object::Package(_) => "PACKAGE",
+        Object::HashTable(_) => "HASH-TABLE",
+        Object::Stream(s) if s.is_string_stream() => "STRING-STREAM",
+        Object::Stream(s) if s.get_pathname().is_some() => "FILE-STREAM",
+        Object::Stream(_) => "STREAM",
+        Object::Quote(o) => return class_of(o),
+        Object::Struct(s) => return s.get_name(),
+        Object::Instance(i) => return i.get_class(),
+    }.into()
+}
+
+impl Generic {
+    /// Whether a lambda list with this many required parameters fits the generic function,
+    /// the first one fixing the number for the others
+    pub fn congruent(&mut self, required: usize) -> bool {
+        *self.required.get_or_insert(required) == required
+    }
+
+    /// Add a method, replacing the one with the same qualifier and specializers
+    pub fn add_method(&mut self, method: Method) {
+        self.methods.retain(|m| m.qualifier != method.qualifier || m.specializers != method.specializers);
+        self.methods.push(method);
+    }
+
+    /// Applicable methods sorted from the most specific to the least specific
+    pub fn applicable_methods(&self, classes: &Classes, args: &[Object]) -> Vec<Method> {
+        let precedence: Vec<Vec<String>> = args.iter()
+            .map(|a| classes.precedence_list(class_of(a)))
+            .collect();
+        // Rank of each specializer, eql specializers come before every class
+        let rank = |method: &Method| -> Option<Vec<usize>> {
+            let mut ret = vec![];
+            for (i, specializer) in method.specializers.iter().enumerate() {
+                let arg = args.get(i)?;
+                ret.push(match specializer {
+                    Specializer::Eql(o) if eql(o, arg) => 0,
+                    Specializer::Eql(_) => return None,
+                    Specializer::Class(c) => 1 + precedence[i].iter().position(|p| p == c)?,
+                });
+            }
+            Some(ret)
+        };
+        let mut ranked: Vec<(Vec<usize>, Method)> = self.methods.iter()
+            .filter_map(|m| rank(m).map(|r| (r, m.clone())))
+            .collect();
+        ranked.sort_by(|(a, _), (b, _)| a.cmp(b));
+        ranked.into_iter().map(|(_, m)| m).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Class, Classes, EvalError};
+
+    fn define(classes: &mut Classes, name: &str, supers: &[&str]) -> Result<(), EvalError> {
+        classes.define(Class::new(name, supers.iter().map(|s| s.to_string()).collect(), vec![]))
+    }
+
+    #[test]
+    fn test_class_builtin_precedence() {
+        let classes = Classes::new();
+        assert_eq!(classes.precedence_list("NULL"), vec!["NULL", "SYMBOL", "LIST", "SEQUENCE", "T"]);
+        assert_eq!(classes.precedence_list("STRING"), vec!["STRING", "VECTOR", "ARRAY", "SEQUENCE", "T"]);
+        assert_eq!(
+            classes.precedence_list("SIMPLE-ERROR"),
+            vec!["SIMPLE-ERROR", "SIMPLE-CONDITION", "ERROR", "SERIOUS-CONDITION", "CONDITION", "T"]
+        );
+    }
+
+    #[test]
+    fn test_class_c3() {
+        let mut classes = Classes::new();
+        define(&mut classes, "O", &["STANDARD-OBJECT"]).unwrap();
+        define(&mut classes, "A", &["O"]).unwrap();
+        define(&mut classes, "B", &["O"]).unwrap();
+        define(&mut classes, "C", &["O"]).unwrap();
+        define(&mut classes, "D", &["O"]).unwrap();
+        define(&mut classes, "E", &["O"]).unwrap();
+        define(&mut classes, "K1", &["A", "B", "C"]).unwrap();
+        define(&mut classes, "K2", &["D", "B", "E"]).unwrap();
+        define(&mut classes, "K3", &["D", "A"]).unwrap();
+        define(&mut classes, "Z", &["K1", "K2", "K3"]).unwrap();
+        assert_eq!(
+            classes.precedence_list("Z"),
+            vec!["Z", "K1", "K2", "K3", "D", "A", "B", "C", "E", "O", "STANDARD-OBJECT", "T"]
+        );
+        assert_eq!(define(&mut classes, "BAD", &["O", "A"]), Err(EvalError::InconsistentPrecedence("BAD".into())));
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+use super::Object;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandlerAction {
+    Call(Object),      // handler-bind, the function is called in the dynamic context of the signal
+    Exit(usize, usize), // handler-case, (Frame id, Clause index)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handler {
+    pub type_spec: Object,
+    pub action: HandlerAction,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Restart {
+    pub name: String,
+    pub id: usize,    // Frame establishing the restart
+    pub index: usize, // Clause index in that frame
+}
+
+// Dynamic state of the condition system, shared by every environment
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Conditions {
+    pub handlers: Vec<Vec<Handler>>, // Innermost cluster last
+    pub restarts: Vec<Restart>,      // Innermost restart last
+    pub reports: HashMap<String, Object>, // Class name -> :report string or function
+}
+
+impl Conditions {
+    pub fn find_restart<S: AsRef<str>>(&self, name: S) -> Option<Restart> {
+        self.restarts.iter().rev().find(|r| r.name == name.as_ref()).cloned()
+    }
+}
@@ -0,0 +1,133 @@
+use super::{Cons, Object};
+
+/// Identity: symbols, fixnums and shared objects such as conses, functions, structures, hash tables
+/// and streams. Unlike Common Lisp, strings are stored by value and have no identity, so they are
+/// compared by content to keep `eq` reflexive for them; `(eq "a" "a")` is true.
+pub fn eq(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Nil, Object::Nil) | (Object::T, Object::T) => true,
+        (Object::Symbol(a), Object::Symbol(b)) | (Object::Package(a), Object::Package(b)) => a == b,
+        (Object::Integer(a), Object::Integer(b)) => a == b,
+        (Object::String(a), Object::String(b)) => a == b,
+        (Object::List(a), Object::List(b)) => a.is_identical(b),
+        (Object::Quote(a), Object::Quote(b)) => eq(a, b),
+        (Object::Lambda(a), Object::Lambda(b)) => a.is_identical(b),
+        (Object::Struct(a), Object::Struct(b)) => a == b,
+        (Object::Instance(a), Object::Instance(b)) => a == b,
+        (Object::HashTable(a), Object::HashTable(b)) => a == b,
+        (Object::Stream(a), Object::Stream(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Identity, or numbers of the same type with the same value
+pub fn eql(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Float(a), Object::Float(b)) => a.to_bits() == b.to_bits(),
+        _ => eq(a, b),
+    }
+}
+
+/// Structural equality of conses and strings, `eql` for everything else
+pub fn equal(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::String(a), Object::String(b)) => a == b,
+        (Object::List(a), Object::List(b)) => list_equal(a, b, equal),
+        (Object::Quote(a), Object::Quote(b)) => equal(a, b),
+        _ => eql(a, b),
+    }
+}
+
+/// Like `equal`, but ignores the case of strings and the type of numbers,
+/// and compares structures and hash tables by content
+pub fn equalp(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Integer(_) | Object::Float(_), Object::Integer(_) | Object::Float(_)) => number_value(a) == number_value(b),
+        (Object::String(a), Object::String(b)) => a.to_lowercase() == b.to_lowercase(),
+        (Object::List(a), Object::List(b)) => list_equal(a, b, equalp),
+        (Object::Quote(a), Object::Quote(b)) => equalp(a, b),
+        (Object::Struct(a), Object::Struct(b)) => {
+            let (a_slots, b_slots) = (a.get_slots(), b.get_slots());
+            a.get_name() == b.get_name() && a_slots.len() == b_slots.len()
+                && a_slots.iter().zip(b_slots.iter()).all(|((_, x), (_, y))| equalp(x, y))
+        },
+        (Object::HashTable(a), Object::HashTable(b)) => {
+            a.get_test() == b.get_test() && a.len() == b.len()
+                && a.entries().iter().all(|(k, v)| b.get(k).is_some_and(|w| equalp(v, &w)))
+        },
+        _ => eq(a, b),
+    }
+}
+
+/// Equality predicate of the given standard name
+pub fn test_function<S: AsRef<str>>(name: S) -> Option<fn(&Object, &Object) -> bool> {
+    match name.as_ref() {
+        "EQ" => Some(eq),
+        "EQL" => Some(eql),
+        "EQUAL" => Some(equal),
+        "EQUALP" => Some(equalp),
+        _ => None,
+    }
+}
+
+fn number_value(obj: &Object) -> f64 {
+    match obj {
+        Object::Integer(i) => *i as f64,
+        Object::Float(f) => *f,
+        _ => f64::NAN,
+    }
+}
+
+fn list_equal(a: &Cons, b: &Cons, test: fn(&Object, &Object) -> bool) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    loop {
+        let ((x, xs), (y, ys)) = (a.unpack(), b.unpack());
+        if !test(&x, &y) {
+            return false;
+        }
+        match (xs, ys) {
+            (Object::List(xs), Object::List(ys)) => (a, b) = (xs, ys),
+            (xs, ys) => return test(&xs, &ys),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{eq, eql, equal, equalp};
+    use crate::types::{Object, symbol};
+    use crate::builtins::test::interpreter;
+
+    #[test]
+    fn test_equality_predicates() {
+        let list = Object::from(vec![symbol("A"), Object::String("b".into())]);
+        let upper = Object::from(vec![symbol("A"), Object::String("B".into())]);
+        assert!(eq(&symbol("A"), &symbol("A")));
+        assert!(eq(&list, &list.clone()));
+        assert!(!eq(&list, &Object::from(vec![symbol("A"), Object::String("b".into())])));
+        assert!(eq(&Object::String("b".into()), &Object::String("b".into())));
+        assert!(!eq(&Object::Float(1.5), &Object::Float(1.5)));
+        assert!(eql(&Object::Float(1.5), &Object::Float(1.5)));
+        assert!(!eql(&Object::Integer(1), &Object::Float(1.0)));
+        assert!(equal(&list, &list.clone()));
+        assert!(!equal(&list, &upper));
+        assert!(equalp(&list, &upper));
+        assert!(equalp(&Object::Integer(1), &Object::Float(1.0)));
+    }
+
+    #[test]
+    fn test_identity() {
+        let (_, run) = interpreter();
+        assert_eq!(run("(let ((l (cons 1 2))) (cons (eq l l) (eql l l)))"), run("'(t . t)"));
+        assert_eq!(run("(let ((s \"str\")) (eq s s))"), Ok(Object::T));
+        assert_eq!(run("(eq (cons 1 2) (cons 1 2))"), Ok(Object::Nil));
+        assert_eq!(run("(let ((l (list 1 2))) (eq (cdr l) (cdr l)))"), Ok(Object::T));
+        assert_eq!(run("(eq \"str\" \"str\")"), Ok(Object::T));
+        assert_eq!(run("(let ((h (make-hash-table))) (setf (gethash (cons 1 2) h) 1) (setf (gethash (cons 1 2) h) 2) (cons (gethash (cons 1 2) h) (hash-table-count h)))"), run("'(nil . 2)"));
+        assert_eq!(run("(let ((h (make-hash-table :test 'equal))) (setf (gethash (cons 1 2) h) 1) (setf (gethash (cons 1 2) h) 2) (cons (gethash (cons 1 2) h) (hash-table-count h)))"), run("'(2 . 1)"));
+        assert_eq!(run("(let ((l (list (cons 1 2)))) (pushnew (cons 1 2) l) (pushnew (cons 1 2) l :test #'equal) l)"), run("'((1 . 2) (1 . 2))"));
+        assert_eq!(run("(let ((l nil) (k (cons 1 2))) (pushnew k l) (pushnew k l) l)"), run("'((1 . 2))"));
+        assert_eq!(run("(eq (lambda (x) x) (lambda (x) x))"), Ok(Object::Nil));
+        assert_eq!(run("(let ((f (lambda (x) x))) (eq f f))"), Ok(Object::T));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::Object;
+use super::equality::{eql, test_function};
+
+#[derive(Debug)]
+struct HashTableData {
+    test: String,
+    entries: Vec<(Object, Object)>, // Objects are not hashable, keys are compared one by one with the test
+}
+
+impl HashTableData {
+    fn test_function(&self) -> fn(&Object, &Object) -> bool {
+        test_function(&self.test).unwrap_or(eql)
+    }
+}
+
+// Hash tables are shared like structures
+#[derive(Clone, Debug)]
+pub struct HashTable {
+    inner: Rc<RefCell<HashTableData>>,
+}
+
+impl HashTable {
+    pub fn new<S: AsRef<str>>(test: S) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(HashTableData {
+                test: test.as_ref().to_string(),
+                entries: vec![],
+            })),
+        }
+    }
+
+    pub fn get_test(&self) -> String {
+        self.inner.borrow().test.clone()
+    }
+
+    pub fn get(&self, key: &Object) -> Option<Object> {
+        let inner = self.inner.borrow();
+        let test = inner.test_function();
+        inner.entries.iter()
+            .find(|(k, _)| test(k, key))
+            .map(|(_, v)| v.clone())
+    }
+
+    pub fn insert(&self, key: Object, value: Object) {
+        let mut inner = self.inner.borrow_mut();
+        let test = inner.test_function();
+        if let Some((_, v)) = inner.entries.iter_mut().find(|(k, _)| test(k, &key)) {
+            *v = value;
+        } else {
+            inner.entries.push((key, value));
+        }
+    }
+
+    pub fn remove(&self, key: &Object) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let test = inner.test_function();
+        let len = inner.entries.len();
+        inner.entries.retain(|(k, _)| !test(k, key));
+        len != inner.entries.len()
+    }
+
+    pub fn clear(&self) {
+        self.inner.borrow_mut().entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.borrow().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn entries(&self) -> Vec<(Object, Object)> {
+        self.inner.borrow().entries.clone()
+    }
+}
+
+impl PartialEq for HashTable {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Display for HashTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "#<HASH-TABLE :TEST {} :COUNT {}>", self.get_test(), self.len())
+    }
+}
+
+impl From<HashTable> for Object {
+    fn from(h: HashTable) -> Self {
+        Self::HashTable(h)
+    }
+}
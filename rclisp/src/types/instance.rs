@@ -0,0 +1,59 @@
+use std::fmt;
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::Object;
+
+#[derive(Debug)]
+struct InstanceData {
+    class: String,
+    slots: HashMap<String, Object>, // Unbound slots are absent
+}
+
+// Instances of user defined classes, shared like structures
+#[derive(Clone, Debug)]
+pub struct Instance {
+    inner: Rc<RefCell<InstanceData>>,
+}
+
+impl Instance {
+    pub fn new<S: AsRef<str>>(class: S) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(InstanceData {
+                class: class.as_ref().to_string(),
+                slots: HashMap::new(),
+            })),
+        }
+    }
+
+    pub fn get_class(&self) -> String {
+        self.inner.borrow().class.clone()
+    }
+
+    pub fn get_slot<S: AsRef<str>>(&self, name: S) -> Option<Object> {
+        self.inner.borrow().slots.get(name.as_ref()).cloned()
+    }
+
+    pub fn set_slot<S: AsRef<str>>(&self, name: S, value: Object) {
+        self.inner.borrow_mut().slots.insert(name.as_ref().to_string(), value);
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "#<{}>", self.get_class())
+    }
+}
+
+impl From<Instance> for Object {
+    fn from(i: Instance) -> Self {
+        Self::Instance(i)
+    }
+}
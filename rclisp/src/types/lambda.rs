@@ -1,15 +1,19 @@
 use std::fmt;
-use std::sync::Arc;
+use std::rc::Rc;
 use std::error::Error;
 use std::ops::{Deref, DerefMut};
 use std::collections::HashSet;
 
-use super::{List, Object};
+use super::{List, Object, cons, nil};
 use crate::env::RcEnv;
 use crate::eval::EvalError;
 
-pub trait BuiltinFunc: Sync + Send {
+pub trait BuiltinFunc {
     fn eval(&self, env: &RcEnv) -> Result<Object, EvalError>;
+    /// Builtins returning more than one value override this, `eval` returns the primary one
+    fn eval_values(&self, env: &RcEnv) -> Result<Vec<Object>, EvalError> {
+        Ok(vec![self.eval(env)?])
+    }
     fn get_parameters(&self) -> &Params;
     fn get_name(&self) -> &str;
 }
@@ -19,14 +23,20 @@ pub enum ParamError {
     InvalidType,
 }
 
-// TODO: Add support for keyed parameters
 // TODO: Add support for type annotation
 #[derive(Clone, Debug, PartialEq)]
 pub enum Parameter {
     Normal(String),
     Plain(String),
     Rest(String),
-    Optional(String, Object), // (Name, Default)
+    PlainRest(String),
+    Optional(String, Object, Option<String>), // (Name, Default, Supplied-p)
+    Key(String, String, Object, Option<String>), // (Name, Keyword, Default, Supplied-p)
+    AllowOtherKeys,
+    Aux(String, Object),      // (Name, Initial form)
+    Nested(Params),           // Destructuring pattern matched against a sublist
+    Whole(String),            // Whole form a macro was called with
+    Environment(String),      // Environment a macro is expanded in
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -38,7 +48,7 @@ pub struct Params {
 // Use a separate struct so that I don't have to implement all traits manually
 #[derive(Clone)]
 pub struct Builtin {
-    pub(crate) inner: Arc<dyn BuiltinFunc>,
+    pub(crate) inner: Rc<dyn BuiltinFunc>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -46,12 +56,24 @@ pub enum Lambda {
     Unnamed(UserLambda),
     Named(String, UserLambda),
     Builtin(Builtin),
+    Macro(String, UserLambda),
 }
 
+// Type of variables declared at the start of a body, `(type spec var...)` or `(spec var...)`
 #[derive(Clone, Debug, PartialEq)]
+pub struct TypeDeclaration {
+    pub type_spec: Object,
+    pub variables: Vec<Object>,
+}
+
+#[derive(Clone)]
 pub struct UserLambda {
     parameters: Params,
     body: List,
+    declarations: Rc<Vec<TypeDeclaration>>, // Parsed from the start of the body, which no longer holds them
+    env: Option<RcEnv>, // Defining environment, the caller's one is used if there is none
+    lambda_list: Option<Object>, // Lambda list as written, if the lambda was defined from source
+    identity: Rc<()>, // Shared by the copies of one lambda, so that they are `eq`
 }
 
 impl Parameter {
@@ -67,51 +89,67 @@ impl Parameter {
         Self::Rest(name.as_ref().to_string())
     }
 
+    pub fn plain_rest<S: AsRef<str>>(name: S) -> Self {
+        Self::PlainRest(name.as_ref().to_string())
+    }
+
     pub fn optional<S: AsRef<str>>(name: S, default: Object) -> Self {
-        Self::Optional(name.as_ref().to_string(), default)
+        Self::Optional(name.as_ref().to_string(), default, None)
     }
 
     pub fn get_name(&self) -> &str {
         match self {
-            Self::Normal(n) | Self::Optional(n, _) | Self::Plain(n) | Self::Rest(n) => n,
+            Self::Normal(n) | Self::Optional(n, _, _) | Self::Plain(n) | Self::Rest(n) | Self::PlainRest(n) => n,
+            Self::Key(n, _, _, _) | Self::Aux(n, _) | Self::Whole(n) | Self::Environment(n) => n,
+            Self::Nested(_) | Self::AllowOtherKeys => "",
         }
     }
 
     pub fn is_rest(&self) -> bool {
-        matches!(self, Parameter::Rest(_))
+        matches!(self, Parameter::Rest(_) | Parameter::PlainRest(_))
+    }
+
+    /// Whether the parameter takes the next argument by position
+    pub fn is_positional(&self) -> bool {
+        matches!(self, Parameter::Normal(_) | Parameter::Plain(_) | Parameter::Nested(_) | Parameter::Optional(_, _, _))
     }
 }
 
 impl Params {
     pub fn len_required(&self) -> usize {
         self.inner.iter().fold(0, |acc, p| 
-            if let Parameter::Optional(_, _) = p { acc } else { acc + 1 })
+            if matches!(p, Parameter::Normal(_) | Parameter::Plain(_) | Parameter::Nested(_)) { acc + 1 } else { acc })
     }
 
     pub fn validate(&self) -> bool {
         let mut set = HashSet::new();
         let mut met_optional = false;
-        let len = self.len();
-        for param in &self.inner {
+        for (i, param) in self.inner.iter().enumerate() {
             let name = param.get_name();
-            if set.contains(name) {
+            if !name.is_empty() && !set.insert(name) {
                 return false;
             }
-            set.insert(name);
             match param {
                 Parameter::Normal(_) | Parameter::Plain(_) => {
                     if met_optional {
                         return false;
                     }
                 },
-                Parameter::Optional(_, _) => {
+                Parameter::Nested(p) => {
+                    if met_optional || !p.validate() {
+                        return false;
+                    }
+                },
+                Parameter::Optional(_, _, _) => {
                     met_optional = true;
                 },
-                Parameter::Rest(_) => {
-                    if len != set.len() {
+                Parameter::Rest(_) | Parameter::PlainRest(_) => {
+                    if self.inner[i + 1..].iter().any(|p| p.is_rest() || p.is_positional()) {
                         return false;
                     }
                 },
+                Parameter::Key(_, _, _, _) | Parameter::AllowOtherKeys | Parameter::Aux(_, _) => (),
+                Parameter::Whole(_) | Parameter::Environment(_) => (),
             }
         }
         true
@@ -123,9 +161,49 @@ impl UserLambda {
         Self {
             parameters,
             body,
+            declarations: Rc::new(vec![]),
+            env: None,
+            lambda_list: None,
+            identity: Rc::new(()),
+        }
+    }
+
+    /// Same lambda closing over the given environment
+    pub fn with_env(self, env: &RcEnv) -> Self {
+        Self {
+            env: Some(env.clone()),
+            ..self
         }
     }
 
+    /// Same lambda remembering the lambda list it was defined with
+    pub fn with_lambda_list(self, lambda_list: Object) -> Self {
+        Self {
+            lambda_list: Some(lambda_list),
+            ..self
+        }
+    }
+
+    /// Same lambda checking the given declarations before running its body
+    pub fn with_declarations(self, declarations: Vec<TypeDeclaration>) -> Self {
+        Self {
+            declarations: Rc::new(declarations),
+            ..self
+        }
+    }
+
+    pub fn get_declarations(&self) -> &[TypeDeclaration] {
+        &self.declarations
+    }
+
+    pub fn get_lambda_list(&self) -> Option<&Object> {
+        self.lambda_list.as_ref()
+    }
+
+    pub fn get_env(&self) -> Option<RcEnv> {
+        self.env.clone()
+    }
+
     pub fn get_parameters(&self) -> &Params {
         &self.parameters
     }
@@ -138,7 +216,7 @@ impl UserLambda {
 impl Builtin {
     pub fn new<F: BuiltinFunc + 'static>(inner: F) -> Self {
         Self {
-            inner: Arc::new(inner),
+            inner: Rc::new(inner),
         }
     }
 
@@ -152,10 +230,20 @@ impl Builtin {
 }
 
 impl Lambda {
+    /// Whether both are the same function object rather than equivalent ones
+    pub fn is_identical(&self, other: &Lambda) -> bool {
+        match (self, other) {
+            (Self::Unnamed(a) | Self::Named(_, a) | Self::Macro(_, a), Self::Unnamed(b) | Self::Named(_, b) | Self::Macro(_, b)) => Rc::ptr_eq(&a.identity, &b.identity),
+            (Self::Builtin(a), Self::Builtin(b)) => a == b,
+            _ => false,
+        }
+    }
+
     pub fn get_parameters(&self) -> &Params {
         match self {
             Self::Named(_, l) | Self::Unnamed(l) => l.get_parameters(),
             Self::Builtin(l) => l.get_parameters(),
+            Self::Macro(_, l) => l.get_parameters(),
         }
     }
 }
@@ -190,7 +278,14 @@ impl fmt::Display for Parameter {
             Parameter::Normal(s) => write!(f, "({})", s),
             Parameter::Plain(s) => write!(f, "(&plain {})", s),
             Parameter::Rest(s) => write!(f, "(&rest {})", s),
-            Parameter::Optional(name, def) => write!(f, "(&optional {} {})", name, def),
+            Parameter::PlainRest(s) => write!(f, "(&plain-rest {})", s),
+            Parameter::Optional(name, def, _) => write!(f, "(&optional {} {})", name, def),
+            Parameter::Key(name, _, def, _) => write!(f, "(&key {} {})", name, def),
+            Parameter::AllowOtherKeys => write!(f, "(&allow-other-keys)"),
+            Parameter::Aux(name, init) => write!(f, "(&aux {} {})", name, init),
+            Parameter::Nested(p) => write!(f, "{}", p),
+            Parameter::Whole(s) => write!(f, "(&whole {})", s),
+            Parameter::Environment(s) => write!(f, "(&environment {})", s),
         }
     }
 }
@@ -215,8 +310,26 @@ impl fmt::Display for Builtin {
 }
 
 impl PartialEq<Builtin> for Builtin {
-    fn eq(&self, _: &Builtin) -> bool {
-        false
+    fn eq(&self, other: &Builtin) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Debug for UserLambda {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "(USER-LAMBDA {} {})", self.parameters, self.body)
+    }
+}
+
+// Closures are the same only if they capture the same environment
+impl PartialEq<UserLambda> for UserLambda {
+    fn eq(&self, other: &UserLambda) -> bool {
+        let same_env = match (&self.env, &other.env) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        same_env && self.parameters == other.parameters && self.body == other.body && self.declarations == other.declarations
     }
 }
 
@@ -232,6 +345,7 @@ impl fmt::Display for Lambda {
             Self::Unnamed(l) => format!("(LAMBDA {})", l.get_parameters()),
             Self::Named(name, l) => format!("(NAMED-LAMBDA:{} {})", name, l.get_parameters()),
             Self::Builtin(b) => format!("{}", b),
+            Self::Macro(name, _) => return write!(f, "#<MACRO {}>", name),
         };
         write!(f, "#<FUNCTION {}>", str)
     }
@@ -248,16 +362,186 @@ impl From<Vec<Parameter>> for Params {
 impl TryFrom<List> for Params {
     type Error = ParamError;
 
+    /// Parse an ordinary lambda list
     fn try_from(params: List) -> Result<Self, Self::Error> {
+        Self::parse(params.into(), false)
+    }
+}
+
+// Sections of a lambda list, each started by a lambda list keyword
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Required,
+    Optional,
+    Rest,
+    Key,
+    Aux,
+    Whole,
+    Environment,
+    Done,
+}
+
+/// Name, initial form and supplied-p variable of a `(var [init [supplied-p]])` specification
+fn parameter_spec(spec: Object) -> Result<(Object, Object, Option<String>), ParamError> {
+    let (name, rest) = match spec {
+        Object::List(l) => l.unpack(),
+        o => return Ok((o, Object::Nil, None)),
+    };
+    let (init, supplied) = match rest {
+        Object::List(l) => l.unpack(),
+        _ => (Object::Nil, Object::Nil),
+    };
+    let supplied = match supplied {
+        Object::List(l) => match l.car() {
+            Object::Symbol(s) => Some(s),
+            _ => return Err(ParamError::InvalidType),
+        },
+        Object::Nil => None,
+        _ => return Err(ParamError::InvalidType),
+    };
+    Ok((name, init, supplied))
+}
+
+/// Specification `(var [init [supplied-p]])` of a defaulted parameter, or just `var`
+fn defaulted_spec(var: Object, init: &Object, supplied: &Option<String>) -> Object {
+    match (init, supplied) {
+        (Object::Nil, None) => var,
+        (init, None) => cons(var, cons(init.clone(), nil())),
+        (init, Some(s)) => cons(var, cons(init.clone(), cons(Object::Symbol(s.clone()), nil()))),
+    }
+}
+
+impl Params {
+    /// Lambda list these parameters were parsed from
+    pub fn to_lambda_list(&self) -> Object {
+        let mut items = vec![];
+        let mut section = Section::Required;
+        for param in self.inner.iter() {
+            let (keyword, item) = match param {
+                Parameter::Normal(n) | Parameter::Plain(n) => (Section::Required, Object::Symbol(n.clone())),
+                Parameter::Nested(p) => (Section::Required, p.to_lambda_list()),
+                Parameter::Optional(n, init, supplied) => (Section::Optional, defaulted_spec(Object::Symbol(n.clone()), init, supplied)),
+                Parameter::Rest(n) | Parameter::PlainRest(n) => (Section::Rest, Object::Symbol(n.clone())),
+                Parameter::Key(n, k, init, supplied) => {
+                    let var = if *k == format!(":{}", n) {
+                        Object::Symbol(n.clone())
+                    } else {
+                        cons(Object::Symbol(k.clone()), cons(Object::Symbol(n.clone()), nil()))
+                    };
+                    (Section::Key, defaulted_spec(var, init, supplied))
+                },
+                Parameter::AllowOtherKeys => (Section::Done, Object::Symbol("&ALLOW-OTHER-KEYS".into())),
+                Parameter::Aux(n, init) => (Section::Aux, defaulted_spec(Object::Symbol(n.clone()), init, &None)),
+                Parameter::Whole(n) => (Section::Whole, Object::Symbol(n.clone())),
+                Parameter::Environment(n) => (Section::Environment, Object::Symbol(n.clone())),
+            };
+            let marker = match keyword {
+                Section::Optional if section != Section::Optional => Some("&OPTIONAL"),
+                Section::Rest => Some("&REST"),
+                Section::Key if section != Section::Key => Some("&KEY"),
+                Section::Aux if section != Section::Aux => Some("&AUX"),
+                Section::Whole => Some("&WHOLE"),
+                Section::Environment => Some("&ENVIRONMENT"),
+                _ => None,
+            };
+            if let Some(marker) = marker {
+                items.push(Object::Symbol(marker.into()));
+            }
+            if keyword != Section::Environment {
+                section = keyword;
+            }
+            items.push(item);
+        }
+        items.into_iter().rev().fold(nil(), |acc, item| cons(item, acc))
+    }
+
+    /// Parse a macro lambda list, where required parameters can be nested patterns
+    /// and a dotted tail stands for `&rest`
+    pub fn destructuring(list: Object) -> Result<Self, ParamError> {
+        Self::parse(list, true)
+    }
+
+    fn parse(list: Object, destructuring: bool) -> Result<Self, ParamError> {
         let mut p = vec![];
-        for param in params.into_iter() {
-            if let Object::Symbol(s) = param {
-                p.push(Parameter::normal(s));
-            } else {
-                return Err(ParamError::InvalidType);
+        let mut section = Section::Required;
+        let mut resume = Section::Required;
+        let mut rest = list;
+        loop {
+            let (param, next) = match rest {
+                Object::Nil => break,
+                Object::Symbol(s) if destructuring && matches!(section, Section::Required | Section::Optional) => {
+                    p.push(Parameter::rest(s));
+                    break;
+                },
+                Object::List(l) => l.unpack(),
+                _ => return Err(ParamError::InvalidType),
+            };
+            rest = next;
+            if let Object::Symbol(s) = &param {
+                let keyword = match s.as_str() {
+                    "&WHOLE" if destructuring && p.is_empty() && section == Section::Required => Some(Section::Whole),
+                    "&ENVIRONMENT" if destructuring => Some(Section::Environment),
+                    "&OPTIONAL" if section == Section::Required => Some(Section::Optional),
+                    "&REST" | "&BODY" if matches!(section, Section::Required | Section::Optional) => Some(Section::Rest),
+                    "&KEY" if matches!(section, Section::Required | Section::Optional | Section::Done) => Some(Section::Key),
+                    "&ALLOW-OTHER-KEYS" if section == Section::Key => {
+                        p.push(Parameter::AllowOtherKeys);
+                        Some(Section::Done)
+                    },
+                    "&AUX" if section != Section::Aux => Some(Section::Aux),
+                    s if s.starts_with('&') => return Err(ParamError::InvalidType),
+                    _ => None,
+                };
+                if let Some(keyword) = keyword {
+                    if keyword != Section::Environment {
+                        resume = keyword;
+                    }
+                    section = keyword;
+                    continue;
+                }
             }
+            let parameter = match (section, param) {
+                (Section::Required, Object::Symbol(s)) => Parameter::normal(s),
+                (Section::Required, pattern @ (Object::List(_) | Object::Nil)) if destructuring => Parameter::Nested(Self::parse(pattern, true)?),
+                (Section::Optional, spec) => match parameter_spec(spec)? {
+                    (Object::Symbol(s), default, supplied) => Parameter::Optional(s, default, supplied),
+                    _ => return Err(ParamError::InvalidType),
+                },
+                (Section::Rest, Object::Symbol(s)) => {
+                    resume = Section::Done;
+                    Parameter::rest(s)
+                },
+                (Section::Key, spec) => match parameter_spec(spec)? {
+                    (Object::Symbol(s), default, supplied) => Parameter::Key(s.clone(), format!(":{}", s), default, supplied),
+                    (Object::List(l), default, supplied) => match l.unpack() {
+                        (Object::Symbol(k), Object::List(var)) => match var.car() {
+                            Object::Symbol(s) => Parameter::Key(s, k, default, supplied),
+                            _ => return Err(ParamError::InvalidType),
+                        },
+                        _ => return Err(ParamError::InvalidType),
+                    },
+                    _ => return Err(ParamError::InvalidType),
+                },
+                (Section::Aux, spec) => match parameter_spec(spec)? {
+                    (Object::Symbol(s), init, None) => Parameter::Aux(s, init),
+                    _ => return Err(ParamError::InvalidType),
+                },
+                (Section::Whole, Object::Symbol(s)) => {
+                    resume = Section::Required;
+                    Parameter::Whole(s)
+                },
+                (Section::Environment, Object::Symbol(s)) => Parameter::Environment(s),
+                _ => return Err(ParamError::InvalidType),
+            };
+            p.push(parameter);
+            section = resume;
+        }
+        let params = Self::from(p);
+        if params.validate() {
+            Ok(params)
+        } else {
+            Err(ParamError::InvalidType)
         }
-        Ok(Self::from(p))
     }
 }
 
@@ -290,3 +574,49 @@ impl<B: BuiltinFunc + 'static> From<B> for Object {
         Self::from(Builtin::from(b))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Params, Parameter, ParamError};
+    use crate::lexer::Lexer;
+    use crate::parse::parse;
+    use crate::types::{Object, List};
+
+    fn lambda_list(s: &str) -> Object {
+        parse(&mut Lexer::new(s.as_bytes())).unwrap()
+    }
+
+    fn ordinary(s: &str) -> Result<Params, ParamError> {
+        match lambda_list(s) {
+            Object::List(l) => Params::try_from(l.to_list()),
+            _ => Params::try_from(List::EndsWith(Object::Nil)),
+        }
+    }
+
+    #[test]
+    fn test_params_parse() {
+        let params = ordinary("(a &optional (b 1 b-p) &rest r &key c ((:d e) 2) &aux (f 3))").unwrap();
+        assert_eq!(params.len_required(), 1);
+        assert_eq!(params.first(), Some(&Parameter::Normal("A".into())));
+        assert_eq!(params.get(1), Some(&Parameter::Optional("B".into(), Object::Integer(1), Some("B-P".into()))));
+        assert_eq!(params.get(4), Some(&Parameter::Key("E".into(), ":D".into(), Object::Integer(2), None)));
+        // The lambda list is rebuilt section by section
+        assert_eq!(params.to_lambda_list().to_string(), "(A &OPTIONAL (B 1 B-P) &REST R &KEY C ((:D E) 2) &AUX (F 3))");
+        assert_eq!(ordinary("()").map(|p| p.len()), Ok(0));
+    }
+
+    #[test]
+    fn test_params_malformed() {
+        assert_eq!(ordinary("(1 2)"), Err(ParamError::InvalidType));
+        assert_eq!(ordinary("(&foo a)"), Err(ParamError::InvalidType));
+        assert_eq!(ordinary("(a &rest r s)"), Err(ParamError::InvalidType));
+        // Supplied-p variables must be symbols
+        assert_eq!(ordinary("(a &optional (b 1 2))"), Err(ParamError::InvalidType));
+        assert_eq!(ordinary("(&key (a 1 2))"), Err(ParamError::InvalidType));
+        // Only destructuring lambda lists take a dotted tail or nested patterns
+        assert_eq!(ordinary("(a . r)"), Err(ParamError::InvalidType));
+        let params = Params::destructuring(lambda_list("((a b) . r)")).unwrap();
+        assert!(matches!(params.first(), Some(Parameter::Nested(p)) if p.len_required() == 2));
+        assert_eq!(params.get(1), Some(&Parameter::Rest("R".into())));
+    }
+}
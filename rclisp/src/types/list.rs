@@ -1,7 +1,8 @@
 use super::Object;
 
+use std::cell::RefCell;
 use std::fmt;
-use std::borrow::Borrow;
+use std::rc::Rc;
 
 const EMPTY_LIST: List = List::EndsWith(Object::Nil);
 
@@ -59,7 +60,7 @@ impl List {
     pub fn unpack(self) -> (Object, Object) {
         match self {
             Self::Cons(car, cdr) => {
-                let cdr_ret: &List = cdr.borrow();
+                let cdr_ret: &List = &cdr;
                 (car, match cdr_ret {
                     List::EndsWith(Object::Nil) => Object::Nil,
                     List::EndsWith(o) => o.clone(),
@@ -94,7 +95,7 @@ impl Iterator for IterMut {
     type Item = Object;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (ret, inner) = match &self.inner.borrow() {
+        let (ret, inner) = match &*self.inner {
             List::EndsWith(Object::Nil) => (None, Box::new(EMPTY_LIST)),
             List::EndsWith(o) => (Some(o.to_owned()), Box::new(EMPTY_LIST)),
             List::Cons(o, r) => (Some(o.to_owned()), Box::clone(r)),
@@ -113,15 +114,143 @@ impl IntoIterator for List {
     }
 }
 
+impl List {
+    /// Cons cells holding the elements, `(a)` for a lone `EndsWith(a)`
+    fn into_object(self) -> Object {
+        let mut items = vec![];
+        let mut tail = self;
+        let end = loop {
+            match tail {
+                Self::Cons(car, cdr) => {
+                    items.push(car);
+                    tail = *cdr;
+                },
+                Self::EndsWith(end) if items.is_empty() => break cons(end, Object::Nil),
+                Self::EndsWith(end) => break end,
+            }
+        };
+        items.into_iter().rev().fold(end, |acc, item| cons(item, acc))
+    }
+}
+
 impl From<List> for Object {
     fn from(l: List) -> Self {
-        Object::List(Box::new(l))
+        match l {
+            List::EndsWith(Object::Nil) => Object::Nil,
+            l => l.into_object(),
+        }
     }
 }
 
 impl From<&List> for Object {
     fn from(l: &List) -> Self {
-        Object::List(Box::new(l.to_owned()))
+        l.to_owned().into()
+    }
+}
+
+/// A cons cell. Cells are shared by every object referring to them, so that a change to
+/// one is seen through all of them, and they have an identity.
+#[derive(Clone)]
+pub struct Cons {
+    inner: Rc<RefCell<(Object, Object)>>,
+}
+
+/// Elements of a chain of conses, followed by the tail if the chain is dotted
+pub struct ConsIter {
+    next: Object,
+}
+
+impl Cons {
+    pub fn new(car: Object, cdr: Object) -> Self {
+        Self { inner: Rc::new(RefCell::new((car, cdr))) }
+    }
+
+    pub fn car(&self) -> Object {
+        self.inner.borrow().0.clone()
+    }
+
+    pub fn cdr(&self) -> Object {
+        self.inner.borrow().1.clone()
+    }
+
+    pub fn unpack(&self) -> (Object, Object) {
+        self.inner.borrow().clone()
+    }
+
+    pub fn set_car(&self, value: Object) {
+        self.inner.borrow_mut().0 = value;
+    }
+
+    pub fn set_cdr(&self, value: Object) {
+        self.inner.borrow_mut().1 = value;
+    }
+
+    pub fn is_identical(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Number of elements, a dotted tail counting as one
+    pub fn len(&self) -> usize {
+        self.clone().into_iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Copy of the chain as a `List`, the elements themselves being shared
+    pub fn to_list(&self) -> List {
+        let mut items = vec![self.car()];
+        let mut tail = self.cdr();
+        while let Object::List(c) = tail {
+            items.push(c.car());
+            tail = c.cdr();
+        }
+        items.into_iter().rev().fold(List::EndsWith(tail), |acc, item| acc.prepend(item))
+    }
+}
+
+impl IntoIterator for Cons {
+    type Item = Object;
+    type IntoIter = ConsIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        ConsIter { next: Object::List(self) }
+    }
+}
+
+impl Iterator for ConsIter {
+    type Item = Object;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match std::mem::replace(&mut self.next, Object::Nil) {
+            Object::Nil => None,
+            Object::List(c) => {
+                let (car, cdr) = c.unpack();
+                self.next = cdr;
+                Some(car)
+            },
+            end => Some(end),
+        }
+    }
+}
+
+// Conses are equal when they hold equal objects, `eq` is the one comparing identities
+impl PartialEq for Cons {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_identical(other) || self.unpack() == other.unpack()
+    }
+}
+
+impl fmt::Debug for Cons {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self.to_list())
+    }
+}
+
+impl fmt::Display for Cons {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.to_list())
     }
 }
 
@@ -153,10 +282,7 @@ impl std::fmt::Display for List {
 }
 
 pub fn cons(l: Object, r: Object) -> Object {
-    match r {
-        Object::List(list) => list.prepend(l).into(),
-        _ => List::new_pair(l, r).into(),
-    }
+    Object::List(Cons::new(l, r))
 }
 
 #[cfg(test)]
@@ -169,10 +295,10 @@ mod test {
         let list = cons(symbol("A"), nil());
         assert_eq!(
             list,
-            Object::List(Box::new(List::Cons(
+            Object::from(List::Cons(
                 Object::Symbol("A".into()),
                 Box::new(List::EndsWith(Object::Nil))
-            )))
+            ))
         );
     }
 
@@ -181,10 +307,10 @@ mod test {
         let list = cons(symbol("A"), symbol("B"));
         assert_eq!(
             list,
-            Object::List(Box::new(List::Cons(
+            Object::from(List::Cons(
                 Object::Symbol("A".into()),
                 Box::new(List::EndsWith(Object::Symbol("B".into())))
-            )))
+            ))
         );
     }
 
@@ -193,13 +319,13 @@ mod test {
         let list = cons(cons(symbol("A"), symbol("B")), symbol("C"));
         assert_eq!(
             list,
-            Object::List(Box::new(List::Cons(
-                Object::List(Box::new(List::Cons(
+            Object::from(List::Cons(
+                Object::from(List::Cons(
                     Object::Symbol("A".into()),
                     Box::new(List::EndsWith(Object::Symbol("B".into())))
-                ))),
+                )),
                 Box::new(List::EndsWith(Object::Symbol("C".into())))
-            )))
+            ))
         );
     }
 
@@ -1,10 +1,23 @@
 mod lambda;
 mod list;
+mod structure;
+mod instance;
+mod hash_table;
+mod condition;
+mod stream;
+pub mod equality;
+pub mod class;
 
 use std::fmt;
 
-pub use lambda::{Lambda, UserLambda, Builtin, BuiltinFunc, Parameter, Params, ParamError};
-pub use list::{cons, List};
+pub use lambda::{Lambda, UserLambda, TypeDeclaration, Builtin, BuiltinFunc, Parameter, Params, ParamError};
+pub use list::{cons, Cons, List};
+pub use structure::{Structure, StructDef, StructSlot};
+pub use instance::Instance;
+pub use hash_table::HashTable;
+pub use condition::{Conditions, Handler, HandlerAction, Restart};
+pub use stream::{Stream, Sink, Source, Direction};
+pub use class::{Class, Classes, Generic, Method, Qualifier, Specializer, SlotDef};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Object {
@@ -14,9 +27,14 @@ pub enum Object {
     Float(f64),
     String(String),
     Symbol(String),
-    List(Box<List>),
+    List(Cons),
     Lambda(Box<Lambda>),
     Quote(Box<Object>),
+    Package(String),
+    Struct(Structure),
+    Instance(Instance),
+    HashTable(HashTable),
+    Stream(Stream),
 }
 
 impl Object {
@@ -36,11 +54,17 @@ impl fmt::Display for Object {
             Object::T => write!(f, "T"),
             Object::Integer(n) => write!(f, "{}", n),
             Object::Float(n) => write!(f, "{}", n),
-            Object::String(s) => write!(f, "\"{}\"", s),
+            // Escaped so that the string reads back
+            Object::String(s) => write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
             Object::Symbol(atom) => write!(f, "{}", atom),
             Object::List(list) => write!(f, "{}", list),
             Object::Lambda(l) => write!(f, "{}", l),
             Object::Quote(o) => write!(f, "'{}", o),
+            Object::Package(p) => write!(f, "#<PACKAGE \"{}\">", p),
+            Object::Struct(s) => write!(f, "{}", s),
+            Object::Instance(i) => write!(f, "{}", i),
+            Object::HashTable(h) => write!(f, "{}", h),
+            Object::Stream(s) => write!(f, "{}", s),
         }
     }
 }
@@ -50,8 +74,6 @@ impl From<Vec<Object>> for Object {
         let mut v = v;
         if v.is_empty() {
             nil()
-        } else if v.len() == 1 && matches!(v[0], Object::List(_)) {
-            v[0].to_owned()
         } else {
             let mut ret = cons(v.pop().unwrap(), nil());
             for t in v.into_iter().rev() {
@@ -85,7 +107,7 @@ pub fn int(int: isize) -> Object {
 #[inline]
 pub fn get_list(obj: Object) -> List {
     if let Object::List(l) = obj {
-        *l
+        l.to_list()
     } else {
         unreachable!()
     }
@@ -0,0 +1,325 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::Object;
+
+/// Destination of the characters written to an output stream, supplied by the host
+pub trait Sink {
+    fn write_str(&mut self, text: &str) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Origin of the characters read from an input stream, supplied by the host
+pub trait Source {
+    /// Next piece of text, usually a line with its newline, or `None` at the end
+    fn read_str(&mut self) -> io::Result<Option<String>>;
+}
+
+impl<W: Write> Sink for W {
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.write_all(text.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+}
+
+impl<R: BufRead> Source for R {
+    fn read_str(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        match self.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+struct StreamData {
+    direction: Direction,
+    // Output streams without a sink collect what is written to them, input streams
+    // without a source only read what is already buffered
+    sink: Option<Box<dyn Sink>>,
+    source: Option<Box<dyn Source>>,
+    buffer: Vec<char>,
+    pos: usize,
+    column: usize,
+    open: bool,
+    pathname: Option<String>, // File the stream was opened on
+}
+
+impl StreamData {
+    fn new(direction: Direction) -> Self {
+        Self { direction, sink: None, source: None, buffer: vec![], pos: 0, column: 0, open: true, pathname: None }
+    }
+
+    fn check(&self, direction: Direction) -> io::Result<()> {
+        if !self.open {
+            Err(io::Error::other("stream is closed"))
+        } else if self.direction != direction {
+            Err(io::Error::other(match direction {
+                Direction::Input => "not an input stream",
+                Direction::Output => "not an output stream",
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Make sure there is a character to read, unless the source is exhausted
+    fn fill(&mut self) -> io::Result<bool> {
+        self.check(Direction::Input)?;
+        while self.pos >= self.buffer.len() {
+            let Some(source) = self.source.as_mut() else {
+                return Ok(false);
+            };
+            match source.read_str()? {
+                Some(text) => {
+                    self.buffer = text.chars().collect();
+                    self.pos = 0;
+                },
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+// Streams are shared like hash tables, they are only equal to themselves
+#[derive(Clone)]
+pub struct Stream {
+    inner: Rc<RefCell<StreamData>>,
+}
+
+impl Stream {
+    fn from_data(data: StreamData) -> Self {
+        Self { inner: Rc::new(RefCell::new(data)) }
+    }
+
+    pub fn output(sink: Box<dyn Sink>) -> Self {
+        Self::from_data(StreamData { sink: Some(sink), ..StreamData::new(Direction::Output) })
+    }
+
+    pub fn input(source: Box<dyn Source>) -> Self {
+        Self::from_data(StreamData { source: Some(source), ..StreamData::new(Direction::Input) })
+    }
+
+    pub fn string_output() -> Self {
+        Self::from_data(StreamData::new(Direction::Output))
+    }
+
+    pub fn string_input<S: AsRef<str>>(text: S) -> Self {
+        Self::from_data(StreamData { buffer: text.as_ref().chars().collect(), ..StreamData::new(Direction::Input) })
+    }
+
+    /// Record the file the stream reads from or writes to
+    pub fn with_pathname<S: Into<String>>(self, pathname: S) -> Self {
+        self.inner.borrow_mut().pathname = Some(pathname.into());
+        self
+    }
+
+    pub fn get_pathname(&self) -> Option<String> {
+        self.inner.borrow().pathname.clone()
+    }
+
+    pub fn get_direction(&self) -> Direction {
+        self.inner.borrow().direction
+    }
+
+    /// Whether the stream reads from or writes to a string
+    pub fn is_string_stream(&self) -> bool {
+        let inner = self.inner.borrow();
+        inner.sink.is_none() && inner.source.is_none()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.inner.borrow().open
+    }
+
+    pub fn close(&self) -> io::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(sink) = inner.sink.as_mut() {
+            sink.flush()?;
+        }
+        inner.open = false;
+        Ok(())
+    }
+
+    pub fn write_str(&self, text: &str) -> io::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        inner.check(Direction::Output)?;
+        match text.rfind('\n') {
+            Some(i) => inner.column = text[i + 1..].chars().count(),
+            None => inner.column += text.chars().count(),
+        }
+        match inner.sink.as_mut() {
+            Some(sink) => sink.write_str(text),
+            None => {
+                inner.buffer.extend(text.chars());
+                Ok(())
+            },
+        }
+    }
+
+    /// Column the next character will be written at
+    pub fn get_column(&self) -> usize {
+        self.inner.borrow().column
+    }
+
+    /// Start a new line unless at the start of one already, returns whether it did
+    pub fn fresh_line(&self) -> io::Result<bool> {
+        if self.get_column() == 0 {
+            return Ok(false);
+        }
+        self.write_str("\n")?;
+        Ok(true)
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        inner.check(Direction::Output)?;
+        match inner.sink.as_mut() {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Everything written to a string output stream since the last call
+    pub fn take_string(&self) -> String {
+        let mut inner = self.inner.borrow_mut();
+        inner.column = 0;
+        std::mem::take(&mut inner.buffer).into_iter().collect()
+    }
+
+    pub fn read_char(&self) -> io::Result<Option<char>> {
+        let mut inner = self.inner.borrow_mut();
+        if !inner.fill()? {
+            return Ok(None);
+        }
+        inner.pos += 1;
+        Ok(Some(inner.buffer[inner.pos - 1]))
+    }
+
+    pub fn peek_char(&self) -> io::Result<Option<char>> {
+        let mut inner = self.inner.borrow_mut();
+        if !inner.fill()? {
+            return Ok(None);
+        }
+        Ok(Some(inner.buffer[inner.pos]))
+    }
+
+    /// Next line without its newline, and whether the end was reached before a newline
+    pub fn read_line(&self) -> io::Result<Option<(String, bool)>> {
+        let mut inner = self.inner.borrow_mut();
+        if !inner.fill()? {
+            return Ok(None);
+        }
+        let mut line = String::new();
+        while inner.fill()? {
+            let c = inner.buffer[inner.pos];
+            inner.pos += 1;
+            if c == '\n' {
+                return Ok(Some((line, false)));
+            }
+            line.push(c);
+        }
+        Ok(Some((line, true)))
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self)
+    }
+}
+
+impl PartialEq for Stream {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let kind = if self.is_string_stream() {
+            "STRING-STREAM"
+        } else if self.get_pathname().is_some() {
+            "FILE-STREAM"
+        } else {
+            "STREAM"
+        };
+        let direction = match self.get_direction() {
+            Direction::Input => "INPUT",
+            Direction::Output => "OUTPUT",
+        };
+        write!(f, "#<{} {} {{{:x}}}>", direction, kind, Rc::as_ptr(&self.inner) as *const () as usize)
+    }
+}
+
+impl From<Stream> for Object {
+    fn from(s: Stream) -> Self {
+        Self::Stream(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Stream, Source, Direction};
+    use std::io;
+
+    // Source handing out its pieces one call at a time
+    struct Pieces(Vec<&'static str>);
+
+    impl Source for Pieces {
+        fn read_str(&mut self) -> io::Result<Option<String>> {
+            Ok(if self.0.is_empty() { None } else { Some(self.0.remove(0).into()) })
+        }
+    }
+
+    #[test]
+    fn test_stream_input() {
+        let stream = Stream::input(Box::new(Pieces(vec!["ab\n", "", "c"])));
+        assert_eq!(stream.peek_char().unwrap(), Some('a'));
+        assert_eq!(stream.read_char().unwrap(), Some('a'));
+        assert_eq!(stream.read_line().unwrap(), Some(("b".into(), false)));
+        // Empty pieces are skipped, the last line has no newline
+        assert_eq!(stream.read_line().unwrap(), Some(("c".into(), true)));
+        assert_eq!(stream.read_line().unwrap(), None);
+        assert_eq!(stream.read_char().unwrap(), None);
+        let stream = Stream::string_input("x");
+        assert!(stream.is_string_stream());
+        assert_eq!(stream.read_char().unwrap(), Some('x'));
+        assert_eq!(stream.peek_char().unwrap(), None);
+    }
+
+    #[test]
+    fn test_stream_output() {
+        let stream = Stream::string_output();
+        assert_eq!(stream.get_direction(), Direction::Output);
+        assert!(!stream.fresh_line().unwrap());
+        stream.write_str("ab\ncd").unwrap();
+        assert_eq!(stream.get_column(), 2);
+        assert!(stream.fresh_line().unwrap());
+        assert_eq!(stream.take_string(), "ab\ncd\n");
+        assert_eq!(stream.take_string(), "");
+        // Streams only go one way, and not at all once closed
+        assert!(stream.read_char().is_err());
+        assert!(Stream::string_input("a").write_str("b").is_err());
+        stream.close().unwrap();
+        assert!(!stream.is_open());
+        assert!(stream.write_str("a").is_err());
+        assert_eq!(stream, stream.clone());
+        assert_ne!(stream, Stream::string_output());
+    }
+}
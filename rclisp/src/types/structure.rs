@@ -0,0 +1,117 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::Object;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructSlot {
+    pub name: String, // Symbol name without package prefix
+    pub default: Object,
+    pub read_only: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructDef {
+    pub name: String,
+    pub include: Option<String>,
+    pub slots: Vec<StructSlot>,
+}
+
+#[derive(Debug)]
+struct StructInstance {
+    name: String,
+    slots: Vec<(String, Object)>,
+}
+
+// Instances are shared so that accessors can modify them in place
+#[derive(Clone, Debug)]
+pub struct Structure {
+    inner: Rc<RefCell<StructInstance>>,
+}
+
+impl StructDef {
+    pub fn find_slot<S: AsRef<str>>(&self, name: S) -> Option<&StructSlot> {
+        self.slots.iter().find(|s| s.name == name.as_ref())
+    }
+}
+
+impl Structure {
+    pub fn new<S: AsRef<str>>(name: S, slots: Vec<(String, Object)>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(StructInstance {
+                name: name.as_ref().to_string(),
+                slots,
+            })),
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        self.inner.borrow().name.clone()
+    }
+
+    pub fn get_slot<S: AsRef<str>>(&self, name: S) -> Option<Object> {
+        self.inner.borrow().slots.iter()
+            .find(|(n, _)| n == name.as_ref())
+            .map(|(_, v)| v.clone())
+    }
+
+    pub fn set_slot<S: AsRef<str>>(&self, name: S, value: Object) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        if let Some((_, v)) = inner.slots.iter_mut().find(|(n, _)| n == name.as_ref()) {
+            *v = value;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn get_slots(&self) -> Vec<(String, Object)> {
+        self.inner.borrow().slots.clone()
+    }
+
+    /// Shallow copy, slot values are shared with the original
+    pub fn copy(&self) -> Self {
+        Self::new(self.get_name(), self.get_slots())
+    }
+}
+
+impl PartialEq for Structure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let inner = self.inner.borrow();
+        write!(f, "#S({}", inner.name)?;
+        for (name, value) in inner.slots.iter() {
+            write!(f, " :{} {}", name, value)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl From<Structure> for Object {
+    fn from(s: Structure) -> Self {
+        Self::Struct(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Structure, Object};
+
+    #[test]
+    fn test_structure_shared() {
+        let s = Structure::new("POINT", vec![("X".into(), Object::Integer(1)), ("Y".into(), Object::Nil)]);
+        let t = s.clone();
+        assert!(t.set_slot("Y", Object::Integer(2)));
+        assert!(!t.set_slot("Z", Object::Integer(3)));
+        assert_eq!(s.get_slot("Y"), Some(Object::Integer(2)));
+        assert_eq!(s.to_string(), "#S(POINT :X 1 :Y 2)");
+        assert_ne!(s, s.copy());
+        assert_eq!(s, t);
+    }
+}